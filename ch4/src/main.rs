@@ -19,7 +19,7 @@ use impls::Console;
 use kernel_context::{foreign::MultislotPortal, LocalContext};
 use kernel_vm::{
     page_table::{MmuMeta, Sv39, VAddr, VmFlags, VmMeta, PPN, VPN},
-    AddressSpace,
+    AddressSpace, AreaKind,
 };
 use rcore_console::log;
 use riscv::register::*;
@@ -68,7 +68,7 @@ extern "C" fn rust_main() -> ! {
         log::info!("detect app[{i}]: {base:#x}..{:#x}", base + elf.len());
         if let Some(process) = Process::new(ElfFile::new(elf).unwrap()) {
             // 映射异界传送门
-            process.address_space.root()[portal_idx] = ks.root()[portal_idx];
+            process.address_space.root()[portal_idx] = Sv39Manager::borrowed(ks.root()[portal_idx]);
             unsafe { PROCESSES.push(process) };
         }
     }
@@ -82,6 +82,7 @@ extern "C" fn rust_main() -> ! {
         VPN::new((1 << 26) - pages)..VPN::new(1 << 26),
         PPN::new(stack as usize >> Sv39::PAGE_BITS),
         VmFlags::build_from_str("_WRV"),
+        AreaKind::Stack,
     );
     // 建立调度线程，目的是划分异常域。调度线程上发生内核异常时会回到这个控制流处理
     let mut scheduling = LocalContext::thread(schedule as _, false);
@@ -167,6 +168,7 @@ fn kernel_space(
             s.floor()..e.ceil(),
             PPN::new(s.floor().val()),
             VmFlags::build_from_str(flags),
+            AreaKind::Kernel,
         )
     }
     log::info!(
@@ -180,11 +182,13 @@ fn kernel_space(
         s.floor()..e.ceil(),
         PPN::new(s.floor().val()),
         VmFlags::build_from_str("_WRV"),
+        AreaKind::Heap,
     );
     space.map_extern(
         PROTAL_TRANSIT..PROTAL_TRANSIT + 1,
         PPN::new(portal >> Sv39::PAGE_BITS),
         VmFlags::build_from_str("__G_XWRV"),
+        AreaKind::Kernel,
     );
     println!();
     unsafe { satp::set(satp::Mode::Sv39, 0, space.root_ppn().val()) };
@@ -194,18 +198,25 @@ fn kernel_space(
 /// 各种接口库的实现。
 mod impls {
     use crate::PROCESSES;
-    use alloc::alloc::alloc_zeroed;
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        collections::BTreeMap,
+    };
     use core::{alloc::Layout, ptr::NonNull};
     use kernel_vm::{
         page_table::{MmuMeta, Pte, Sv39, VAddr, VmFlags, PPN, VPN},
         PageManager,
     };
     use rcore_console::log;
+    use spin::Mutex;
     use syscall::*;
 
     #[repr(transparent)]
     pub struct Sv39Manager(NonNull<Pte<Sv39>>);
 
+    /// 被多个地址空间共享的物理页的引用计数，只属于一个地址空间的页不在表中。
+    static FRAME_REFS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
     impl Sv39Manager {
         const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
 
@@ -219,9 +230,32 @@ mod impls {
             }
             .cast()
         }
+
+        #[inline]
+        fn page_dealloc<T>(ptr: *mut T) {
+            unsafe {
+                dealloc(
+                    ptr.cast(),
+                    Layout::from_size_align_unchecked(1 << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
+
+        /// 去掉页表项的所有权标记，地址空间回收时不会释放它指向的页。
+        pub fn borrowed(pte: Pte<Sv39>) -> Pte<Sv39> {
+            let flags = pte.flags();
+            if flags.contains(Self::OWNED) {
+                (flags ^ Self::OWNED).build_pte(pte.ppn())
+            } else {
+                pte
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
+        const WRITABLE: VmFlags<Sv39> = VmFlags::build_from_str("W");
+        const COW: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 9) };
+
         #[inline]
         fn new_root() -> Self {
             Self(NonNull::new(Self::page_alloc(1)).unwrap())
@@ -258,12 +292,37 @@ mod impls {
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
 
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        #[inline]
+        fn share(&mut self, ppn: PPN<Sv39>) {
+            *FRAME_REFS.lock().entry(ppn.val()).or_insert(1) += 1;
+        }
+
+        #[inline]
+        fn ref_count(&self, ppn: PPN<Sv39>) -> usize {
+            FRAME_REFS.lock().get(&ppn.val()).copied().unwrap_or(1)
         }
 
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            let mut refs = FRAME_REFS.lock();
+            let mut freed = 0;
+            for ppn in (pte.ppn().val()..).take(len) {
+                match refs.get_mut(&ppn) {
+                    Some(count) if *count > 2 => *count -= 1,
+                    Some(_) => {
+                        refs.remove(&ppn);
+                    }
+                    None => {
+                        Self::page_dealloc(self.p_to_v::<u8>(PPN::new(ppn)).as_ptr());
+                        freed += 1;
+                    }
+                }
+            }
+            freed
+        }
+
+        #[inline]
         fn drop_root(&mut self) {
-            todo!()
+            Self::page_dealloc(self.0.as_ptr());
         }
     }
 
@@ -329,7 +388,7 @@ mod impls {
             const WRITABLE: VmFlags<Sv39> = VmFlags::build_from_str("W_V");
             match clock_id {
                 ClockId::CLOCK_MONOTONIC => {
-                    if let Some(mut ptr) = unsafe { PROCESSES.get(caller.entity) }
+                    if let Some(mut ptr) = unsafe { PROCESSES.get_mut(caller.entity) }
                         .unwrap()
                         .address_space
                         .translate(VAddr::new(tp), WRITABLE)
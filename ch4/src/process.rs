@@ -1,10 +1,9 @@
 ﻿use crate::Sv39Manager;
-use alloc::alloc::alloc_zeroed;
-use core::{alloc::Layout, str::FromStr};
+use core::str::FromStr;
 use kernel_context::{foreign::ForeignContext, LocalContext};
 use kernel_vm::{
-    page_table::{MmuMeta, Sv39, VAddr, VmFlags, PPN, VPN},
-    AddressSpace,
+    page_table::{MmuMeta, Sv39, VAddr, VmFlags, VPN},
+    AddressSpace, AreaKind,
 };
 use rcore_console::log;
 use xmas_elf::{
@@ -60,18 +59,15 @@ impl Process {
                 &elf.input[off_file..][..len_file],
                 off_mem & PAGE_MASK,
                 VmFlags::from_str(unsafe { core::str::from_utf8_unchecked(&flags) }).unwrap(),
+                AreaKind::Elf,
             );
         }
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
+        address_space.map(
             VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
+            &[],
+            0,
             VmFlags::build_from_str("U_WRV"),
+            AreaKind::Stack,
         );
 
         log::info!("process entry = {:#x}", entry);
@@ -0,0 +1,71 @@
+use alloc::sync::Arc;
+use devfs::{DevFs, Device, Random};
+use rcore_vfs::{FSManager, FdTable, FileHandle, FileSystem, MountTable};
+use spin::Lazy;
+use tmpfs::TmpFs;
+
+/// 时钟频率
+const CLOCK_FREQ: u64 = 12_500_000;
+
+/// 没有实时时钟，用开机以来的秒数记录文件时间
+fn now() -> u64 {
+    riscv::register::time::read() as u64 / CLOCK_FREQ
+}
+
+/// 控制台
+struct ConsoleDevice;
+
+impl Device for ConsoleDevice {
+    /// 逐字节读取键盘输入，直到填满 `buf`
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+        for byte in buf.iter_mut() {
+            #[allow(deprecated)]
+            {
+                *byte = sbi_rt::legacy::console_getchar() as u8;
+            }
+        }
+        buf.len()
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        print!("{}", unsafe { core::str::from_utf8_unchecked(buf) });
+        buf.len()
+    }
+
+    fn mode(&self) -> u32 {
+        0o620
+    }
+}
+
+/// 设备文件系统，挂载到 `/dev`
+static DEV: Lazy<Arc<DevFs>> = Lazy::new(|| {
+    let dev = DevFs::new();
+    dev.add("console", Arc::new(ConsoleDevice));
+    let seed = riscv::register::time::read() as u64;
+    dev.add("random", Arc::new(Random::new(seed)));
+    Arc::new(dev)
+});
+
+/// 挂载表，这一章没有块设备，根文件系统是一个 tmpfs，`/dev` 挂载设备文件系统
+///
+/// 应用程序仍然内联在内核中，根目录开始时是空的，写入的文件关机后全部丢失。
+pub static FS: Lazy<MountTable> = Lazy::new(|| {
+    let fs = MountTable::new(Arc::new(TmpFs::new(now)));
+    fs.register("tmpfs", |_| Some(Arc::new(TmpFs::new(now))));
+    fs.register("devfs", |_| Some(DEV.clone()));
+    fs.mkdir("tmp");
+    fs.mount("tmpfs", "tmp", "tmpfs");
+    fs.mkdir("dev");
+    fs.mount("devfs", "dev", "devfs");
+    fs
+});
+
+/// 初始进程的描述符表，0、1、2 分别是指向控制台的标准输入、标准输出和标准错误
+pub fn stdio_fd_table() -> FdTable {
+    let console = DEV.root_inode().find("console").unwrap();
+    let mut fd_table = FdTable::new();
+    fd_table.insert(FileHandle::new(true, false, console.clone()), false);
+    fd_table.insert(FileHandle::new(false, true, console.clone()), false);
+    fd_table.insert(FileHandle::new(false, true, console), false);
+    fd_table
+}
@@ -2,6 +2,7 @@
 #![no_main]
 // #![deny(warnings)]
 
+mod fs;
 mod process;
 mod processor;
 
@@ -16,7 +17,7 @@ use impls::{Console, Sv39Manager, SyscallContext};
 use kernel_context::foreign::MultislotPortal;
 use kernel_vm::{
     page_table::{MmuMeta, Sv39, VAddr, VmFlags, VmMeta, PPN, VPN},
-    AddressSpace,
+    AddressSpace, AreaKind,
 };
 use process::Process;
 use processor::{ProcManager, PROCESSOR};
@@ -85,6 +86,7 @@ extern "C" fn rust_main() -> ! {
     syscall::init_process(&SyscallContext);
     syscall::init_scheduling(&SyscallContext);
     syscall::init_clock(&SyscallContext);
+    syscall::init_memory(&SyscallContext);
     // 加载初始进程
     let initproc_data = APPS.get("initproc").unwrap();
     if let Some(process) = Process::from_elf(ElfFile::new(initproc_data).unwrap()) {
@@ -118,8 +120,23 @@ extern "C" fn rust_main() -> ! {
                         }
                     }
                 }
+                scause::Trap::Exception(
+                    e @ (scause::Exception::LoadPageFault
+                    | scause::Exception::StorePageFault
+                    | scause::Exception::InstructionPageFault),
+                ) if task
+                    .address_space
+                    .handle_page_fault(VAddr::new(stval::read()), page_fault_flags(e)) =>
+                {
+                    // 按需分配了页或分开了写时复制的页，回到用户态重新执行这条指令
+                    unsafe { PROCESSOR.make_current_suspend() };
+                }
                 e => {
-                    log::error!("unsupported trap: {e:?}");
+                    log::error!("unsupported trap: {e:?}, stval = {:#x}", stval::read());
+                    // 打印进程的内存布局，便于找出非法访问的原因
+                    for area in task.address_space.areas() {
+                        log::debug!("{area}");
+                    }
                     unsafe { PROCESSOR.make_current_exited(-3) };
                 }
             }
@@ -156,6 +173,7 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
             s.floor()..e.ceil(),
             PPN::new(s.floor().val()),
             VmFlags::build_from_str(flags),
+            AreaKind::Kernel,
         )
     }
     let s = VAddr::<Sv39>::new(layout.end());
@@ -165,11 +183,13 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
         s.floor()..e.ceil(),
         PPN::new(s.floor().val()),
         VmFlags::build_from_str("_WRV"),
+        AreaKind::Heap,
     );
     space.map_extern(
         PROTAL_TRANSIT..PROTAL_TRANSIT + 1,
         PPN::new(portal >> Sv39::PAGE_BITS),
         VmFlags::build_from_str("__G_XWRV"),
+        AreaKind::Kernel,
     );
     println!();
     unsafe { satp::set(satp::Mode::Sv39, 0, space.root_ppn().val()) };
@@ -177,28 +197,50 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
 }
 
 /// 映射异界传送门。
+///
+/// 传送门所在的页表属于内核地址空间，用户地址空间只是借用。
 fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
     let portal_idx = PROTAL_TRANSIT.index_in(Sv39::MAX_LEVEL);
-    space.root()[portal_idx] = unsafe { KERNEL_SPACE.assume_init_ref() }.root()[portal_idx];
+    space.root()[portal_idx] =
+        Sv39Manager::borrowed(unsafe { KERNEL_SPACE.assume_init_ref() }.root()[portal_idx]);
+}
+
+/// 用户态页异常的访问类型要求的页属性。
+fn page_fault_flags(e: scause::Exception) -> VmFlags<Sv39> {
+    match e {
+        scause::Exception::StorePageFault => VmFlags::build_from_str("U_W_V"),
+        scause::Exception::InstructionPageFault => VmFlags::build_from_str("UX__V"),
+        _ => VmFlags::build_from_str("U__RV"),
+    }
 }
 
 /// 各种接口库的实现。
 mod impls {
-    use crate::{APPS, PROCESSOR};
-    use alloc::alloc::alloc_zeroed;
-    use core::{alloc::Layout, ptr::NonNull};
+    use crate::{fs::FS, APPS, PROCESSOR};
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        collections::BTreeMap,
+        string::String,
+        vec::Vec,
+    };
+    use core::{alloc::Layout, ops::Range, ptr::NonNull};
     use kernel_vm::{
         page_table::{MmuMeta, Pte, Sv39, VAddr, VmFlags, PPN, VPN},
-        PageManager,
+        AddressSpace, AreaKind, PageManager,
     };
     use rcore_console::log;
     use rcore_task_manage::ProcId;
+    use rcore_vfs::{FSManager, FileType, OpenFlags, SeekFrom, UserBuffer};
+    use spin::Mutex;
     use syscall::*;
     use xmas_elf::ElfFile;
 
     #[repr(transparent)]
     pub struct Sv39Manager(NonNull<Pte<Sv39>>);
 
+    /// 被多个地址空间共享的物理页的引用计数，只属于一个地址空间的页不在表中。
+    static FRAME_REFS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
     impl Sv39Manager {
         const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
 
@@ -212,9 +254,32 @@ mod impls {
             }
             .cast()
         }
+
+        #[inline]
+        fn page_dealloc<T>(ptr: *mut T) {
+            unsafe {
+                dealloc(
+                    ptr.cast(),
+                    Layout::from_size_align_unchecked(1 << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
+
+        /// 去掉页表项的所有权标记，地址空间回收时不会释放它指向的页。
+        pub fn borrowed(pte: Pte<Sv39>) -> Pte<Sv39> {
+            let flags = pte.flags();
+            if flags.contains(Self::OWNED) {
+                (flags ^ Self::OWNED).build_pte(pte.ppn())
+            } else {
+                pte
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
+        const WRITABLE: VmFlags<Sv39> = VmFlags::build_from_str("W");
+        const COW: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 9) };
+
         #[inline]
         fn new_root() -> Self {
             Self(NonNull::new(Self::page_alloc(1)).unwrap())
@@ -251,12 +316,37 @@ mod impls {
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
 
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        #[inline]
+        fn share(&mut self, ppn: PPN<Sv39>) {
+            *FRAME_REFS.lock().entry(ppn.val()).or_insert(1) += 1;
+        }
+
+        #[inline]
+        fn ref_count(&self, ppn: PPN<Sv39>) -> usize {
+            FRAME_REFS.lock().get(&ppn.val()).copied().unwrap_or(1)
+        }
+
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            let mut refs = FRAME_REFS.lock();
+            let mut freed = 0;
+            for ppn in (pte.ppn().val()..).take(len) {
+                match refs.get_mut(&ppn) {
+                    Some(count) if *count > 2 => *count -= 1,
+                    Some(_) => {
+                        refs.remove(&ppn);
+                    }
+                    None => {
+                        Self::page_dealloc(self.p_to_v::<u8>(PPN::new(ppn)).as_ptr());
+                        freed += 1;
+                    }
+                }
+            }
+            freed
         }
 
+        #[inline]
         fn drop_root(&mut self) {
-            todo!()
+            Self::page_dealloc(self.0.as_ptr());
         }
     }
 
@@ -271,54 +361,214 @@ mod impls {
     }
 
     pub struct SyscallContext;
+    const READABLE: VmFlags<Sv39> = VmFlags::build_from_str("RV");
+    const WRITEABLE: VmFlags<Sv39> = VmFlags::build_from_str("W_V");
+
+    /// 从用户地址空间读取以 `\0` 结尾的字符串。
+    fn read_str(address_space: &mut AddressSpace<Sv39, Sv39Manager>, ptr: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut addr = ptr;
+        loop {
+            let ch = unsafe {
+                *address_space
+                    .translate::<u8>(VAddr::new(addr), READABLE)?
+                    .as_ptr()
+            };
+            if ch == 0 {
+                break Some(string);
+            }
+            string.push(ch as char);
+            addr += 1;
+        }
+    }
+
+    /// 把用户地址空间中从 `ptr` 开始的 `len` 个字节逐页翻译成内核可以访问的若干段。
+    fn user_buffer(
+        address_space: &mut AddressSpace<Sv39, Sv39Manager>,
+        ptr: usize,
+        len: usize,
+        flags: VmFlags<Sv39>,
+    ) -> Option<Vec<&'static mut [u8]>> {
+        address_space
+            .translate_buffer(VAddr::new(ptr), len, flags)
+            .map(|segments| {
+                segments
+                    .into_iter()
+                    .map(|segment| unsafe { &mut *segment.as_ptr() })
+                    .collect()
+            })
+    }
 
     impl IO for SyscallContext {
         fn write(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
-            match fd {
-                STDOUT | STDDEBUG => {
-                    const READABLE: VmFlags<Sv39> = VmFlags::build_from_str("RV");
-                    if let Some(ptr) = unsafe { PROCESSOR.current() }
-                        .unwrap()
-                        .address_space
-                        .translate(VAddr::new(buf), READABLE)
-                    {
-                        print!("{}", unsafe {
-                            core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                                ptr.as_ptr(),
-                                count,
-                            ))
-                        });
-                        count as _
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, READABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
+                    let mut file = file.lock();
+                    if file.writable() {
+                        file.write(UserBuffer::new(v)) as _
                     } else {
-                        log::error!("ptr not readable");
+                        log::error!("file not writable");
                         -1
                     }
-                }
-                _ => {
+                } else {
                     log::error!("unsupported fd: {fd}");
                     -1
                 }
+            } else {
+                log::error!("ptr not readable");
+                -1
             }
         }
 
-        #[inline]
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
-            if fd == STDIN {
-                const WRITEABLE: VmFlags<Sv39> = VmFlags::build_from_str("W_V");
-                if let Some(mut ptr) = unsafe { PROCESSOR.current().unwrap() }
-                    .address_space
-                    .translate(VAddr::new(buf), WRITEABLE)
-                {
-                    let mut ptr = unsafe { ptr.as_mut() } as *mut u8;
-                    for _ in 0..count {
-                        #[allow(deprecated)]
-                        let c = sbi_rt::legacy::console_getchar() as u8;
-                        unsafe {
-                            *ptr = c;
-                            ptr = ptr.add(1);
-                        }
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, WRITEABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
+                    let mut file = file.lock();
+                    if file.readable() {
+                        file.read(UserBuffer::new(v)) as _
+                    } else {
+                        log::error!("file not readable");
+                        -1
                     }
-                    count as _
+                } else {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
+            } else {
+                log::error!("ptr not writeable");
+                -1
+            }
+        }
+
+        fn open(&self, _caller: Caller, path: usize, flags: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                let flags = OpenFlags::from_bits_truncate(flags as u32);
+                if let Some(fd) = FS.open(path.as_str(), flags) {
+                    let cloexec = flags.contains(OpenFlags::CLOEXEC);
+                    current
+                        .fd_table
+                        .insert(fd.as_ref().clone(), cloexec)
+                        .map_or(-1, |fd| fd as isize)
+                } else {
+                    -1
+                }
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn dup(&self, _caller: Caller, fd: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            // 使用最小的空闲描述符，关闭标准输入输出后 dup 就能替换它们
+            current.fd_table.dup(fd).map_or(-1, |fd| fd as isize)
+        }
+
+        #[inline]
+        fn close(&self, _caller: Caller, fd: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if current.fd_table.close(fd) {
+                0
+            } else {
+                -1
+            }
+        }
+
+        fn lseek(&self, _caller: Caller, fd: usize, offset: isize, whence: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let pos = match whence {
+                SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+                SEEK_CUR => SeekFrom::Current(offset),
+                SEEK_END => SeekFrom::End(offset),
+                _ => return -1,
+            };
+            match current.fd_table.get(fd) {
+                Some(file) => file.lock().seek(pos),
+                None => {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
+            }
+        }
+
+        fn mkdir(&self, _caller: Caller, path: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                FS.mkdir(path.as_str())
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn link(&self, _caller: Caller, src: usize, dst: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, src),
+                read_str(&mut current.address_space, dst),
+            ) {
+                (Some(src), Some(dst)) => FS.link(src.as_str(), dst.as_str()),
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn unlink(&self, _caller: Caller, path: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                FS.unlink(path.as_str())
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn rename(&self, _caller: Caller, oldpath: usize, newpath: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, oldpath),
+                read_str(&mut current.address_space, newpath),
+            ) {
+                (Some(oldpath), Some(newpath)) => FS.rename(oldpath.as_str(), newpath.as_str()),
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn fstat(&self, _caller: Caller, fd: usize, st: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let inode = match current.fd_table.get(fd) {
+                Some(file) => file.lock().inode.clone(),
+                _ => None,
+            };
+            if let Some(inode) = inode {
+                if let Some(mut ptr) = current.address_space.translate(VAddr::new(st), WRITEABLE) {
+                    let stat = inode.metadata();
+                    *unsafe { ptr.as_mut() } = Stat {
+                        dev: 0,
+                        ino: stat.ino,
+                        mode: match stat.type_ {
+                            FileType::File => StatMode::FILE,
+                            FileType::Directory => StatMode::DIR,
+                            FileType::SymLink => StatMode::LINK,
+                            FileType::CharDevice => StatMode::CHAR,
+                            FileType::BlockDevice => StatMode::BLOCK,
+                        },
+                        nlink: stat.nlink,
+                        size: stat.size,
+                        perm: stat.mode,
+                        atime: stat.atime,
+                        mtime: stat.mtime,
+                        crtime: stat.crtime,
+                    };
+                    0
                 } else {
                     log::error!("ptr not writeable");
                     -1
@@ -366,10 +616,7 @@ mod impls {
                         println!();
                         -1
                     },
-                    |data| {
-                        current.exec(data);
-                        0
-                    },
+                    |elf| if current.exec(elf) { 0 } else { -1 },
                 )
         }
 
@@ -430,4 +677,136 @@ mod impls {
             }
         }
     }
+
+    /// 内核选择映射地址时，从这里向上寻找空闲的虚拟地址块
+    const MMAP_BASE: usize = 1 << 36;
+    /// 用户地址空间的上界
+    const USER_TOP: usize = 1 << 38;
+
+    /// 把 `mmap` 和 `mprotect` 的保护属性转换为用户页的属性。
+    ///
+    /// 不可访问的页和只写的页都不能表示为有效的叶子页表项，返回 `None`。
+    fn prot_flags(prot: i32) -> Option<VmFlags<Sv39>> {
+        let prot = ProtFlags::from_bits(prot as _)?;
+        if prot.is_empty() || (prot.contains(ProtFlags::WRITE) && !prot.contains(ProtFlags::READ)) {
+            return None;
+        }
+        let mut flags: [u8; 5] = *b"U___V";
+        if prot.contains(ProtFlags::EXEC) {
+            flags[1] = b'X';
+        }
+        if prot.contains(ProtFlags::WRITE) {
+            flags[2] = b'W';
+        }
+        if prot.contains(ProtFlags::READ) {
+            flags[3] = b'R';
+        }
+        Some(VmFlags::build_from_str(unsafe {
+            core::str::from_utf8_unchecked(&flags)
+        }))
+    }
+
+    /// `addr` 开始 `len` 字节所在的虚页，`addr` 不按页对齐、`len` 为零或超出用户地址空间时返回 `None`。
+    fn user_pages(addr: usize, len: usize) -> Option<Range<VPN<Sv39>>> {
+        let end = addr.checked_add(len)?;
+        if addr & ((1 << Sv39::PAGE_BITS) - 1) != 0 || len == 0 || end > USER_TOP {
+            return None;
+        }
+        Some(VAddr::new(addr).floor()..VAddr::new(end).ceil())
+    }
+
+    impl Memory for SyscallContext {
+        fn mmap(
+            &self,
+            _caller: Caller,
+            addr: usize,
+            length: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            _offset: usize,
+        ) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let flags = match MapFlags::from_bits(flags as _) {
+                // 只支持私有映射
+                Some(flags)
+                    if flags.contains(MapFlags::PRIVATE) && !flags.contains(MapFlags::SHARED) =>
+                {
+                    flags
+                }
+                _ => {
+                    log::error!("unsupported mmap flags: {flags:#x}");
+                    return MAP_FAILED;
+                }
+            };
+            let vm_flags = match prot_flags(prot) {
+                Some(vm_flags) => vm_flags,
+                None => {
+                    log::error!("unsupported mmap prot: {prot:#x}");
+                    return MAP_FAILED;
+                }
+            };
+            if length == 0 {
+                return MAP_FAILED;
+            }
+            // 没有文件系统，只支持匿名映射
+            if !flags.contains(MapFlags::ANONYMOUS) {
+                log::error!("unsupported fd: {fd}");
+                return MAP_FAILED;
+            }
+            let backing = None;
+            let range = if flags.contains(MapFlags::FIXED) {
+                match user_pages(addr, length) {
+                    Some(range) => {
+                        // 覆盖原有的映射
+                        current.address_space.unmap(range.clone());
+                        range
+                    }
+                    None => return MAP_FAILED,
+                }
+            } else {
+                let count = length.div_ceil(1 << Sv39::PAGE_BITS);
+                match current
+                    .address_space
+                    .find_free(VAddr::new(MMAP_BASE).floor(), count)
+                {
+                    Some(range) if range.end.base().val() <= USER_TOP => range,
+                    _ => return MAP_FAILED,
+                }
+            };
+            let start = range.start.base().val();
+            if !current
+                .address_space
+                .map_lazy(range, backing, vm_flags, AreaKind::Mmap)
+            {
+                return MAP_FAILED;
+            }
+            start as isize
+        }
+
+        fn munmap(&self, _caller: Caller, addr: usize, length: usize) -> isize {
+            match user_pages(addr, length) {
+                Some(range) => {
+                    unsafe { PROCESSOR.current().unwrap() }
+                        .address_space
+                        .unmap(range);
+                    0
+                }
+                None => -1,
+            }
+        }
+
+        fn mprotect(&self, _caller: Caller, addr: usize, length: usize, prot: i32) -> isize {
+            match (user_pages(addr, length), prot_flags(prot)) {
+                (Some(range), Some(flags))
+                    if unsafe { PROCESSOR.current().unwrap() }
+                        .address_space
+                        .protect(range, flags) =>
+                {
+                    0
+                }
+                _ => -1,
+            }
+        }
+    }
 }
@@ -19,18 +19,18 @@ use crate::{
     process::Process,
     processor::ProcManager,
 };
-use alloc::alloc::alloc;
+use alloc::{alloc::alloc, sync::Arc};
 use core::{alloc::Layout, mem::MaybeUninit};
-use easy_fs::{FSManager, OpenFlags};
 use impls::Console;
 use kernel_context::foreign::MultislotPortal;
 use kernel_vm::{
     page_table::{MmuMeta, Sv39, VAddr, VmFlags, VmMeta, PPN, VPN},
-    AddressSpace,
+    AddressSpace, AreaKind,
 };
 pub use processor::PROCESSOR;
 use rcore_console::log;
 use rcore_task_manage::ProcId;
+use rcore_vfs::{FSManager, OpenFlags, WOULD_BLOCK};
 use riscv::register::*;
 use sbi_rt::*;
 use signal::SignalResult;
@@ -76,9 +76,14 @@ extern "C" fn rust_main() -> ! {
     syscall::init_process(&SyscallContext);
     syscall::init_scheduling(&SyscallContext);
     syscall::init_clock(&SyscallContext);
+    syscall::init_memory(&SyscallContext);
     syscall::init_signal(&SyscallContext);
-    let initproc = read_all(FS.open("initproc", OpenFlags::RDONLY).unwrap());
-    if let Some(process) = Process::from_elf(ElfFile::new(initproc.as_slice()).unwrap()) {
+    let initproc = FS.open("initproc", OpenFlags::RDONLY).unwrap();
+    // 程序段从读入内存的文件中按需加载，之后文件被删除或修改都不影响进程
+    let initproc = Arc::new(read_all(initproc));
+    if let Some(process) =
+        Process::from_elf(ElfFile::new(initproc.as_slice()).unwrap(), initproc.clone())
+    {
         unsafe {
             PROCESSOR.set_manager(ProcManager::new());
             PROCESSOR.add(process.pid, process, ProcId::from_usize(usize::MAX));
@@ -95,6 +100,16 @@ extern "C" fn rust_main() -> ! {
                     let id: Id = ctx.a(7).into();
                     let args = [ctx.a(0), ctx.a(1), ctx.a(2), ctx.a(3), ctx.a(4), ctx.a(5)];
                     let syscall_ret = syscall::handle(Caller { entity: 0, flow: 0 }, id, args);
+                    // 先把系统调用的结果写回上下文，处理信号时保存的才是系统调用返回后的上下文
+                    match (&syscall_ret, id) {
+                        // 管道暂时不能读写，退回到 ecall，之后重新执行这次系统调用
+                        (Ret::Done(WOULD_BLOCK), Id::READ | Id::WRITE) => *ctx.pc_mut() -= 4,
+                        // 上下文已经恢复成处理信号之前的样子
+                        (Ret::Done(0), Id::RT_SIGRETURN) => {}
+                        (Ret::Done(_), Id::EXIT) => {}
+                        (Ret::Done(ret), _) => *ctx.a_mut(0) = *ret as _,
+                        (Ret::Unsupported(_), _) => {}
+                    }
                     // 目前信号处理位置放在 syscall 执行之后，这只是临时的实现。
                     // 正确处理信号的位置应该是在 “trap 中处理异常和中断和异常之后，返回用户态之前”。
                     // 例如发现有访存异常时，应该触发 SIGSEGV 信号然后进行处理。
@@ -107,14 +122,19 @@ extern "C" fn rust_main() -> ! {
                         SignalResult::ProcessKilled(exit_code) => unsafe {
                             PROCESSOR.make_current_exited(exit_code as _)
                         },
-                        _ => match syscall_ret {
+                        signal_result => match syscall_ret {
                             Ret::Done(ret) => match id {
                                 Id::EXIT => unsafe { PROCESSOR.make_current_exited(ret) },
-                                _ => {
-                                    let ctx = &mut task.context.context;
-                                    *ctx.a_mut(0) = ret as _;
-                                    unsafe { PROCESSOR.make_current_suspend() };
-                                }
+                                // 管道暂时不能读写，阻塞到被唤醒后重新执行这次系统调用。
+                                // 刚转去执行信号处理函数时不阻塞，处理函数返回后再重新执行
+                                Id::READ | Id::WRITE
+                                    if ret == WOULD_BLOCK
+                                        && matches!(
+                                            signal_result,
+                                            SignalResult::NoSignal | SignalResult::IsHandlingSignal
+                                        ) =>
+                                unsafe { PROCESSOR.make_current_blocked() },
+                                _ => unsafe { PROCESSOR.make_current_suspend() },
                             },
                             Ret::Unsupported(_) => {
                                 log::info!("id = {id:?}");
@@ -123,8 +143,23 @@ extern "C" fn rust_main() -> ! {
                         },
                     }
                 }
+                scause::Trap::Exception(
+                    e @ (scause::Exception::LoadPageFault
+                    | scause::Exception::StorePageFault
+                    | scause::Exception::InstructionPageFault),
+                ) if task
+                    .address_space
+                    .handle_page_fault(VAddr::new(stval::read()), page_fault_flags(e)) =>
+                {
+                    // 按需分配了页或分开了写时复制的页，回到用户态重新执行这条指令
+                    unsafe { PROCESSOR.make_current_suspend() };
+                }
                 e => {
-                    log::error!("unsupported trap: {e:?}");
+                    log::error!("unsupported trap: {e:?}, stval = {:#x}", stval::read());
+                    // 打印进程的内存布局，便于找出非法访问的原因
+                    for area in task.address_space.areas() {
+                        log::debug!("{area}");
+                    }
                     unsafe { PROCESSOR.make_current_exited(-3) };
                 }
             }
@@ -134,6 +169,8 @@ extern "C" fn rust_main() -> ! {
         }
     }
 
+    // 关机前把块缓存写回磁盘
+    FS.sync();
     system_reset(Shutdown, NoReason);
     unreachable!()
 }
@@ -166,6 +203,7 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
             s.floor()..e.ceil(),
             PPN::new(s.floor().val()),
             VmFlags::build_from_str(flags),
+            AreaKind::Kernel,
         )
     }
     let s = VAddr::<Sv39>::new(layout.end());
@@ -175,11 +213,13 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
         s.floor()..e.ceil(),
         PPN::new(s.floor().val()),
         VmFlags::build_from_str("_WRV"),
+        AreaKind::Heap,
     );
     space.map_extern(
         PROTAL_TRANSIT..PROTAL_TRANSIT + 1,
         PPN::new(portal >> Sv39::PAGE_BITS),
         VmFlags::build_from_str("__G_XWRV"),
+        AreaKind::Kernel,
     );
     println!();
 
@@ -192,6 +232,7 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
             s.floor()..e.ceil(),
             PPN::new(s.floor().val()),
             VmFlags::build_from_str("_WRV"),
+            AreaKind::Kernel,
         );
     }
 
@@ -200,27 +241,46 @@ fn kernel_space(layout: linker::KernelLayout, memory: usize, portal: usize) {
 }
 
 /// 映射异界传送门。
+///
+/// 传送门所在的页表属于内核地址空间，用户地址空间只是借用。
 fn map_portal(space: &AddressSpace<Sv39, Sv39Manager>) {
     let portal_idx = PROTAL_TRANSIT.index_in(Sv39::MAX_LEVEL);
-    space.root()[portal_idx] = unsafe { KERNEL_SPACE.assume_init_ref() }.root()[portal_idx];
+    space.root()[portal_idx] =
+        Sv39Manager::borrowed(unsafe { KERNEL_SPACE.assume_init_ref() }.root()[portal_idx]);
+}
+
+/// 用户态页异常的访问类型要求的页属性。
+fn page_fault_flags(e: scause::Exception) -> VmFlags<Sv39> {
+    match e {
+        scause::Exception::StorePageFault => VmFlags::build_from_str("U_W_V"),
+        scause::Exception::InstructionPageFault => VmFlags::build_from_str("UX__V"),
+        _ => VmFlags::build_from_str("U__RV"),
+    }
 }
 
 /// 各种接口库的实现。
 mod impls {
     use crate::{
-        fs::{read_all, FS},
+        fs::{read_all, InodeBacking, FS},
         PROCESSOR,
     };
-    use alloc::{alloc::alloc_zeroed, string::String, vec::Vec};
-    use core::{alloc::Layout, ptr::NonNull};
-    use easy_fs::UserBuffer;
-    use easy_fs::{FSManager, OpenFlags};
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        collections::BTreeMap,
+        string::String,
+        sync::Arc,
+        vec::Vec,
+    };
+    use core::{alloc::Layout, ops::Range, ptr::NonNull};
     use kernel_vm::{
         page_table::{MmuMeta, Pte, Sv39, VAddr, VmFlags, PPN, VPN},
-        PageManager,
+        AddressSpace, AreaKind, Backing, PageManager,
     };
     use rcore_console::log;
     use rcore_task_manage::ProcId;
+    use rcore_vfs::{
+        make_pipe, FSManager, FileHandle, FileType, OpenFlags, SeekFrom, UserBuffer, WOULD_BLOCK,
+    };
     use signal::SignalNo;
     use spin::Mutex;
     use syscall::*;
@@ -229,6 +289,9 @@ mod impls {
     #[repr(transparent)]
     pub struct Sv39Manager(NonNull<Pte<Sv39>>);
 
+    /// 被多个地址空间共享的物理页的引用计数，只属于一个地址空间的页不在表中。
+    static FRAME_REFS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
     impl Sv39Manager {
         const OWNED: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 8) };
 
@@ -242,9 +305,32 @@ mod impls {
             }
             .cast()
         }
+
+        #[inline]
+        fn page_dealloc<T>(ptr: *mut T) {
+            unsafe {
+                dealloc(
+                    ptr.cast(),
+                    Layout::from_size_align_unchecked(1 << Sv39::PAGE_BITS, 1 << Sv39::PAGE_BITS),
+                )
+            }
+        }
+
+        /// 去掉页表项的所有权标记，地址空间回收时不会释放它指向的页。
+        pub fn borrowed(pte: Pte<Sv39>) -> Pte<Sv39> {
+            let flags = pte.flags();
+            if flags.contains(Self::OWNED) {
+                (flags ^ Self::OWNED).build_pte(pte.ppn())
+            } else {
+                pte
+            }
+        }
     }
 
     impl PageManager<Sv39> for Sv39Manager {
+        const WRITABLE: VmFlags<Sv39> = VmFlags::build_from_str("W");
+        const COW: VmFlags<Sv39> = unsafe { VmFlags::from_raw(1 << 9) };
+
         #[inline]
         fn new_root() -> Self {
             Self(NonNull::new(Self::page_alloc(1)).unwrap())
@@ -281,12 +367,37 @@ mod impls {
             NonNull::new(Self::page_alloc(len)).unwrap()
         }
 
-        fn deallocate(&mut self, _pte: Pte<Sv39>, _len: usize) -> usize {
-            todo!()
+        #[inline]
+        fn share(&mut self, ppn: PPN<Sv39>) {
+            *FRAME_REFS.lock().entry(ppn.val()).or_insert(1) += 1;
+        }
+
+        #[inline]
+        fn ref_count(&self, ppn: PPN<Sv39>) -> usize {
+            FRAME_REFS.lock().get(&ppn.val()).copied().unwrap_or(1)
+        }
+
+        fn deallocate(&mut self, pte: Pte<Sv39>, len: usize) -> usize {
+            let mut refs = FRAME_REFS.lock();
+            let mut freed = 0;
+            for ppn in (pte.ppn().val()..).take(len) {
+                match refs.get_mut(&ppn) {
+                    Some(count) if *count > 2 => *count -= 1,
+                    Some(_) => {
+                        refs.remove(&ppn);
+                    }
+                    None => {
+                        Self::page_dealloc(self.p_to_v::<u8>(PPN::new(ppn)).as_ptr());
+                        freed += 1;
+                    }
+                }
+            }
+            freed
         }
 
+        #[inline]
         fn drop_root(&mut self) {
-            todo!()
+            Self::page_dealloc(self.0.as_ptr());
         }
     }
 
@@ -304,24 +415,58 @@ mod impls {
     const READABLE: VmFlags<Sv39> = VmFlags::build_from_str("RV");
     const WRITEABLE: VmFlags<Sv39> = VmFlags::build_from_str("W_V");
 
+    /// 从用户地址空间读取以 `\0` 结尾的字符串。
+    fn read_str(address_space: &mut AddressSpace<Sv39, Sv39Manager>, ptr: usize) -> Option<String> {
+        let mut string = String::new();
+        let mut addr = ptr;
+        loop {
+            let ch = unsafe {
+                *address_space
+                    .translate::<u8>(VAddr::new(addr), READABLE)?
+                    .as_ptr()
+            };
+            if ch == 0 {
+                break Some(string);
+            }
+            string.push(ch as char);
+            addr += 1;
+        }
+    }
+
+    /// 唤醒等待管道的进程，进程已经结束时忽略
+    fn wake_process(pid: usize) {
+        unsafe { PROCESSOR.re_enque(ProcId::from_usize(pid)) };
+    }
+
+    /// 把用户地址空间中从 `ptr` 开始的 `len` 个字节逐页翻译成内核可以访问的若干段。
+    fn user_buffer(
+        address_space: &mut AddressSpace<Sv39, Sv39Manager>,
+        ptr: usize,
+        len: usize,
+        flags: VmFlags<Sv39>,
+    ) -> Option<Vec<&'static mut [u8]>> {
+        address_space
+            .translate_buffer(VAddr::new(ptr), len, flags)
+            .map(|segments| {
+                segments
+                    .into_iter()
+                    .map(|segment| unsafe { &mut *segment.as_ptr() })
+                    .collect()
+            })
+    }
+
     impl IO for SyscallContext {
         fn write(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = unsafe { PROCESSOR.current().unwrap() };
-            if let Some(ptr) = current.address_space.translate(VAddr::new(buf), READABLE) {
-                if fd == STDOUT || fd == STDDEBUG {
-                    print!("{}", unsafe {
-                        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-                            ptr.as_ptr(),
-                            count,
-                        ))
-                    });
-                    count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, READABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
                     let mut file = file.lock();
                     if file.writable() {
-                        let mut v: Vec<&'static mut [u8]> = Vec::new();
-                        unsafe { v.push(core::slice::from_raw_parts_mut(ptr.as_ptr(), count)) };
-                        file.write(UserBuffer::new(v)) as _
+                        let ret = file.write(UserBuffer::new(v));
+                        if ret == WOULD_BLOCK {
+                            file.wait(current.pid.get_usize(), wake_process);
+                        }
+                        ret
                     } else {
                         log::error!("file not writable");
                         -1
@@ -338,23 +483,15 @@ mod impls {
 
         fn read(&self, _caller: Caller, fd: usize, buf: usize, count: usize) -> isize {
             let current = unsafe { PROCESSOR.current().unwrap() };
-            if let Some(ptr) = current.address_space.translate(VAddr::new(buf), WRITEABLE) {
-                if fd == STDIN {
-                    let mut ptr = ptr.as_ptr();
-                    for _ in 0..count {
-                        #[allow(deprecated)]
-                        unsafe {
-                            *ptr = sbi_rt::legacy::console_getchar() as u8;
-                            ptr = ptr.add(1);
-                        }
-                    }
-                    count as _
-                } else if let Some(file) = &current.fd_table[fd] {
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, WRITEABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
                     let mut file = file.lock();
                     if file.readable() {
-                        let mut v: Vec<&'static mut [u8]> = Vec::new();
-                        unsafe { v.push(core::slice::from_raw_parts_mut(ptr.as_ptr(), count)) };
-                        file.read(UserBuffer::new(v)) as _
+                        let ret = file.read(UserBuffer::new(v));
+                        if ret == WOULD_BLOCK {
+                            file.wait(current.pid.get_usize(), wake_process);
+                        }
+                        ret
                     } else {
                         log::error!("file not readable");
                         -1
@@ -370,29 +507,118 @@ mod impls {
         }
 
         fn open(&self, _caller: Caller, path: usize, flags: usize) -> isize {
-            // FS.open(, flags)
             let current = unsafe { PROCESSOR.current().unwrap() };
-            if let Some(ptr) = current.address_space.translate(VAddr::new(path), READABLE) {
-                let mut string = String::new();
-                let mut raw_ptr: *mut u8 = ptr.as_ptr();
-                loop {
-                    unsafe {
-                        let ch = *raw_ptr;
-                        if ch == 0 {
-                            break;
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                let flags = OpenFlags::from_bits_truncate(flags as u32);
+                if let Some(fd) = FS.open(path.as_str(), flags) {
+                    let cloexec = flags.contains(OpenFlags::CLOEXEC);
+                    current
+                        .fd_table
+                        .insert(fd.as_ref().clone(), cloexec)
+                        .map_or(-1, |fd| fd as isize)
+                } else {
+                    -1
+                }
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn pipe2(&self, _caller: Caller, pipefd: usize, flags: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(mut ptr) = current
+                .address_space
+                .translate::<[usize; 2]>(VAddr::new(pipefd), WRITEABLE)
+            {
+                let cloexec =
+                    OpenFlags::from_bits_truncate(flags as u32).contains(OpenFlags::CLOEXEC);
+                let (read_end, write_end) = make_pipe();
+                let read_fd = current.fd_table.insert(FileHandle::pipe(read_end), cloexec);
+                let write_fd = current
+                    .fd_table
+                    .insert(FileHandle::pipe(write_end), cloexec);
+                match (read_fd, write_fd) {
+                    (Some(read_fd), Some(write_fd)) => {
+                        *unsafe { ptr.as_mut() } = [read_fd, write_fd];
+                        0
+                    }
+                    (read_fd, _) => {
+                        // 描述符用完了，关闭已经打开的读端
+                        if let Some(read_fd) = read_fd {
+                            current.fd_table.close(read_fd);
                         }
-                        string.push(ch as char);
-                        raw_ptr = (raw_ptr as usize + 1) as *mut u8;
+                        -1
                     }
                 }
+            } else {
+                log::error!("ptr not writeable");
+                -1
+            }
+        }
 
-                if let Some(fd) =
-                    FS.open(string.as_str(), OpenFlags::from_bits(flags as u32).unwrap())
-                {
-                    let new_fd = current.fd_table.len();
-                    current.fd_table.push(Some(Mutex::new(fd.as_ref().clone())));
-                    new_fd as isize
+        fn dup(&self, _caller: Caller, fd: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            // 使用最小的空闲描述符，关闭标准输入输出后 dup 就能替换它们
+            current.fd_table.dup(fd).map_or(-1, |fd| fd as isize)
+        }
+
+        fn dup3(&self, _caller: Caller, oldfd: usize, newfd: usize, flags: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let cloexec = OpenFlags::from_bits_truncate(flags as u32).contains(OpenFlags::CLOEXEC);
+            current
+                .fd_table
+                .dup3(oldfd, newfd, cloexec)
+                .map_or(-1, |fd| fd as isize)
+        }
+
+        #[inline]
+        fn close(&self, _caller: Caller, fd: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if current.fd_table.close(fd) {
+                0
+            } else {
+                -1
+            }
+        }
+
+        fn lseek(&self, _caller: Caller, fd: usize, offset: isize, whence: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let pos = match whence {
+                SEEK_SET if offset >= 0 => SeekFrom::Start(offset as usize),
+                SEEK_CUR => SeekFrom::Current(offset),
+                SEEK_END => SeekFrom::End(offset),
+                _ => return -1,
+            };
+            match current.fd_table.get(fd) {
+                Some(file) => file.lock().seek(pos),
+                None => {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
+            }
+        }
+
+        fn pread(
+            &self,
+            _caller: Caller,
+            fd: usize,
+            buf: usize,
+            count: usize,
+            offset: usize,
+        ) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, WRITEABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
+                    let file = file.lock();
+                    if file.readable() {
+                        file.read_at(offset, UserBuffer::new(v))
+                    } else {
+                        log::error!("file not readable");
+                        -1
+                    }
                 } else {
+                    log::error!("unsupported fd: {fd}");
                     -1
                 }
             } else {
@@ -401,15 +627,240 @@ mod impls {
             }
         }
 
-        #[inline]
-        fn close(&self, _caller: Caller, fd: usize) -> isize {
+        fn pwrite(
+            &self,
+            _caller: Caller,
+            fd: usize,
+            buf: usize,
+            count: usize,
+            offset: usize,
+        ) -> isize {
             let current = unsafe { PROCESSOR.current().unwrap() };
-            if fd >= current.fd_table.len() || current.fd_table[fd].is_none() {
-                return -1;
+            if let Some(v) = user_buffer(&mut current.address_space, buf, count, READABLE) {
+                if let Some(file) = current.fd_table.get(fd) {
+                    let file = file.lock();
+                    if file.writable() {
+                        file.write_at(offset, UserBuffer::new(v))
+                    } else {
+                        log::error!("file not writable");
+                        -1
+                    }
+                } else {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn mkdir(&self, _caller: Caller, path: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                FS.mkdir(path.as_str())
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn link(&self, _caller: Caller, src: usize, dst: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, src),
+                read_str(&mut current.address_space, dst),
+            ) {
+                (Some(src), Some(dst)) => FS.link(src.as_str(), dst.as_str()),
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn unlink(&self, _caller: Caller, path: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(path) = read_str(&mut current.address_space, path) {
+                FS.unlink(path.as_str())
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
+
+        fn symlink(&self, _caller: Caller, target: usize, linkpath: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, target),
+                read_str(&mut current.address_space, linkpath),
+            ) {
+                (Some(target), Some(linkpath)) => FS.symlink(target.as_str(), linkpath.as_str()),
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn readlink(&self, _caller: Caller, path: usize, buf: usize, bufsiz: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let target = match read_str(&mut current.address_space, path) {
+                Some(path) => match FS.readlink(path.as_str()) {
+                    Some(target) => target,
+                    None => return -1,
+                },
+                None => {
+                    log::error!("ptr not readable");
+                    return -1;
+                }
+            };
+            // 和 Linux 一样，超出 `bufsiz` 的部分被截断，不补 `\0`
+            let len = target.len().min(bufsiz);
+            for (i, byte) in target.bytes().take(len).enumerate() {
+                match current
+                    .address_space
+                    .translate::<u8>(VAddr::new(buf + i), WRITEABLE)
+                {
+                    Some(mut ptr) => *unsafe { ptr.as_mut() } = byte,
+                    None => {
+                        log::error!("ptr not writeable");
+                        return -1;
+                    }
+                }
+            }
+            len as _
+        }
+
+        fn rename(&self, _caller: Caller, oldpath: usize, newpath: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, oldpath),
+                read_str(&mut current.address_space, newpath),
+            ) {
+                (Some(oldpath), Some(newpath)) => FS.rename(oldpath.as_str(), newpath.as_str()),
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn fstat(&self, _caller: Caller, fd: usize, st: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let inode = match current.fd_table.get(fd) {
+                Some(file) => file.lock().inode.clone(),
+                _ => None,
+            };
+            if let Some(inode) = inode {
+                if let Some(mut ptr) = current.address_space.translate(VAddr::new(st), WRITEABLE) {
+                    let stat = inode.metadata();
+                    *unsafe { ptr.as_mut() } = Stat {
+                        dev: 0,
+                        ino: stat.ino,
+                        mode: match stat.type_ {
+                            FileType::File => StatMode::FILE,
+                            FileType::Directory => StatMode::DIR,
+                            FileType::SymLink => StatMode::LINK,
+                            FileType::CharDevice => StatMode::CHAR,
+                            FileType::BlockDevice => StatMode::BLOCK,
+                        },
+                        nlink: stat.nlink,
+                        size: stat.size,
+                        perm: stat.mode,
+                        atime: stat.atime,
+                        mtime: stat.mtime,
+                        crtime: stat.crtime,
+                    };
+                    0
+                } else {
+                    log::error!("ptr not writeable");
+                    -1
+                }
+            } else {
+                log::error!("unsupported fd: {fd}");
+                -1
+            }
+        }
+
+        fn ftruncate(&self, _caller: Caller, fd: usize, len: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let inode = match current.fd_table.get(fd) {
+                Some(file) => {
+                    let file = file.lock();
+                    if file.writable() {
+                        file.inode.clone()
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+            match inode {
+                Some(inode) if inode.truncate(len) => 0,
+                Some(_) => -1,
+                None => {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
             }
-            current.fd_table[fd].take();
+        }
+
+        fn fsync(&self, _caller: Caller, fd: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match current.fd_table.get(fd) {
+                Some(file) => {
+                    if let Some(inode) = &file.lock().inode {
+                        inode.sync();
+                    }
+                    0
+                }
+                _ => {
+                    log::error!("unsupported fd: {fd}");
+                    -1
+                }
+            }
+        }
+
+        fn sync(&self, _caller: Caller) -> isize {
+            FS.sync();
             0
         }
+
+        fn mount(
+            &self,
+            _caller: Caller,
+            source: usize,
+            target: usize,
+            fstype: usize,
+            _flags: usize,
+            _data: usize,
+        ) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            match (
+                read_str(&mut current.address_space, source),
+                read_str(&mut current.address_space, target),
+                read_str(&mut current.address_space, fstype),
+            ) {
+                (Some(source), Some(target), Some(fstype)) => {
+                    FS.mount(source.as_str(), target.as_str(), fstype.as_str())
+                }
+                _ => {
+                    log::error!("ptr not readable");
+                    -1
+                }
+            }
+        }
+
+        fn umount(&self, _caller: Caller, target: usize, _flags: usize) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            if let Some(target) = read_str(&mut current.address_space, target) {
+                FS.umount(target.as_str())
+            } else {
+                log::error!("ptr not readable");
+                -1
+            }
+        }
     }
 
     impl Process for SyscallContext {
@@ -451,8 +902,16 @@ mod impls {
                         -1
                     },
                     |fd| {
-                        current.exec(ElfFile::new(&read_all(fd)).unwrap());
-                        0
+                        // 目录、设备和不是 ELF 的文件都不能执行
+                        let type_ = fd.inode.as_ref().map(|inode| inode.metadata().type_);
+                        if type_ != Some(FileType::File) {
+                            return -1;
+                        }
+                        let data = Arc::new(read_all(fd));
+                        match ElfFile::new(data.as_slice()) {
+                            Ok(elf) if current.exec(elf, data.clone()) => 0,
+                            _ => -1,
+                        }
                     },
                 )
         }
@@ -515,6 +974,157 @@ mod impls {
         }
     }
 
+    /// 内核选择映射地址时，从这里向上寻找空闲的虚拟地址块
+    const MMAP_BASE: usize = 1 << 36;
+    /// 用户地址空间的上界
+    const USER_TOP: usize = 1 << 38;
+
+    /// 把 `mmap` 和 `mprotect` 的保护属性转换为用户页的属性。
+    ///
+    /// 不可访问的页和只写的页都不能表示为有效的叶子页表项，返回 `None`。
+    fn prot_flags(prot: i32) -> Option<VmFlags<Sv39>> {
+        let prot = ProtFlags::from_bits(prot as _)?;
+        if prot.is_empty() || (prot.contains(ProtFlags::WRITE) && !prot.contains(ProtFlags::READ)) {
+            return None;
+        }
+        let mut flags: [u8; 5] = *b"U___V";
+        if prot.contains(ProtFlags::EXEC) {
+            flags[1] = b'X';
+        }
+        if prot.contains(ProtFlags::WRITE) {
+            flags[2] = b'W';
+        }
+        if prot.contains(ProtFlags::READ) {
+            flags[3] = b'R';
+        }
+        Some(VmFlags::build_from_str(unsafe {
+            core::str::from_utf8_unchecked(&flags)
+        }))
+    }
+
+    /// `addr` 开始 `len` 字节所在的虚页，`addr` 不按页对齐、`len` 为零或超出用户地址空间时返回 `None`。
+    fn user_pages(addr: usize, len: usize) -> Option<Range<VPN<Sv39>>> {
+        let end = addr.checked_add(len)?;
+        if addr & ((1 << Sv39::PAGE_BITS) - 1) != 0 || len == 0 || end > USER_TOP {
+            return None;
+        }
+        Some(VAddr::new(addr).floor()..VAddr::new(end).ceil())
+    }
+
+    impl Memory for SyscallContext {
+        fn mmap(
+            &self,
+            _caller: Caller,
+            addr: usize,
+            length: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: usize,
+        ) -> isize {
+            let current = unsafe { PROCESSOR.current().unwrap() };
+            let flags = match MapFlags::from_bits(flags as _) {
+                // 只支持私有映射
+                Some(flags)
+                    if flags.contains(MapFlags::PRIVATE) && !flags.contains(MapFlags::SHARED) =>
+                {
+                    flags
+                }
+                _ => {
+                    log::error!("unsupported mmap flags: {flags:#x}");
+                    return MAP_FAILED;
+                }
+            };
+            let vm_flags = match prot_flags(prot) {
+                Some(vm_flags) => vm_flags,
+                None => {
+                    log::error!("unsupported mmap prot: {prot:#x}");
+                    return MAP_FAILED;
+                }
+            };
+            if length == 0 || offset & ((1 << Sv39::PAGE_BITS) - 1) != 0 {
+                return MAP_FAILED;
+            }
+            let backing = if flags.contains(MapFlags::ANONYMOUS) {
+                None
+            } else {
+                match current.fd_table.get(fd as usize) {
+                    Some(file) => {
+                        let file = file.lock();
+                        match &file.inode {
+                            Some(inode) if file.readable() => Some(Backing {
+                                file: Arc::new(InodeBacking(inode.clone())),
+                                offset,
+                                len: length,
+                                page_offset: 0,
+                            }),
+                            _ => {
+                                log::error!("file not readable");
+                                return MAP_FAILED;
+                            }
+                        }
+                    }
+                    None => {
+                        log::error!("unsupported fd: {fd}");
+                        return MAP_FAILED;
+                    }
+                }
+            };
+            let range = if flags.contains(MapFlags::FIXED) {
+                match user_pages(addr, length) {
+                    Some(range) => {
+                        // 覆盖原有的映射
+                        current.address_space.unmap(range.clone());
+                        range
+                    }
+                    None => return MAP_FAILED,
+                }
+            } else {
+                let count = length.div_ceil(1 << Sv39::PAGE_BITS);
+                match current
+                    .address_space
+                    .find_free(VAddr::new(MMAP_BASE).floor(), count)
+                {
+                    Some(range) if range.end.base().val() <= USER_TOP => range,
+                    _ => return MAP_FAILED,
+                }
+            };
+            let start = range.start.base().val();
+            if !current
+                .address_space
+                .map_lazy(range, backing, vm_flags, AreaKind::Mmap)
+            {
+                return MAP_FAILED;
+            }
+            start as isize
+        }
+
+        fn munmap(&self, _caller: Caller, addr: usize, length: usize) -> isize {
+            match user_pages(addr, length) {
+                Some(range) => {
+                    unsafe { PROCESSOR.current().unwrap() }
+                        .address_space
+                        .unmap(range);
+                    0
+                }
+                None => -1,
+            }
+        }
+
+        fn mprotect(&self, _caller: Caller, addr: usize, length: usize, prot: i32) -> isize {
+            match (user_pages(addr, length), prot_flags(prot)) {
+                (Some(range), Some(flags))
+                    if unsafe { PROCESSOR.current().unwrap() }
+                        .address_space
+                        .protect(range, flags) =>
+                {
+                    0
+                }
+                _ => -1,
+            }
+        }
+    }
+
     impl Signal for SyscallContext {
         fn kill(&self, _caller: Caller, pid: isize, signum: u8) -> isize {
             if let Some(target_task) =
@@ -523,6 +1133,8 @@ mod impls {
                 if let Ok(signal_no) = SignalNo::try_from(signum) {
                     if signal_no != SignalNo::ERR {
                         target_task.signal.add_signal(signal_no);
+                        // 目标进程阻塞在管道上时唤醒它，让它及时处理信号
+                        unsafe { PROCESSOR.re_enque(ProcId::from_usize(pid as usize)) };
                         return 0;
                     }
                 }
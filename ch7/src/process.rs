@@ -1,16 +1,15 @@
-use crate::{map_portal, Sv39Manager};
-use alloc::{alloc::alloc_zeroed, boxed::Box, vec::Vec};
-use core::{alloc::Layout, str::FromStr};
-use easy_fs::FileHandle;
+use crate::{fs::stdio_fd_table, map_portal, Sv39Manager};
+use alloc::{boxed::Box, sync::Arc};
+use core::str::FromStr;
 use kernel_context::{foreign::ForeignContext, LocalContext};
 use kernel_vm::{
-    page_table::{MmuMeta, Sv39, VAddr, VmFlags, PPN, VPN},
-    AddressSpace,
+    page_table::{MmuMeta, Sv39, VAddr, VmFlags, VPN},
+    AddressSpace, AreaKind, Backing, BackingFile,
 };
 use rcore_task_manage::ProcId;
+use rcore_vfs::FdTable;
 use signal::Signal;
 use signal_impl::SignalImpl;
-use spin::Mutex;
 use xmas_elf::{
     header::{self, HeaderPt2, Machine},
     program, ElfFile,
@@ -25,50 +24,48 @@ pub struct Process {
     pub address_space: AddressSpace<Sv39, Sv39Manager>,
 
     /// 文件描述符表
-    pub fd_table: Vec<Option<Mutex<FileHandle>>>,
+    pub fd_table: FdTable,
 
     /// 信号模块
     pub signal: Box<dyn Signal>,
 }
 
 impl Process {
-    pub fn exec(&mut self, elf: ElfFile) {
-        let proc = Process::from_elf(elf).unwrap();
+    /// 不是可以加载的程序时返回 `false`，原来的地址空间和文件描述符保持不变
+    pub fn exec(&mut self, elf: ElfFile, file: Arc<dyn BackingFile>) -> bool {
+        let proc = match Process::from_elf(elf, file) {
+            Some(proc) => proc,
+            None => return false,
+        };
         self.address_space = proc.address_space;
         self.context = proc.context;
+        self.fd_table.close_on_exec();
+        true
     }
 
     pub fn fork(&mut self) -> Option<Process> {
         // 子进程 pid
         let pid = ProcId::new();
-        // 复制父进程地址空间
-        let parent_addr_space = &self.address_space;
+        // 复制父进程地址空间，父子进程写时复制地共享物理页
         let mut address_space: AddressSpace<Sv39, Sv39Manager> = AddressSpace::new();
-        parent_addr_space.cloneself(&mut address_space);
+        self.address_space.cloneself(&mut address_space);
         map_portal(&address_space);
         // 复制父进程上下文
         let context = self.context.context.clone();
         let satp = (8 << 60) | address_space.root_ppn().val();
         let foreign_ctx = ForeignContext { context, satp };
-        // 复制父进程文件符描述表
-        let mut new_fd_table: Vec<Option<Mutex<FileHandle>>> = Vec::new();
-        for fd in self.fd_table.iter_mut() {
-            if let Some(file) = fd {
-                new_fd_table.push(Some(Mutex::new(file.get_mut().clone())));
-            } else {
-                new_fd_table.push(None);
-            }
-        }
+        // 复制父进程文件描述符表，父子进程的描述符指向同一个打开的文件，共享读写位置
+        let fd_table = self.fd_table.clone();
         Some(Self {
             pid,
             context: foreign_ctx,
             address_space,
-            fd_table: new_fd_table,
+            fd_table,
             signal: self.signal.from_fork(),
         })
     }
 
-    pub fn from_elf(elf: ElfFile) -> Option<Self> {
+    pub fn from_elf(elf: ElfFile, file: Arc<dyn BackingFile>) -> Option<Self> {
         let entry = match elf.header.pt2 {
             HeaderPt2::Header64(pt2)
                 if pt2.type_.as_type() == header::Type::Executable
@@ -91,8 +88,11 @@ impl Process {
             let off_file = program.offset() as usize;
             let len_file = program.file_size() as usize;
             let off_mem = program.virtual_addr() as usize;
-            let end_mem = off_mem + program.mem_size() as usize;
-            assert_eq!(off_file & PAGE_MASK, off_mem & PAGE_MASK);
+            // 文件中和内存中的偏移在页内要对齐
+            let end_mem = match off_mem.checked_add(program.mem_size() as usize) {
+                Some(end_mem) if off_file & PAGE_MASK == off_mem & PAGE_MASK => end_mem,
+                _ => return None,
+            };
 
             let mut flags: [u8; 5] = *b"U___V";
             if program.flags().is_execute() {
@@ -104,25 +104,29 @@ impl Process {
             if program.flags().is_read() {
                 flags[3] = b'R';
             }
-            address_space.map(
+            if !address_space.map_lazy(
                 VAddr::new(off_mem).floor()..VAddr::new(end_mem).ceil(),
-                &elf.input[off_file..][..len_file],
-                off_mem & PAGE_MASK,
+                Some(Backing {
+                    file: file.clone(),
+                    offset: off_file,
+                    len: len_file,
+                    page_offset: off_mem & PAGE_MASK,
+                }),
                 VmFlags::from_str(unsafe { core::str::from_utf8_unchecked(&flags) }).unwrap(),
-            );
+                AreaKind::Elf,
+            ) {
+                return None;
+            }
         }
-        // 映射用户栈
-        let stack = unsafe {
-            alloc_zeroed(Layout::from_size_align_unchecked(
-                2 << Sv39::PAGE_BITS,
-                1 << Sv39::PAGE_BITS,
-            ))
-        };
-        address_space.map_extern(
+        // 映射用户栈，第一次访问时才分配
+        if !address_space.map_lazy(
             VPN::new((1 << 26) - 2)..VPN::new(1 << 26),
-            PPN::new(stack as usize >> Sv39::PAGE_BITS),
+            None,
             VmFlags::build_from_str("U_WRV"),
-        );
+            AreaKind::Stack,
+        ) {
+            return None;
+        }
         // 映射异界传送门
         map_portal(&address_space);
 
@@ -133,12 +137,7 @@ impl Process {
             pid: ProcId::new(),
             context: ForeignContext { context, satp },
             address_space,
-            fd_table: vec![
-                // Stdin
-                Some(Mutex::new(FileHandle::empty(true, false))),
-                // Stdout
-                Some(Mutex::new(FileHandle::empty(false, true))),
-            ],
+            fd_table: stdio_fd_table(),
             signal: Box::new(SignalImpl::new()),
         })
     }
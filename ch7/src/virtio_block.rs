@@ -19,6 +19,14 @@ pub static BLOCK_DEVICE: Lazy<Arc<dyn BlockDevice>> = Lazy::new(|| {
     })
 });
 
+/// 块设备的块数，即 virtio-blk 配置空间中以 512 字节扇区为单位的容量
+pub fn block_count() -> usize {
+    // 配置空间从 0x100 开始，第一个字段是 64 位的容量，分两次按 32 位读
+    let config = (VIRTIO0 + 0x100) as *const u32;
+    let (low, high) = unsafe { (config.read_volatile(), config.add(1).read_volatile()) };
+    ((high as usize) << 32) | low as usize
+}
+
 struct VirtIOBlock(Mutex<VirtIOBlk<'static, VirtioHal>>);
 
 impl BlockDevice for VirtIOBlock {
@@ -70,7 +78,7 @@ impl Hal for VirtioHal {
         const VALID: VmFlags<Sv39> = VmFlags::build_from_str("__V");
         let ptr: NonNull<u8> = unsafe {
             KERNEL_SPACE
-                .assume_init_ref()
+                .assume_init_mut()
                 .translate(VAddr::new(vaddr), VALID)
                 .unwrap()
         };
@@ -1,54 +1,108 @@
-use crate::virtio_block::BLOCK_DEVICE;
-use alloc::{string::String, sync::Arc, vec::Vec};
-use easy_fs::{EasyFileSystem, FSManager, FileHandle, Inode, OpenFlags};
+use crate::virtio_block::{block_count, BLOCK_DEVICE};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use devfs::{BlockFile, DevFs, Device, Random};
+use easy_fs::{CacheConfig, EasyFileSystem, EasyFs, LruPolicy};
+use fat32::Fat32;
+use kernel_vm::BackingFile;
+use rcore_vfs::{FSManager, FdTable, FileHandle, FileSystem, Inode, MountTable};
 use spin::Lazy;
+use tmpfs::TmpFs;
 
-pub static FS: Lazy<FileSystem> = Lazy::new(|| FileSystem {
-    root: EasyFileSystem::root_inode(&EasyFileSystem::open(BLOCK_DEVICE.clone())),
-});
+/// 块缓存容量
+const BLOCK_CACHE_CAPACITY: usize = 64;
+
+/// 时钟频率
+const CLOCK_FREQ: u64 = 12_500_000;
 
-pub struct FileSystem {
-    root: Inode,
+/// 没有实时时钟，用开机以来的秒数记录文件时间
+fn now() -> u64 {
+    riscv::register::time::read() as u64 / CLOCK_FREQ
 }
 
-impl FSManager for FileSystem {
-    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>> {
-        let (readable, writable) = flags.read_write();
-        if flags.contains(OpenFlags::CREATE) {
-            if let Some(inode) = self.find(path) {
-                // Clear size
-                inode.clear();
-                Some(Arc::new(FileHandle::new(readable, writable, inode)))
-            } else {
-                // Create new file
-                self.root
-                    .create(path)
-                    .map(|new_inode| Arc::new(FileHandle::new(readable, writable, new_inode)))
+/// 打开块设备上的文件系统，是 FAT32 卷时只读挂载，否则按 easy-fs 打开
+fn root_fs() -> Arc<dyn FileSystem> {
+    if let Some(fat) = Fat32::open(BLOCK_DEVICE.clone()) {
+        return Arc::new(fat);
+    }
+    EasyFileSystem::set_clock(|| now() as u32);
+    let efs = EasyFileSystem::open_with(
+        BLOCK_DEVICE.clone(),
+        CacheConfig {
+            capacity: BLOCK_CACHE_CAPACITY,
+            policy: Box::new(LruPolicy::default()),
+        },
+    );
+    Arc::new(EasyFs::new(efs))
+}
+
+/// 控制台
+struct ConsoleDevice;
+
+impl Device for ConsoleDevice {
+    /// 逐字节读取键盘输入，直到填满 `buf`
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+        for byte in buf.iter_mut() {
+            #[allow(deprecated)]
+            {
+                *byte = sbi_rt::legacy::console_getchar() as u8;
             }
-        } else {
-            self.find(path).map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(FileHandle::new(readable, writable, inode))
-            })
         }
+        buf.len()
     }
 
-    fn find(&self, path: &str) -> Option<Arc<Inode>> {
-        self.root.find(path)
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        print!("{}", unsafe { core::str::from_utf8_unchecked(buf) });
+        buf.len()
     }
 
-    fn readdir(&self, _path: &str) -> Option<alloc::vec::Vec<String>> {
-        Some(self.root.readdir())
+    fn mode(&self) -> u32 {
+        0o620
     }
+}
 
-    fn link(&self, _src: &str, _dst: &str) -> isize {
-        unimplemented!()
-    }
+/// 设备文件系统，挂载到 `/dev`
+static DEV: Lazy<Arc<DevFs>> = Lazy::new(|| {
+    let dev = DevFs::new();
+    dev.add("console", Arc::new(ConsoleDevice));
+    let seed = riscv::register::time::read() as u64;
+    dev.add("random", Arc::new(Random::new(seed)));
+    // 块设备上挂载着根文件系统，只读，读之前先写回块缓存
+    let vda = BlockFile::mounted(BLOCK_DEVICE.clone(), block_count(), || FS.sync());
+    dev.add("vda", Arc::new(vda));
+    Arc::new(dev)
+});
+
+/// 挂载表，块设备上的文件系统是根文件系统，`/tmp` 挂载一个 tmpfs，`/dev` 挂载设备文件系统
+pub static FS: Lazy<MountTable> = Lazy::new(|| {
+    let fs = MountTable::new(root_fs());
+    fs.register("tmpfs", |_| Some(Arc::new(TmpFs::new(now))));
+    fs.register("devfs", |_| Some(DEV.clone()));
+    // 镜像中可能还没有 /tmp 和 /dev，只读的根文件系统上需要镜像中已有这两个目录
+    fs.mkdir("tmp");
+    fs.mount("tmpfs", "tmp", "tmpfs");
+    fs.mkdir("dev");
+    fs.mount("devfs", "dev", "devfs");
+    fs
+});
+
+/// 初始进程的描述符表，0、1、2 分别是指向控制台的标准输入、标准输出和标准错误
+///
+/// 直接从设备文件系统取得控制台，`/dev` 没有挂载时也能使用。
+pub fn stdio_fd_table() -> FdTable {
+    let console = DEV.root_inode().find("console").unwrap();
+    let mut fd_table = FdTable::new();
+    fd_table.insert(FileHandle::new(true, false, console.clone()), false);
+    fd_table.insert(FileHandle::new(false, true, console.clone()), false);
+    fd_table.insert(FileHandle::new(false, true, console), false);
+    fd_table
+}
+
+/// `mmap` 按需读取的文件，文件被删除后 inode 在映射解除之前仍然有效
+pub struct InodeBacking(pub Arc<dyn Inode>);
 
-    fn unlink(&self, _path: &str) -> isize {
-        unimplemented!()
+impl BackingFile for InodeBacking {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.0.read_at(offset, buf)
     }
 }
 
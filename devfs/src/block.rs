@@ -0,0 +1,95 @@
+use crate::Device;
+use alloc::sync::Arc;
+use easy_fs::{BlockDevice, BLOCK_SZ};
+use rcore_vfs::FileType;
+
+/// 把整个块设备当作一个文件读写
+///
+/// 读写直接访问设备，不经过文件系统的块缓存。设备上挂载了文件系统时用 [`BlockFile::mounted`]
+/// 包装：文件只读，每次读之前先把文件系统缓存的脏块写回设备，读到的才是最新的内容。
+pub struct BlockFile {
+    device: Arc<dyn BlockDevice>,
+    /// 设备的块数
+    blocks: usize,
+    /// 把设备上文件系统的块缓存写回设备，没有挂载文件系统时为 `None`
+    flush: Option<fn()>,
+}
+
+impl BlockFile {
+    /// 包装有 `blocks` 块、没有挂载文件系统的设备，可以读写
+    pub fn new(device: Arc<dyn BlockDevice>, blocks: usize) -> Self {
+        Self {
+            device,
+            blocks,
+            flush: None,
+        }
+    }
+
+    /// 包装有 `blocks` 块、挂载了文件系统的设备，只读
+    ///
+    /// 每次读之前调用 `flush` 把文件系统的块缓存写回设备。
+    pub fn mounted(device: Arc<dyn BlockDevice>, blocks: usize, flush: fn()) -> Self {
+        Self {
+            device,
+            blocks,
+            flush: Some(flush),
+        }
+    }
+}
+
+impl Device for BlockFile {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if let Some(flush) = self.flush {
+            flush();
+        }
+        let end = self.size().min(offset.saturating_add(buf.len()));
+        let mut block = [0u8; BLOCK_SZ];
+        let mut pos = offset;
+        while pos < end {
+            let start = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - start).min(end - pos);
+            self.device.read_block(pos / BLOCK_SZ, &mut block);
+            buf[pos - offset..][..len].copy_from_slice(&block[start..start + len]);
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+
+    /// 挂载了文件系统时不写入，以免和块缓存中的脏块互相覆盖
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        if self.flush.is_some() {
+            return 0;
+        }
+        let end = self.size().min(offset.saturating_add(buf.len()));
+        let mut block = [0u8; BLOCK_SZ];
+        let mut pos = offset;
+        while pos < end {
+            let start = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - start).min(end - pos);
+            // 只写一部分时先读出整块
+            if len < BLOCK_SZ {
+                self.device.read_block(pos / BLOCK_SZ, &mut block);
+            }
+            block[start..start + len].copy_from_slice(&buf[pos - offset..][..len]);
+            self.device.write_block(pos / BLOCK_SZ, &block);
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+
+    fn file_type(&self) -> FileType {
+        FileType::BlockDevice
+    }
+
+    fn mode(&self) -> u32 {
+        if self.flush.is_some() {
+            0o440
+        } else {
+            0o660
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.blocks * BLOCK_SZ
+    }
+}
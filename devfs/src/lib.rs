@@ -0,0 +1,298 @@
+//! 设备文件系统
+//!
+//! 把设备表示成文件，挂载到 `/dev` 后用户程序可以像普通文件一样打开和读写设备。
+//! 自带 `null` 和 `zero`，控制台、块设备等依赖硬件的设备由内核通过 [`DevFs::add`] 加入。
+//! 设备不能在用户程序中创建或删除。
+
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+extern crate alloc;
+
+mod block;
+mod misc;
+
+pub use block::BlockFile;
+pub use misc::{Null, Random, Zero};
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+use rcore_vfs::{FileSystem, FileType, Inode, Metadata};
+use spin::Mutex;
+
+/// 一个设备
+///
+/// 读写位置由打开的文件记录，字符设备没有位置，忽略 `offset`。
+pub trait Device: Send + Sync {
+    /// 从 `offset` 处读出数据，返回读到的字节数
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// 从 `offset` 处写入数据，返回写入的字节数
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+
+    /// 设备类型，[`FileType::CharDevice`] 或 [`FileType::BlockDevice`]
+    fn file_type(&self) -> FileType {
+        FileType::CharDevice
+    }
+
+    /// 权限位
+    fn mode(&self) -> u32 {
+        0o666
+    }
+
+    /// 字节数，字符设备为 0
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+/// 设备文件系统，只有一层目录
+pub struct DevFs {
+    root: Arc<DevDir>,
+}
+
+impl DevFs {
+    /// 创建只有 `null` 和 `zero` 的设备文件系统
+    pub fn new() -> Self {
+        let fs = Self {
+            root: Arc::new(DevDir {
+                devices: Mutex::new(BTreeMap::new()),
+                // 1 号是根目录
+                next_ino: AtomicU64::new(2),
+            }),
+        };
+        fs.add("null", Arc::new(Null));
+        fs.add("zero", Arc::new(Zero));
+        fs
+    }
+
+    /// 以 `name` 加入设备，名字已被占用时返回 `false`
+    pub fn add(&self, name: &str, device: Arc<dyn Device>) -> bool {
+        let mut devices = self.root.devices.lock();
+        if name.is_empty() || name.contains('/') || devices.contains_key(name) {
+            return false;
+        }
+        let ino = self.root.next_ino.fetch_add(1, Ordering::Relaxed);
+        devices.insert(String::from(name), Arc::new(DevInode { ino, device }));
+        true
+    }
+}
+
+impl Default for DevFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for DevFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sync(&self) {}
+}
+
+/// 根目录
+struct DevDir {
+    devices: Mutex<BTreeMap<String, Arc<DevInode>>>,
+    next_ino: AtomicU64,
+}
+
+impl Inode for DevDir {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: 1,
+            type_: FileType::Directory,
+            nlink: 2,
+            size: self.devices.lock().len() as u64,
+            mode: 0o755,
+            atime: 0,
+            mtime: 0,
+            crtime: 0,
+        }
+    }
+
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.devices
+            .lock()
+            .get(name)
+            .map(|inode| inode.clone() as Arc<dyn Inode>)
+    }
+
+    fn readdir(&self) -> Option<Vec<String>> {
+        let devices = self.devices.lock();
+        let mut names = Vec::with_capacity(devices.len() + 2);
+        names.push(String::from("."));
+        names.push(String::from(".."));
+        names.extend(devices.keys().cloned());
+        Some(names)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// 设备文件
+struct DevInode {
+    ino: u64,
+    device: Arc<dyn Device>,
+}
+
+impl Inode for DevInode {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            ino: self.ino,
+            type_: self.device.file_type(),
+            nlink: 1,
+            size: self.device.size() as u64,
+            mode: self.device.mode(),
+            atime: 0,
+            mtime: 0,
+            crtime: 0,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        self.device.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        self.device.write_at(offset, buf)
+    }
+
+    /// 设备的长度不能改变，打开时清空文件的选项被忽略
+    fn truncate(&self, _len: usize) -> bool {
+        false
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicUsize;
+    use easy_fs::{BlockDevice, BLOCK_SZ};
+
+    /// 内存中的块设备
+    struct RamDisk(Mutex<Vec<[u8; BLOCK_SZ]>>);
+
+    impl RamDisk {
+        fn new(blocks: usize) -> Arc<Self> {
+            Arc::new(Self(Mutex::new(alloc::vec![[0; BLOCK_SZ]; blocks])))
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock()[block_id]);
+        }
+
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock()[block_id].copy_from_slice(buf);
+        }
+    }
+
+    #[test]
+    fn null_and_zero() {
+        let mut buf = [1u8; 8];
+        assert_eq!(Null.read_at(0, &mut buf), 0);
+        assert_eq!(buf, [1; 8]);
+        assert_eq!(Null.write_at(0, &buf), 8);
+        assert_eq!(Zero.read_at(100, &mut buf), 8);
+        assert_eq!(buf, [0; 8]);
+        assert_eq!(Zero.write_at(0, &[1; 3]), 3);
+    }
+
+    #[test]
+    fn random_depends_on_seed_and_writes() {
+        let read = |random: &Random| {
+            let mut buf = [0u8; 13];
+            assert_eq!(random.read_at(0, &mut buf), 13);
+            buf
+        };
+        let (a, b) = (Random::new(42), Random::new(42));
+        let first = read(&a);
+        assert_eq!(first, read(&b));
+        assert_ne!(first, read(&a));
+        // 写入的数据混入状态后两个设备不再同步
+        assert_eq!(a.write_at(0, b"entropy"), 7);
+        assert_ne!(read(&a), read(&b));
+        // 种子为 0 时状态也不为 0
+        assert_ne!(read(&Random::new(0)), [0; 13]);
+    }
+
+    #[test]
+    fn block_file_reads_and_writes_across_blocks() {
+        let disk = RamDisk::new(4);
+        let file = BlockFile::new(disk.clone(), 4);
+        assert_eq!(file.size(), 4 * BLOCK_SZ);
+        assert_eq!(file.mode(), 0o660);
+        let data: Vec<u8> = (0..700).map(|i| i as u8).collect();
+        assert_eq!(file.write_at(300, &data), 700);
+        // 没有写到的部分保持不变
+        assert_eq!(disk.0.lock()[0][299], 0);
+        assert_eq!(disk.0.lock()[1][0], data[BLOCK_SZ - 300]);
+        let mut buf = alloc::vec![0u8; 700];
+        assert_eq!(file.read_at(300, &mut buf), 700);
+        assert_eq!(buf, data);
+        // 超出设备末尾的部分被截掉
+        assert_eq!(file.write_at(4 * BLOCK_SZ - 10, &[7; 20]), 10);
+        assert_eq!(file.read_at(4 * BLOCK_SZ - 4, &mut buf), 4);
+        assert_eq!(buf[..4], [7; 4]);
+        assert_eq!(file.read_at(4 * BLOCK_SZ, &mut buf), 0);
+        assert_eq!(file.read_at(usize::MAX, &mut buf), 0);
+    }
+
+    static FLUSHES: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn mounted_block_file_is_read_only_and_flushes_before_reads() {
+        let disk = RamDisk::new(2);
+        disk.0.lock()[1][0] = 9;
+        let file = BlockFile::mounted(disk.clone(), 2, || {
+            FLUSHES.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(file.mode(), 0o440);
+        assert_eq!(file.write_at(BLOCK_SZ, &[1; 4]), 0);
+        assert_eq!(disk.0.lock()[1][0], 9);
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read_at(BLOCK_SZ, &mut buf), 4);
+        assert_eq!(buf, [9, 0, 0, 0]);
+        assert_eq!(FLUSHES.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn devices_in_root_directory() {
+        let fs = DevFs::new();
+        let disk = RamDisk::new(1);
+        assert!(fs.add("vda", Arc::new(BlockFile::new(disk, 1))));
+        assert!(!fs.add("vda", Arc::new(Null)));
+        assert!(!fs.add("a/b", Arc::new(Null)));
+        assert!(!fs.add("", Arc::new(Null)));
+        let root = fs.root_inode();
+        assert_eq!(root.readdir().unwrap(), [".", "..", "null", "vda", "zero"]);
+        let null = root.find("null").unwrap().metadata();
+        assert_eq!(null.type_, FileType::CharDevice);
+        let vda = root.find("vda").unwrap();
+        let stat = vda.metadata();
+        assert_eq!(stat.type_, FileType::BlockDevice);
+        assert_eq!(stat.size, BLOCK_SZ as u64);
+        assert_ne!(stat.ino, null.ino);
+        // 设备的长度不能改变
+        assert!(!vda.truncate(0));
+        assert!(root.find("missing").is_none());
+    }
+}
@@ -0,0 +1,71 @@
+use crate::Device;
+use spin::Mutex;
+
+/// 空设备，读到文件尾，写入的数据被丢弃
+pub struct Null;
+
+impl Device for Null {
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        buf.len()
+    }
+}
+
+/// 零设备，读出的都是 0，写入的数据被丢弃
+pub struct Zero;
+
+impl Device for Zero {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+        buf.fill(0);
+        buf.len()
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        buf.len()
+    }
+}
+
+/// 伪随机数设备，用 xorshift64* 生成，不能用于密码学
+///
+/// 写入的数据会混入状态。
+pub struct Random {
+    state: Mutex<u64>,
+}
+
+impl Random {
+    /// 以 `seed` 为种子创建，如开机以来的时钟周期数
+    pub fn new(seed: u64) -> Self {
+        // 状态不能为 0
+        Self {
+            state: Mutex::new(seed | 1),
+        }
+    }
+}
+
+impl Device for Random {
+    fn read_at(&self, _offset: usize, buf: &mut [u8]) -> usize {
+        let mut state = self.state.lock();
+        for chunk in buf.chunks_mut(8) {
+            *state ^= *state >> 12;
+            *state ^= *state << 25;
+            *state ^= *state >> 27;
+            let value = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+            chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+        }
+        buf.len()
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> usize {
+        let mut state = self.state.lock();
+        for byte in buf {
+            *state = state.rotate_left(8) ^ *byte as u64;
+        }
+        if *state == 0 {
+            *state = 1;
+        }
+        buf.len()
+    }
+}
@@ -8,6 +8,8 @@ const BLOCK_BITS: usize = BLOCK_SZ * 8;
 pub struct Bitmap {
     start_block_id: usize,
     blocks: usize,
+    /// Number of bits in use, the rest of the last block is never allocated
+    bits: usize,
 }
 
 /// Decompose bits into (block_pos, bits64_pos, inner_pos)
@@ -18,37 +20,39 @@ fn decomposition(mut bit: usize) -> (usize, usize, usize) {
 }
 
 impl Bitmap {
-    /// A new bitmap from start block id and number of blocks
-    pub fn new(start_block_id: usize, blocks: usize) -> Self {
+    /// A new bitmap from start block id, number of blocks and number of bits in use
+    pub fn new(start_block_id: usize, blocks: usize, bits: usize) -> Self {
+        assert!(bits <= blocks * BLOCK_BITS);
         Self {
             start_block_id,
             blocks,
+            bits,
         }
     }
     /// Allocate a new block from a block device
     pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
         for block_id in 0..self.blocks {
-            let pos = get_block_cache(
-                block_id + self.start_block_id as usize,
-                Arc::clone(block_device),
-            )
-            .lock()
-            .modify(0, |bitmap_block: &mut BitmapBlock| {
-                if let Some((bits64_pos, inner_pos)) = bitmap_block
+            let block_cache =
+                get_block_cache(block_id + self.start_block_id, Arc::clone(block_device));
+            let mut block_cache = block_cache.lock();
+            let pos = block_cache.read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block
                     .iter()
                     .enumerate()
                     .find(|(_, bits64)| **bits64 != u64::MAX)
                     .map(|(bits64_pos, bits64)| (bits64_pos, bits64.trailing_ones() as usize))
-                {
-                    // modify cache
-                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
-                    Some(block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos as usize)
-                } else {
-                    None
-                }
             });
-            if pos.is_some() {
-                return pos;
+            // only modify the block with a free bit, so that full blocks stay clean
+            if let Some((bits64_pos, inner_pos)) = pos {
+                let bit = block_id * BLOCK_BITS + bits64_pos * 64 + inner_pos;
+                // the first free bit is out of use, so every bit in use is taken
+                if bit >= self.bits {
+                    return None;
+                }
+                block_cache.modify(0, |bitmap_block: &mut BitmapBlock| {
+                    bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+                });
+                return Some(bit);
             }
         }
         None
@@ -63,8 +67,30 @@ impl Bitmap {
                 bitmap_block[bits64_pos] -= 1u64 << inner_pos;
             });
     }
+    /// Whether a bit is allocated
+    pub fn test(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) -> bool {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |bitmap_block: &BitmapBlock| {
+                bitmap_block[bits64_pos] & (1u64 << inner_pos) > 0
+            })
+    }
+    /// Mark a bit as allocated
+    pub fn set(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block_pos, bits64_pos, inner_pos) = decomposition(bit);
+        get_block_cache(block_pos + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                bitmap_block[bits64_pos] |= 1u64 << inner_pos;
+            });
+    }
+    /// Get the number of blocks of the bitmap
+    pub fn blocks(&self) -> usize {
+        self.blocks
+    }
     /// Get the max number of allocatable blocks
     pub fn maximum(&self) -> usize {
-        self.blocks * BLOCK_BITS
+        self.bits
     }
 }
@@ -1,5 +1,10 @@
 use super::{BlockDevice, BLOCK_SZ};
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
 use spin::{Lazy, Mutex};
 
 /// Cached block inside memory
@@ -26,6 +31,14 @@ impl BlockCache {
             modified: false,
         }
     }
+    /// Get the underlying block id
+    pub fn block_id(&self) -> usize {
+        self.block_id
+    }
+    /// Get the cached block data
+    pub fn data(&self) -> &[u8; BLOCK_SZ] {
+        &self.cache
+    }
     /// Get the address of an offset inside the cached block data
     fn addr_of_offset(&self, offset: usize) -> usize {
         &self.cache[offset] as *const _ as usize
@@ -73,18 +86,241 @@ impl Drop for BlockCache {
         self.sync()
     }
 }
-/// Use a block cache of 16 blocks
+/// Use a block cache of 16 blocks by default
 const BLOCK_CACHE_SIZE: usize = 16;
 
+/// Key of a cached block: (address of the block device, block id)
+pub type BlockKey = (usize, usize);
+
+/// Replacement policy of the block cache
+pub trait ReplacePolicy: Send {
+    /// A block has been loaded into the cache
+    fn insert(&mut self, key: BlockKey);
+    /// A cached block is accessed again
+    fn access(&mut self, key: BlockKey);
+    /// Choose a victim among blocks that `evictable` accepts and forget it.
+    /// Return `None` if no block can be evicted.
+    fn evict(&mut self, evictable: &mut dyn FnMut(BlockKey) -> bool) -> Option<BlockKey>;
+}
+
+/// Evict the block loaded earliest.
+///
+/// Blocks that cannot be evicted are moved to the back of the queue,
+/// so eviction takes O(1) unless the oldest blocks are in use or dirty.
+#[derive(Default)]
+pub struct FifoPolicy {
+    queue: VecDeque<BlockKey>,
+}
+
+impl ReplacePolicy for FifoPolicy {
+    fn insert(&mut self, key: BlockKey) {
+        self.queue.push_back(key);
+    }
+
+    fn access(&mut self, _key: BlockKey) {}
+
+    fn evict(&mut self, evictable: &mut dyn FnMut(BlockKey) -> bool) -> Option<BlockKey> {
+        for _ in 0..self.queue.len() {
+            let key = self.queue.pop_front()?;
+            if evictable(key) {
+                return Some(key);
+            }
+            self.queue.push_back(key);
+        }
+        None
+    }
+}
+
+/// Evict the block accessed least recently
+#[derive(Default)]
+pub struct LruPolicy {
+    clock: u64,
+    stamps: BTreeMap<BlockKey, u64>,
+    order: BTreeMap<u64, BlockKey>,
+}
+
+impl ReplacePolicy for LruPolicy {
+    fn insert(&mut self, key: BlockKey) {
+        self.access(key);
+    }
+
+    fn access(&mut self, key: BlockKey) {
+        self.clock += 1;
+        if let Some(stamp) = self.stamps.insert(key, self.clock) {
+            self.order.remove(&stamp);
+        }
+        self.order.insert(self.clock, key);
+    }
+
+    fn evict(&mut self, evictable: &mut dyn FnMut(BlockKey) -> bool) -> Option<BlockKey> {
+        let (stamp, key) = self
+            .order
+            .iter()
+            .map(|(stamp, key)| (*stamp, *key))
+            .find(|(_, key)| evictable(*key))?;
+        self.order.remove(&stamp);
+        self.stamps.remove(&key);
+        Some(key)
+    }
+}
+
+/// Second-chance approximation of LRU
+#[derive(Default)]
+pub struct ClockPolicy {
+    hand: usize,
+    /// (key, referenced)
+    entries: Vec<(BlockKey, bool)>,
+    index: BTreeMap<BlockKey, usize>,
+}
+
+impl ReplacePolicy for ClockPolicy {
+    fn insert(&mut self, key: BlockKey) {
+        self.index.insert(key, self.entries.len());
+        self.entries.push((key, true));
+    }
+
+    fn access(&mut self, key: BlockKey) {
+        if let Some(&idx) = self.index.get(&key) {
+            self.entries[idx].1 = true;
+        }
+    }
+
+    fn evict(&mut self, evictable: &mut dyn FnMut(BlockKey) -> bool) -> Option<BlockKey> {
+        // every entry is visited at most twice: once to clear its bit, once to check it
+        for _ in 0..self.entries.len() * 2 {
+            if self.hand >= self.entries.len() {
+                self.hand = 0;
+            }
+            let (key, referenced) = &mut self.entries[self.hand];
+            if *referenced {
+                *referenced = false;
+            } else if evictable(*key) {
+                let key = *key;
+                self.index.remove(&key);
+                self.entries.swap_remove(self.hand);
+                if let Some((moved, _)) = self.entries.get(self.hand) {
+                    self.index.insert(*moved, self.hand);
+                }
+                return Some(key);
+            }
+            self.hand += 1;
+        }
+        None
+    }
+}
+
+/// Configuration of the block cache of a device
+pub struct CacheConfig {
+    /// Max number of cached blocks, besides dirty blocks waiting for the journal
+    /// and blocks in use
+    pub capacity: usize,
+    /// Replacement policy
+    pub policy: Box<dyn ReplacePolicy>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: BLOCK_CACHE_SIZE,
+            policy: Box::new(FifoPolicy::default()),
+        }
+    }
+}
+
+/// Cached blocks of a block device.
+///
+/// It holds at most `capacity` blocks, plus dirty blocks kept for the journal,
+/// which operations keep within the capacity of the journal, plus blocks in use.
+/// When no block can be evicted, a block is loaded beyond the capacity rather than failing,
+/// and later loads evict blocks until the cache is back within the capacity.
+struct DeviceCache {
+    capacity: usize,
+    /// Cached blocks by block id, looked up in O(log n)
+    map: BTreeMap<usize, Arc<Mutex<BlockCache>>>,
+    policy: Box<dyn ReplacePolicy>,
+    /// Max number of dirty blocks kept until the journal commits them, 0 without a journal
+    journaled: usize,
+}
+
+impl DeviceCache {
+    fn new(config: CacheConfig) -> Self {
+        Self {
+            capacity: config.capacity.max(1),
+            map: BTreeMap::new(),
+            policy: config.policy,
+            journaled: 0,
+        }
+    }
+
+    /// Evict blocks not in use until there is room for one more block
+    fn shrink(&mut self) {
+        let map = &self.map;
+        let journaled = self.journaled > 0;
+        let mut evictable = |(_, block_id): BlockKey| {
+            map.get(&block_id)
+                .is_none_or(|c| Arc::strong_count(c) == 1 && !(journaled && c.lock().modified))
+        };
+        let mut victims = Vec::new();
+        while map.len() - victims.len() >= self.capacity {
+            match self.policy.evict(&mut evictable) {
+                Some((_, block_id)) => victims.push(block_id),
+                None => break,
+            }
+        }
+        // write back dirty blocks when dropped
+        for block_id in victims {
+            self.map.remove(&block_id);
+        }
+    }
+}
+
+/// Block caches of all block devices, each configured on its own
 pub struct BlockCacheManager {
-    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    devices: BTreeMap<usize, DeviceCache>,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
         Self {
-            queue: VecDeque::new(),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /// The cache of `block_device`, configured by default if it is new
+    fn device(&mut self, block_device: &Arc<dyn BlockDevice>) -> &mut DeviceCache {
+        self.devices
+            .entry(device_key(block_device))
+            .or_insert_with(|| DeviceCache::new(CacheConfig::default()))
+    }
+
+    /// Keep at most `blocks` dirty blocks of `block_device` in memory
+    /// until the journal commits them
+    pub fn set_journaled(&mut self, block_device: &Arc<dyn BlockDevice>, blocks: usize) {
+        self.device(block_device).journaled = blocks;
+    }
+
+    /// Write back dirty blocks of `block_device` when they are evicted
+    pub fn unset_journaled(&mut self, block_device: &Arc<dyn BlockDevice>) {
+        self.device(block_device).journaled = 0;
+    }
+
+    /// Replace the capacity and the policy of the cache of `block_device`,
+    /// blocks already cached are kept
+    pub fn configure(&mut self, block_device: &Arc<dyn BlockDevice>, config: CacheConfig) {
+        let device = device_key(block_device);
+        let cache = self.device(block_device);
+        cache.capacity = config.capacity.max(1);
+        cache.policy = config.policy;
+        for block_id in cache.map.keys() {
+            cache.policy.insert((device, *block_id));
         }
+        cache.shrink();
+    }
+
+    /// Write back and forget all cached blocks of `block_device`,
+    /// as its address may be reused by another device
+    pub fn remove(&mut self, block_device: &Arc<dyn BlockDevice>) {
+        self.devices.remove(&device_key(block_device));
     }
 
     pub fn get_block_cache(
@@ -92,34 +328,28 @@ impl BlockCacheManager {
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            // substitute
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
-            }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
+        let key = (device_key(&block_device), block_id);
+        let cache = self.device(&block_device);
+        if let Some(block) = cache.map.get(&block_id) {
+            cache.policy.access(key);
+            return Arc::clone(block);
         }
+        // substitute, or exceed the capacity for a while
+        // if every cached block is in use or kept for the journal
+        cache.shrink();
+        // load block into mem
+        let block = Arc::new(Mutex::new(BlockCache::new(block_id, block_device)));
+        cache.map.insert(block_id, Arc::clone(&block));
+        cache.policy.insert(key);
+        block
     }
 }
 
+/// Identify a block device by its address
+fn device_key(block_device: &Arc<dyn BlockDevice>) -> usize {
+    Arc::as_ptr(block_device) as *const () as usize
+}
+
 /// The global block cache manager
 pub static BLOCK_CACHE_MANAGER: Lazy<Mutex<BlockCacheManager>> =
     Lazy::new(|| Mutex::new(BlockCacheManager::new()));
@@ -136,7 +366,148 @@ pub fn get_block_cache(
 /// Sync all block cache to block device
 pub fn block_cache_sync_all() {
     let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
+    for cache in manager
+        .devices
+        .values()
+        .flat_map(|device| device.map.values())
+    {
         cache.lock().sync();
     }
 }
+/// Sync all block cache of `block_device` to it
+pub fn block_cache_sync_device(block_device: &Arc<dyn BlockDevice>) {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    if let Some(device) = manager.devices.get(&device_key(block_device)) {
+        for cache in device.map.values() {
+            cache.lock().sync();
+        }
+    }
+}
+/// Get all dirty block caches of `block_device`
+pub fn dirty_block_caches(block_device: &Arc<dyn BlockDevice>) -> Vec<Arc<Mutex<BlockCache>>> {
+    let caches: Vec<_> = match BLOCK_CACHE_MANAGER
+        .lock()
+        .devices
+        .get(&device_key(block_device))
+    {
+        Some(device) => device.map.values().cloned().collect(),
+        None => Vec::new(),
+    };
+    // do not hold the manager while locking caches
+    caches
+        .into_iter()
+        .filter(|cache| cache.lock().modified)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ramdisk::RamDisk;
+
+    fn cached_blocks(block_device: &Arc<dyn BlockDevice>) -> usize {
+        BLOCK_CACHE_MANAGER.lock().device(block_device).map.len()
+    }
+
+    #[test]
+    fn devices_are_configured_respectively() {
+        let small: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        let large: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        BLOCK_CACHE_MANAGER.lock().configure(
+            &small,
+            CacheConfig {
+                capacity: 4,
+                policy: Box::new(FifoPolicy::default()),
+            },
+        );
+        for block_id in 0..32 {
+            get_block_cache(block_id, Arc::clone(&small));
+            get_block_cache(block_id, Arc::clone(&large));
+        }
+        assert_eq!(cached_blocks(&small), 4);
+        assert_eq!(cached_blocks(&large), BLOCK_CACHE_SIZE);
+        BLOCK_CACHE_MANAGER.lock().remove(&small);
+        BLOCK_CACHE_MANAGER.lock().remove(&large);
+    }
+
+    #[test]
+    fn fifo_skips_blocks_in_use() {
+        let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        BLOCK_CACHE_MANAGER.lock().configure(
+            &device,
+            CacheConfig {
+                capacity: 2,
+                policy: Box::new(FifoPolicy::default()),
+            },
+        );
+        let first = get_block_cache(0, Arc::clone(&device));
+        for block_id in 1..8 {
+            get_block_cache(block_id, Arc::clone(&device));
+        }
+        // the oldest block is in use, the others are evicted in turn
+        let manager = BLOCK_CACHE_MANAGER.lock();
+        let map = &manager.devices[&device_key(&device)].map;
+        assert_eq!(map.keys().copied().collect::<Vec<_>>(), [0, 7]);
+        assert!(Arc::ptr_eq(&map[&0], &first));
+        drop(manager);
+        BLOCK_CACHE_MANAGER.lock().remove(&device);
+    }
+
+    #[test]
+    fn dirty_blocks_wait_for_the_journal() {
+        let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        let mut manager = BLOCK_CACHE_MANAGER.lock();
+        manager.configure(
+            &device,
+            CacheConfig {
+                capacity: 2,
+                policy: Box::new(FifoPolicy::default()),
+            },
+        );
+        manager.set_journaled(&device, 8);
+        drop(manager);
+        for block_id in 0..8 {
+            get_block_cache(block_id, Arc::clone(&device))
+                .lock()
+                .modify(0, |byte: &mut u8| *byte = 1);
+        }
+        // the cache grows beyond its capacity rather than writing blocks in place
+        assert_eq!(dirty_block_caches(&device).len(), 8);
+        let mut buf = [0; BLOCK_SZ];
+        device.read_block(0, &mut buf);
+        assert_eq!(buf[0], 0);
+        BLOCK_CACHE_MANAGER.lock().remove(&device);
+        device.read_block(0, &mut buf);
+        assert_eq!(buf[0], 1);
+    }
+
+    #[test]
+    fn pinned_blocks_exceed_the_capacity_for_a_while() {
+        let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(64));
+        BLOCK_CACHE_MANAGER.lock().configure(
+            &device,
+            CacheConfig {
+                capacity: 2,
+                policy: Box::new(LruPolicy::default()),
+            },
+        );
+        // every cached block is in use, dirty blocks are written back when evicted
+        let pinned: Vec<_> = (0..32)
+            .map(|block_id| {
+                let block = get_block_cache(block_id, Arc::clone(&device));
+                block
+                    .lock()
+                    .modify(0, |byte: &mut u8| *byte = block_id as u8 + 1);
+                block
+            })
+            .collect();
+        assert_eq!(cached_blocks(&device), 32);
+        drop(pinned);
+        get_block_cache(32, Arc::clone(&device));
+        assert_eq!(cached_blocks(&device), 2);
+        let mut buf = [0; BLOCK_SZ];
+        device.read_block(0, &mut buf);
+        assert_eq!(buf[0], 1);
+        BLOCK_CACHE_MANAGER.lock().remove(&device);
+    }
+}
@@ -0,0 +1,132 @@
+use super::{BlockDevice, DiskInode, BLOCK_SZ};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// The max number of directories indexed in memory at the same time
+const DIR_INDEX_LIMIT: usize = 32;
+
+/// Where a directory entry is
+#[derive(Clone, Copy, Debug)]
+pub struct DirSlot {
+    /// Index of the block in the directory
+    pub block: u32,
+    /// Offset of the entry in the block
+    pub offset: usize,
+    /// Inode number in the entry
+    pub inode_number: u32,
+}
+
+/// In-memory index of a directory, so that lookups do not scan the entries
+pub struct DirIndex {
+    /// Entries sorted by name
+    entries: BTreeMap<String, DirSlot>,
+    /// Size of the largest entry each block can hold
+    free: Vec<u16>,
+    /// Tick of the last use, the least recently used index is evicted first
+    last_used: u64,
+}
+
+impl DirIndex {
+    /// Build the index by scanning all blocks of a directory
+    fn build(disk_inode: &DiskInode, block_device: &Arc<dyn BlockDevice>) -> Self {
+        let mut entries = BTreeMap::new();
+        let mut free = Vec::new();
+        for block in 0..disk_inode.size / BLOCK_SZ as u32 {
+            disk_inode.read_dir_block(block, block_device, |dir_block| {
+                for (offset, entry, name) in dir_block.entries() {
+                    if !entry.is_empty() {
+                        let slot = DirSlot {
+                            block,
+                            offset,
+                            inode_number: entry.inode_number(),
+                        };
+                        entries.insert(String::from(name), slot);
+                    }
+                }
+                free.push(dir_block.max_free() as u16);
+            });
+        }
+        Self {
+            entries,
+            free,
+            last_used: 0,
+        }
+    }
+    /// Find an entry by name
+    pub fn find(&self, name: &str) -> Option<DirSlot> {
+        self.entries.get(name).copied()
+    }
+    /// The first block that can hold an entry of `size` bytes
+    pub fn block_for(&self, size: usize) -> Option<u32> {
+        self.free
+            .iter()
+            .position(|free| *free as usize >= size)
+            .map(|block| block as u32)
+    }
+    /// Record an entry added to the directory
+    pub fn insert(&mut self, name: &str, slot: DirSlot) {
+        self.entries.insert(String::from(name), slot);
+    }
+    /// Record an entry removed from the directory
+    pub fn remove(&mut self, name: &str) {
+        self.entries.remove(name);
+    }
+    /// Record the size of the largest entry `block` can hold, the directory may grow by a block
+    pub fn set_free(&mut self, block: u32, free: usize) {
+        let block = block as usize;
+        if block == self.free.len() {
+            self.free.push(0);
+        }
+        self.free[block] = free as u16;
+    }
+}
+
+/// Indexes of recently used directories on a filesystem
+pub struct DirIndexes {
+    indexes: BTreeMap<u32, DirIndex>,
+    tick: u64,
+}
+
+impl DirIndexes {
+    /// Create an empty set of indexes
+    pub fn new() -> Self {
+        Self {
+            indexes: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+    /// Get the index of directory `inode_id`, build it from `disk_inode` if it is not cached
+    pub fn get(
+        &mut self,
+        inode_id: u32,
+        disk_inode: &DiskInode,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> &mut DirIndex {
+        self.tick += 1;
+        if !self.indexes.contains_key(&inode_id) && self.indexes.len() >= DIR_INDEX_LIMIT {
+            let lru = self
+                .indexes
+                .iter()
+                .min_by_key(|(_, index)| index.last_used)
+                .map(|(inode_id, _)| *inode_id)
+                .unwrap();
+            self.indexes.remove(&lru);
+        }
+        let index = self
+            .indexes
+            .entry(inode_id)
+            .or_insert_with(|| DirIndex::build(disk_inode, block_device));
+        index.last_used = self.tick;
+        index
+    }
+    /// Drop the index of directory `inode_id`
+    pub fn remove(&mut self, inode_id: u32) {
+        self.indexes.remove(&inode_id);
+    }
+    /// Drop all indexes, e.g. after directories are modified on disk directly
+    pub fn clear(&mut self) {
+        self.indexes.clear();
+    }
+}
@@ -1,8 +1,10 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_sync_all, block_cache_sync_device, dirty_block_caches, get_block_cache, Bitmap,
+    BlockDevice, CacheConfig, DirIndexes, DiskInode, DiskInodeType, Inode, Journal, SuperBlock,
+    BLOCK_CACHE_MANAGER,
 };
 use crate::BLOCK_SZ;
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use spin::Mutex;
 ///An easy file system on block
@@ -14,30 +16,140 @@ pub struct EasyFileSystem {
     ///Data bitmap
     pub data_bitmap: Bitmap,
     inode_area_start_block: u32,
-    data_area_start_block: u32,
+    pub(crate) data_area_start_block: u32,
+    journal: Option<Journal>,
+    /// In-memory indexes of directories
+    pub(crate) dir_indexes: DirIndexes,
+    /// Inode number -> number of [`Inode`]s referring to it,
+    /// an unlinked inode is freed once none is left
+    held_inodes: BTreeMap<u32, usize>,
 }
 
 type DataBlock = [u8; BLOCK_SZ];
+/// Default size of the journal region, larger devices get a larger journal, see [`Geometry::new`]
+pub const JOURNAL_BLOCKS: u32 = 64;
+/// The max number of blocks an operation modifies besides data it writes
+/// and data bitmap blocks of blocks it frees
+pub(crate) const OP_BLOCKS: usize = 24;
+/// Clock used to stamp inodes, see [`EasyFileSystem::set_clock`]
+static CLOCK: Mutex<fn() -> u32> = Mutex::new(|| 0);
+
+/// Sizes of the areas of a filesystem in blocks, in the order on the device after the super block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Geometry {
+    /// Blocks of the inode bitmap
+    pub inode_bitmap_blocks: u32,
+    /// Blocks of inodes
+    pub inode_area_blocks: u32,
+    /// Blocks of the data bitmap
+    pub data_bitmap_blocks: u32,
+    /// Blocks of data
+    pub data_area_blocks: u32,
+    /// Blocks of the journal, 0 if there is no journal
+    pub journal_blocks: u32,
+}
+
+impl Geometry {
+    /// Lay out `total_blocks` blocks with `inode_bitmap_blocks` blocks of inode bitmap
+    /// and a journal large enough for the largest operation, at least [`JOURNAL_BLOCKS`] blocks.
+    /// Return `None` if there is no room for data or no journal is large enough.
+    pub fn new(total_blocks: u32, inode_bitmap_blocks: u32) -> Option<Self> {
+        // a larger journal leaves fewer data blocks, so the data bitmap never grows
+        let data_bitmap_blocks =
+            Self::with_journal(total_blocks, inode_bitmap_blocks, 0)?.data_bitmap_blocks;
+        let journal_blocks = JOURNAL_BLOCKS.max(1 + OP_BLOCKS as u32 + data_bitmap_blocks);
+        Self::with_journal(total_blocks, inode_bitmap_blocks, journal_blocks)
+    }
+
+    /// Lay out `total_blocks` blocks with `inode_bitmap_blocks` blocks of inode bitmap
+    /// and a journal of `journal_blocks` blocks, there is no journal if it is 0.
+    /// Return `None` if there is no room for data or the journal cannot hold the largest operation.
+    pub fn with_journal(
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        journal_blocks: u32,
+    ) -> Option<Self> {
+        let inode_num = inode_bitmap_blocks as usize * BLOCK_SZ * 8;
+        let inode_area_blocks =
+            (inode_num * core::mem::size_of::<DiskInode>()).div_ceil(BLOCK_SZ) as u32;
+        let data_total_blocks = total_blocks
+            .checked_sub(1 + inode_bitmap_blocks + inode_area_blocks)?
+            .checked_sub(journal_blocks)?;
+        // a bitmap block covers itself and 4096 data blocks
+        let data_bitmap_blocks = data_total_blocks.div_ceil(4097);
+        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        let geometry = Self {
+            inode_bitmap_blocks,
+            inode_area_blocks,
+            data_bitmap_blocks,
+            data_area_blocks,
+            journal_blocks,
+        };
+        if data_area_blocks == 0 || !geometry.journal_fits() {
+            return None;
+        }
+        Some(geometry)
+    }
+
+    /// Whether the journal can hold the largest operation, which frees blocks all over the data area
+    fn journal_fits(&self) -> bool {
+        self.journal_blocks == 0
+            || (self.journal_blocks >= 2
+                && Journal::capacity_of(self.journal_blocks)
+                    >= OP_BLOCKS + self.data_bitmap_blocks as usize)
+    }
+}
+
 /// An easy fs over a block device
 impl EasyFileSystem {
-    /// A data block of block size
+    /// Create a filesystem with a journal of default size on a block device,
+    /// the journal grows beyond the default size for a large device.
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
         total_blocks: u32,
         inode_bitmap_blocks: u32,
     ) -> Arc<Mutex<Self>> {
-        // calculate block size of areas & create bitmaps
-        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
-        let inode_num = inode_bitmap.maximum();
-        let inode_area_blocks =
-            ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SZ - 1) / BLOCK_SZ) as u32;
+        let geometry =
+            Geometry::new(total_blocks, inode_bitmap_blocks).expect("Bad size of the filesystem!");
+        Self::format(block_device, total_blocks, geometry)
+    }
+    /// Create a filesystem with a journal of `journal_blocks` blocks on a block device.
+    /// There is no journal if `journal_blocks` is 0.
+    pub fn create_with_journal(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        journal_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        let geometry = Geometry::with_journal(total_blocks, inode_bitmap_blocks, journal_blocks)
+            .expect("Bad size of the filesystem or the journal!");
+        Self::format(block_device, total_blocks, geometry)
+    }
+    /// Create a filesystem laid out as `geometry` on a block device
+    fn format(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        geometry: Geometry,
+    ) -> Arc<Mutex<Self>> {
+        let Geometry {
+            inode_bitmap_blocks,
+            inode_area_blocks,
+            data_bitmap_blocks,
+            data_area_blocks,
+            journal_blocks,
+        } = geometry;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
-        let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
-        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        // create bitmaps
+        let inode_bitmap = Bitmap::new(
+            1,
+            inode_bitmap_blocks as usize,
+            inode_bitmap_blocks as usize * BLOCK_SZ * 8,
+        );
         let data_bitmap = Bitmap::new(
             (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
             data_bitmap_blocks as usize,
+            // bits after the data area would refer to the journal
+            data_area_blocks as usize,
         );
         let mut efs = Self {
             block_device: Arc::clone(&block_device),
@@ -45,6 +157,9 @@ impl EasyFileSystem {
             data_bitmap,
             inode_area_start_block: 1 + inode_bitmap_blocks,
             data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            journal: None,
+            dir_indexes: DirIndexes::new(),
+            held_inodes: BTreeMap::new(),
         };
         // clear all blocks
         for i in 0..total_blocks {
@@ -66,6 +181,7 @@ impl EasyFileSystem {
                     inode_area_blocks,
                     data_bitmap_blocks,
                     data_area_blocks,
+                    journal_blocks,
                 );
             },
         );
@@ -76,40 +192,183 @@ impl EasyFileSystem {
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, Self::now());
             });
+        let efs = Arc::new(Mutex::new(efs));
+        // the parent of root directory is itself
+        let root = Self::root_inode(&efs);
+        root.init_dir(0, &mut efs.lock());
         block_cache_sync_all();
-        Arc::new(Mutex::new(efs))
+        // enable the journal once the filesystem is formatted
+        if journal_blocks > 0 {
+            efs.lock().enable_journal(total_blocks, journal_blocks);
+        }
+        efs
     }
     /// Open a block device as a filesystem
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        Self::open_with(block_device, CacheConfig::default())
+    }
+    /// Open a block device as a filesystem, with its block cache configured by `config`.
+    ///
+    /// Each block device has a cache of its own, other filesystems are not affected.
+    pub fn open_with(block_device: Arc<dyn BlockDevice>, config: CacheConfig) -> Arc<Mutex<Self>> {
+        BLOCK_CACHE_MANAGER.lock().configure(&block_device, config);
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
+        let (mut efs, total_blocks, journal_blocks) = get_block_cache(0, Arc::clone(&block_device))
             .lock()
             .read(0, |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
+                assert!(
+                    super_block.is_supported(),
+                    "Unsupported EFS version {}, please rebuild the image!",
+                    super_block.version()
+                );
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
                     block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
+                    inode_bitmap: Bitmap::new(
+                        1,
+                        super_block.inode_bitmap_blocks as usize,
+                        super_block.inode_bitmap_blocks as usize * BLOCK_SZ * 8,
+                    ),
                     data_bitmap: Bitmap::new(
                         (1 + inode_total_blocks) as usize,
                         super_block.data_bitmap_blocks as usize,
+                        super_block.data_area_blocks as usize,
                     ),
                     inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
                     data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    journal: None,
+                    dir_indexes: DirIndexes::new(),
+                    held_inodes: BTreeMap::new(),
                 };
-                Arc::new(Mutex::new(efs))
-            })
+                (efs, super_block.total_blocks, super_block.journal_blocks)
+            });
+        // images made without a journal have 0 journal blocks
+        if journal_blocks > 0 {
+            efs.enable_journal(total_blocks, journal_blocks);
+        }
+        if let Some(journal) = &efs.journal {
+            assert!(
+                journal.capacity() >= OP_BLOCKS + efs.data_bitmap.blocks(),
+                "Journal too small, please rebuild the image!"
+            );
+            journal.replay(&efs.block_device);
+        }
+        Arc::new(Mutex::new(efs))
+    }
+    /// Use the journal region at the end of the device
+    fn enable_journal(&mut self, total_blocks: u32, journal_blocks: u32) {
+        let journal = Journal::new(total_blocks - journal_blocks, journal_blocks);
+        BLOCK_CACHE_MANAGER
+            .lock()
+            .set_journaled(&self.block_device, journal.capacity());
+        self.journal = Some(journal);
+    }
+    /// Reserve room in the running transaction for an operation that modifies
+    /// at most `blocks` blocks, before the operation modifies anything.
+    ///
+    /// The running transaction is committed first if the operation may not fit in it,
+    /// so that an operation is never split between transactions.
+    pub fn begin_op(&self, blocks: usize) {
+        if let Some(journal) = &self.journal {
+            assert!(
+                blocks <= journal.capacity(),
+                "Operation too large for the journal!"
+            );
+            if dirty_block_caches(&self.block_device).len() + blocks > journal.capacity() {
+                journal.commit(&self.block_device);
+            }
+        }
+    }
+    /// Commit operations since the last commit as a transaction.
+    ///
+    /// Without a journal, modifications stay in the block cache until synced.
+    pub fn commit(&self) {
+        if let Some(journal) = &self.journal {
+            journal.commit(&self.block_device);
+        }
+    }
+    /// The max number of blocks an operation may modify to be atomic,
+    /// `None` if there is no journal
+    pub fn journal_capacity(&self) -> Option<usize> {
+        self.journal.as_ref().map(|journal| journal.capacity())
+    }
+    /// The max number of data bitmap blocks modified when allocating or freeing `blocks` blocks
+    pub(crate) fn bitmap_blocks(&self, blocks: usize) -> usize {
+        blocks.min(self.data_bitmap.blocks())
+    }
+    /// Make all operations durable: commit them through the journal,
+    /// or write back all dirty blocks if there is no journal
+    pub fn sync(&self) {
+        match &self.journal {
+            Some(journal) => journal.commit(&self.block_device),
+            None => block_cache_sync_device(&self.block_device),
+        }
+    }
+    /// Make all operations durable and write blocks in place from now on,
+    /// return the journal to be restored by [`Self::resume_journal`]
+    pub(crate) fn suspend_journal(&mut self) -> Option<Journal> {
+        self.sync();
+        let journal = self.journal.take();
+        if journal.is_some() {
+            BLOCK_CACHE_MANAGER
+                .lock()
+                .unset_journaled(&self.block_device);
+        }
+        journal
+    }
+    /// Write back blocks modified in place and use `journal` again
+    pub(crate) fn resume_journal(&mut self, journal: Option<Journal>) {
+        self.sync();
+        if let Some(journal) = &journal {
+            BLOCK_CACHE_MANAGER
+                .lock()
+                .set_journaled(&self.block_device, journal.capacity());
+        }
+        self.journal = journal;
+    }
+    /// Set the clock used to stamp inodes, which returns seconds since the Unix epoch,
+    /// or since boot if there is no real-time clock.
+    ///
+    /// All filesystems share the clock, inodes are stamped with 0 before it is set.
+    pub fn set_clock(clock: fn() -> u32) {
+        *CLOCK.lock() = clock;
+    }
+    /// Current time of the clock
+    pub fn now() -> u32 {
+        (CLOCK.lock())()
     }
     /// Get the root inode of the filesystem
     pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
-        let block_device = Arc::clone(&efs.lock().block_device);
-        // acquire efs lock temporarily
-        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
-        // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(0, efs, &mut efs.lock())
+    }
+    /// Count a new [`Inode`] referring to `inode_id`
+    pub(crate) fn hold_inode(&mut self, inode_id: u32) {
+        *self.held_inodes.entry(inode_id).or_insert(0) += 1;
+    }
+    /// Forget an [`Inode`] referring to `inode_id`, return whether it was the last one
+    pub(crate) fn release_inode(&mut self, inode_id: u32) -> bool {
+        match self.held_inodes.get_mut(&inode_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            _ => {
+                self.held_inodes.remove(&inode_id);
+                true
+            }
+        }
+    }
+    /// Whether an [`Inode`] refers to `inode_id`
+    pub(crate) fn is_held(&self, inode_id: u32) -> bool {
+        self.held_inodes.contains_key(&inode_id)
+    }
+    /// Inode numbers that some [`Inode`] refers to
+    pub(crate) fn held_inodes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.held_inodes.keys().copied()
     }
     /// Get inode by id
     pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
@@ -130,12 +389,18 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
-    /// Allocate a data block
-    pub fn alloc_data(&mut self) -> u32 {
-        self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
+    /// Deallocate an inode
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.dir_indexes.remove(inode_id);
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
     }
-    /// Deallocate a data block
-    pub fn dealloc_data(&mut self, block_id: u32) {
+
+    /// Allocate a data block filled with zero
+    pub fn alloc_data(&mut self) -> u32 {
+        let block_id =
+            self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block;
+        // clear here rather than when freed, so that freeing touches only the bitmap
         get_block_cache(block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(0, |data_block: &mut DataBlock| {
@@ -143,9 +408,21 @@ impl EasyFileSystem {
                     *p = 0;
                 })
             });
+        block_id
+    }
+    /// Deallocate a data block
+    pub fn dealloc_data(&mut self, block_id: u32) {
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize,
         )
     }
 }
+
+impl Drop for EasyFileSystem {
+    fn drop(&mut self) {
+        self.commit();
+        // dirty blocks are written back when dropped
+        BLOCK_CACHE_MANAGER.lock().remove(&self.block_device);
+    }
+}
@@ -0,0 +1,485 @@
+use super::{get_block_cache, DiskInode, EasyFileSystem, SuperBlock, BLOCK_SZ};
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use spin::Mutex;
+
+/// An inconsistency found by [`fsck`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Problem {
+    /// Areas described by the super block do not add up to the device size
+    BadSuperBlock,
+    /// The type of an inode is not a file, directory or symbolic link
+    BadType {
+        /// Inode number
+        inode: u32,
+    },
+    /// A directory entry refers to an inode that is out of range or not allocated
+    DanglingEntry {
+        /// Inode number of the directory
+        dir: u32,
+        /// Name of the entry
+        name: String,
+        /// Inode number in the entry
+        inode: u32,
+    },
+    /// A block referred by an inode is outside the data area
+    BadBlock {
+        /// Inode number
+        inode: u32,
+        /// Block id
+        block: u32,
+    },
+    /// A block is referred by two inodes, or twice by one inode
+    DoubleRef {
+        /// Block id
+        block: u32,
+        /// The inode refers to it first
+        first: u32,
+        /// The inode refers to it again
+        second: u32,
+    },
+    /// The size of an inode is too large, or not a multiple of block size for a directory
+    BadSize {
+        /// Inode number
+        inode: u32,
+        /// Size on disk
+        size: u32,
+    },
+    /// Entries in a block of a directory are malformed
+    BadDirBlock {
+        /// Inode number of the directory
+        dir: u32,
+        /// Index of the block in the directory
+        block: u32,
+    },
+    /// The link count does not match the number of directory entries
+    WrongLinkCount {
+        /// Inode number
+        inode: u32,
+        /// Link count on disk
+        nlink: u32,
+        /// Number of entries referring to the inode
+        actual: u32,
+    },
+    /// An inode is allocated but not reachable from the root
+    LeakedInode(u32),
+    /// A data block is allocated but not referred by any inode
+    LeakedBlock(u32),
+    /// A data block is referred by an inode but not allocated
+    UnallocatedBlock(u32),
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadSuperBlock => write!(f, "super block does not match the device"),
+            Self::BadType { inode } => write!(f, "inode {inode} has a bad type"),
+            Self::DanglingEntry { dir, name, inode } => {
+                write!(
+                    f,
+                    "entry {name:?} in directory {dir} refers to free inode {inode}"
+                )
+            }
+            Self::BadBlock { inode, block } => {
+                write!(
+                    f,
+                    "inode {inode} refers to block {block} outside the data area"
+                )
+            }
+            Self::DoubleRef {
+                block,
+                first,
+                second,
+            } => write!(f, "block {block} is referred by inode {first} and {second}"),
+            Self::BadSize { inode, size } => write!(f, "inode {inode} has a bad size {size}"),
+            Self::BadDirBlock { dir, block } => {
+                write!(f, "block {block} of directory {dir} has bad entries")
+            }
+            Self::WrongLinkCount {
+                inode,
+                nlink,
+                actual,
+            } => write!(
+                f,
+                "inode {inode} has link count {nlink}, should be {actual}"
+            ),
+            Self::LeakedInode(inode) => write!(f, "inode {inode} is allocated but unreachable"),
+            Self::LeakedBlock(block) => write!(f, "block {block} is allocated but unused"),
+            Self::UnallocatedBlock(block) => write!(f, "block {block} is used but not allocated"),
+        }
+    }
+}
+
+/// Check the consistency of a filesystem, return all problems found.
+///
+/// With `repair`, problems are fixed along the way:
+/// dangling entries are removed, malformed directory blocks keep the entries before
+/// the first bad one, files are truncated at the first bad or shared block,
+/// link counts and bitmaps are rewritten to match what is reachable from the root.
+/// A bad super block or a bad inode type is only reported.
+///
+/// Unlinked inodes that an [`Inode`](crate::Inode) still refers to are not leaked.
+///
+/// Repairs are written in place rather than through the journal,
+/// a crash while repairing leaves the rest of the problems to the next check.
+pub fn fsck(efs: &Arc<Mutex<EasyFileSystem>>, repair: bool) -> Vec<Problem> {
+    let mut fs = efs.lock();
+    if !repair {
+        return check(&mut fs, false);
+    }
+    let journal = fs.suspend_journal();
+    let problems = check(&mut fs, true);
+    // directories may have been modified behind their indexes
+    fs.dir_indexes.clear();
+    fs.resume_journal(journal);
+    problems
+}
+
+/// Check the filesystem and fix problems if `repair`, see [`fsck`]
+fn check(fs: &mut EasyFileSystem, repair: bool) -> Vec<Problem> {
+    let block_device = Arc::clone(&fs.block_device);
+    let mut problems = Vec::new();
+    let (geometry_ok, data_area_blocks) = get_block_cache(0, Arc::clone(&block_device))
+        .lock()
+        .read(0, |super_block: &SuperBlock| {
+            let used = 1
+                + super_block.inode_bitmap_blocks
+                + super_block.inode_area_blocks
+                + super_block.data_bitmap_blocks
+                + super_block.data_area_blocks
+                + super_block.journal_blocks;
+            (
+                used == super_block.total_blocks,
+                super_block.data_area_blocks,
+            )
+        });
+    if !geometry_ok {
+        problems.push(Problem::BadSuperBlock);
+        return problems;
+    }
+    let data_start = fs.data_area_start_block;
+    let data_range = data_start..data_start + data_area_blocks;
+    let max_inodes = fs.inode_bitmap.maximum() as u32;
+    // block id -> the inode refers to it
+    let mut owners = BTreeMap::<u32, u32>::new();
+    // inode number -> number of entries referring to it
+    let mut links = BTreeMap::<u32, u32>::new();
+    let mut visited = BTreeSet::new();
+    // unlinked inodes still in use are kept as if they were reachable
+    let mut queue: VecDeque<u32> = core::iter::once(0).chain(fs.held_inodes()).collect();
+    while let Some(inode_id) = queue.pop_front() {
+        if !visited.insert(inode_id) {
+            continue;
+        }
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let disk_inode = get_block_cache(block_id as usize, Arc::clone(&block_device));
+        let mut disk_inode = disk_inode.lock();
+        if !disk_inode.read(block_offset, DiskInode::has_valid_type) {
+            problems.push(Problem::BadType { inode: inode_id });
+            continue;
+        }
+        // size
+        let (size, is_dir) = disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+            (disk_inode.size, disk_inode.is_dir())
+        });
+        let mut valid_size = size.min(DiskInode::MAX_SIZE);
+        if is_dir {
+            valid_size -= valid_size % BLOCK_SZ as u32;
+        }
+        if valid_size != size {
+            problems.push(Problem::BadSize {
+                inode: inode_id,
+                size,
+            });
+            if repair {
+                disk_inode.modify(block_offset, |disk_inode: &mut DiskInode| {
+                    disk_inode.size = valid_size
+                });
+            }
+        }
+        // blocks
+        let valid_blocks = disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+            disk_inode.claim_blocks(&block_device, |block| {
+                if !data_range.contains(&block) {
+                    problems.push(Problem::BadBlock {
+                        inode: inode_id,
+                        block,
+                    });
+                    false
+                } else if let Some(first) = owners.get(&block) {
+                    problems.push(Problem::DoubleRef {
+                        block,
+                        first: *first,
+                        second: inode_id,
+                    });
+                    false
+                } else {
+                    owners.insert(block, inode_id);
+                    true
+                }
+            })
+        });
+        let readable_size = valid_size.min(valid_blocks * BLOCK_SZ as u32);
+        if readable_size < valid_size && repair {
+            // blocks after are left unreferred and freed below
+            disk_inode.modify(block_offset, |disk_inode: &mut DiskInode| {
+                disk_inode.size = readable_size
+            });
+        }
+        if !is_dir {
+            continue;
+        }
+        // entries
+        for block in 0..readable_size / BLOCK_SZ as u32 {
+            let valid = disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+                disk_inode.read_dir_block(block, &block_device, |dir_block| dir_block.is_valid())
+            });
+            if !valid {
+                problems.push(Problem::BadDirBlock {
+                    dir: inode_id,
+                    block,
+                });
+                if repair {
+                    disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+                        disk_inode.modify_dir_block(block, &block_device, |dir_block| {
+                            let entries: Vec<(u32, String)> = dir_block
+                                .entries()
+                                .filter(|(_, dirent, _)| !dirent.is_empty())
+                                .map(|(_, dirent, name)| {
+                                    (dirent.inode_number(), String::from(name))
+                                })
+                                .collect();
+                            dir_block.init();
+                            for (inode_number, name) in entries {
+                                dir_block.insert(&name, inode_number);
+                            }
+                        })
+                    });
+                }
+            }
+            let dirents: Vec<(usize, u32, String)> =
+                disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+                    disk_inode.read_dir_block(block, &block_device, |dir_block| {
+                        dir_block
+                            .entries()
+                            .filter(|(_, dirent, _)| !dirent.is_empty())
+                            .map(|(offset, dirent, name)| {
+                                (offset, dirent.inode_number(), String::from(name))
+                            })
+                            .collect()
+                    })
+                });
+            for (offset, target, name) in dirents {
+                if target >= max_inodes || !fs.inode_bitmap.test(&block_device, target as usize) {
+                    problems.push(Problem::DanglingEntry {
+                        dir: inode_id,
+                        name,
+                        inode: target,
+                    });
+                    if repair {
+                        disk_inode.read(block_offset, |disk_inode: &DiskInode| {
+                            disk_inode.modify_dir_block(block, &block_device, |dir_block| {
+                                dir_block.remove(offset)
+                            })
+                        });
+                    }
+                    continue;
+                }
+                *links.entry(target).or_insert(0) += 1;
+                if name != "." && name != ".." {
+                    queue.push_back(target);
+                }
+            }
+        }
+    }
+    // link counts
+    for inode_id in visited.iter().copied() {
+        let actual = links.get(&inode_id).copied().unwrap_or(0);
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        let disk_inode = get_block_cache(block_id as usize, Arc::clone(&block_device));
+        let mut disk_inode = disk_inode.lock();
+        if !disk_inode.read(block_offset, DiskInode::has_valid_type) {
+            continue;
+        }
+        let nlink = disk_inode.read(block_offset, |disk_inode: &DiskInode| disk_inode.nlink);
+        if nlink != actual {
+            problems.push(Problem::WrongLinkCount {
+                inode: inode_id,
+                nlink,
+                actual,
+            });
+            if repair {
+                disk_inode.modify(block_offset, |disk_inode: &mut DiskInode| {
+                    disk_inode.nlink = actual
+                });
+            }
+        }
+    }
+    // inode bitmap
+    for inode_id in 0..max_inodes {
+        if fs.inode_bitmap.test(&block_device, inode_id as usize) && !visited.contains(&inode_id) {
+            problems.push(Problem::LeakedInode(inode_id));
+            if repair {
+                fs.dealloc_inode(inode_id);
+            }
+        }
+    }
+    // data bitmap
+    for bit in 0..fs.data_bitmap.maximum() {
+        let block = data_start + bit as u32;
+        match (
+            fs.data_bitmap.test(&block_device, bit),
+            owners.contains_key(&block),
+        ) {
+            (true, false) => {
+                problems.push(Problem::LeakedBlock(block));
+                if repair {
+                    fs.data_bitmap.dealloc(&block_device, bit);
+                }
+            }
+            (false, true) => {
+                problems.push(Problem::UnallocatedBlock(block));
+                if repair {
+                    fs.data_bitmap.set(&block_device, bit);
+                }
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ramdisk::RamDisk;
+    use crate::Inode;
+
+    /// A filesystem with a directory `d`, a file `a` of a block and a symbolic link to it
+    fn sample() -> (Arc<Mutex<EasyFileSystem>>, Inode) {
+        let efs = EasyFileSystem::create(Arc::new(RamDisk::new(2048)), 2048, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        root.mkdir("d").unwrap();
+        root.create("a").unwrap().write_at(0, &[1; BLOCK_SZ]);
+        root.symlink("l", "a").unwrap();
+        (efs, root)
+    }
+
+    /// Modify the disk inode of `inode_id` behind the filesystem
+    fn corrupt_inode(
+        efs: &Arc<Mutex<EasyFileSystem>>,
+        inode_id: u32,
+        f: impl FnOnce(&mut DiskInode),
+    ) {
+        let fs = efs.lock();
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&fs.block_device))
+            .lock()
+            .modify(block_offset, f);
+    }
+
+    /// Check that `problems` are reported and repaired, and nothing else is found after
+    fn check_repair(efs: &Arc<Mutex<EasyFileSystem>>, problems: &[Problem]) {
+        assert_eq!(fsck(efs, false), problems);
+        assert_eq!(fsck(efs, true), problems);
+        assert_eq!(fsck(efs, false), []);
+    }
+
+    #[test]
+    fn clean() {
+        let (efs, root) = sample();
+        root.find("d").unwrap().create("b").unwrap();
+        assert_eq!(fsck(&efs, false), []);
+    }
+
+    #[test]
+    fn dangling_entry() {
+        let (efs, root) = sample();
+        let inode = root.create("b").unwrap().inode_id();
+        {
+            let fs = efs.lock();
+            fs.inode_bitmap.dealloc(&fs.block_device, inode as usize);
+        }
+        check_repair(
+            &efs,
+            &[Problem::DanglingEntry {
+                dir: 0,
+                name: String::from("b"),
+                inode,
+            }],
+        );
+        assert!(root.find("b").is_none());
+        assert!(root.find("a").is_some());
+    }
+
+    #[test]
+    fn double_ref() {
+        let (efs, root) = sample();
+        let a = root.find("a").unwrap();
+        let b = root.create("b").unwrap();
+        b.write_at(0, &[2; BLOCK_SZ]);
+        let block = |inode: &Inode| {
+            let fs = efs.lock();
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode.inode_id());
+            get_block_cache(block_id as usize, Arc::clone(&fs.block_device))
+                .lock()
+                .read(block_offset, |disk_inode: &DiskInode| disk_inode.direct[0])
+        };
+        let (shared, leaked) = (block(&a), block(&b));
+        corrupt_inode(&efs, b.inode_id(), |disk_inode| {
+            disk_inode.direct[0] = shared
+        });
+        check_repair(
+            &efs,
+            &[
+                Problem::DoubleRef {
+                    block: shared,
+                    first: a.inode_id(),
+                    second: b.inode_id(),
+                },
+                Problem::LeakedBlock(leaked),
+            ],
+        );
+        // the second owner is truncated before the shared block
+        assert_eq!(b.stat().size, 0);
+        let mut buf = [0; BLOCK_SZ];
+        assert_eq!(a.read_at(0, &mut buf), BLOCK_SZ);
+        assert_eq!(buf, [1; BLOCK_SZ]);
+    }
+
+    #[test]
+    fn wrong_link_count() {
+        let (efs, root) = sample();
+        let a = root.find("a").unwrap();
+        corrupt_inode(&efs, a.inode_id(), |disk_inode| disk_inode.nlink = 5);
+        check_repair(
+            &efs,
+            &[Problem::WrongLinkCount {
+                inode: a.inode_id(),
+                nlink: 5,
+                actual: 1,
+            }],
+        );
+        assert_eq!(a.stat().nlink, 1);
+    }
+
+    #[test]
+    fn leaked_inode_and_block() {
+        let (efs, _root) = sample();
+        let (inode, block) = {
+            let mut fs = efs.lock();
+            (fs.alloc_inode(), fs.alloc_data())
+        };
+        check_repair(
+            &efs,
+            &[Problem::LeakedInode(inode), Problem::LeakedBlock(block)],
+        );
+        let fs = efs.lock();
+        assert!(!fs.inode_bitmap.test(&fs.block_device, inode as usize));
+        let bit = (block - fs.data_area_start_block) as usize;
+        assert!(!fs.data_bitmap.test(&fs.block_device, bit));
+    }
+}
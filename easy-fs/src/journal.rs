@@ -0,0 +1,250 @@
+use super::{dirty_block_caches, get_block_cache, BlockDevice, BLOCK_SZ};
+use alloc::sync::Arc;
+
+/// Magic number of a committed journal header
+const JOURNAL_MAGIC: u32 = 0x6a6f7572;
+/// The max number of blocks recorded by one journal header
+const JOURNAL_HEADER_ENTRIES: usize = BLOCK_SZ / 4 - 2;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+/// The first block of the journal region.
+///
+/// `count` blocks following the header hold the new contents of `blocks`.
+/// The header is only written after all of them, so a valid header means
+/// a complete transaction that can be replayed.
+#[repr(C)]
+struct JournalHeader {
+    magic: u32,
+    count: u32,
+    blocks: [u32; JOURNAL_HEADER_ENTRIES],
+}
+
+impl JournalHeader {
+    fn empty() -> Self {
+        Self {
+            magic: 0,
+            count: 0,
+            blocks: [0; JOURNAL_HEADER_ENTRIES],
+        }
+    }
+    fn as_bytes(&self) -> &DataBlock {
+        unsafe { &*(self as *const _ as *const DataBlock) }
+    }
+    fn as_bytes_mut(&mut self) -> &mut DataBlock {
+        unsafe { &mut *(self as *mut _ as *mut DataBlock) }
+    }
+    fn is_committed(&self) -> bool {
+        self.magic == JOURNAL_MAGIC && self.count as usize <= JOURNAL_HEADER_ENTRIES
+    }
+}
+
+/// A write-ahead journal at the end of the device.
+///
+/// All dirty blocks of the device make up one transaction when committing,
+/// the journal never holds more than one transaction.
+pub struct Journal {
+    start_block: u32,
+    blocks: u32,
+}
+
+impl Journal {
+    /// A journal region of `blocks` blocks from `start_block`
+    pub fn new(start_block: u32, blocks: u32) -> Self {
+        assert!(blocks >= 2, "Journal too small!");
+        Self {
+            start_block,
+            blocks,
+        }
+    }
+
+    /// The max number of blocks in a transaction
+    pub fn capacity(&self) -> usize {
+        Self::capacity_of(self.blocks)
+    }
+
+    /// The max number of blocks in a transaction of a journal region of `blocks` blocks
+    pub fn capacity_of(blocks: u32) -> usize {
+        (blocks as usize)
+            .saturating_sub(1)
+            .min(JOURNAL_HEADER_ENTRIES)
+    }
+
+    fn write_header(&self, header: &JournalHeader, block_device: &Arc<dyn BlockDevice>) {
+        block_device.write_block(self.start_block as usize, header.as_bytes());
+    }
+
+    /// Write all dirty blocks of `block_device` to the journal as a transaction,
+    /// then to their home locations.
+    ///
+    /// Operations reserve room with [`EasyFileSystem::begin_op`](crate::EasyFileSystem::begin_op)
+    /// beforehand, so that the transaction fits in the journal.
+    pub fn commit(&self, block_device: &Arc<dyn BlockDevice>) {
+        let caches = dirty_block_caches(block_device);
+        if caches.is_empty() {
+            return;
+        }
+        assert!(
+            caches.len() <= self.capacity(),
+            "Transaction too large for the journal!"
+        );
+        let mut header = JournalHeader::empty();
+        header.magic = JOURNAL_MAGIC;
+        header.count = caches.len() as u32;
+        // log blocks
+        for (i, cache) in caches.iter().enumerate() {
+            let cache = cache.lock();
+            header.blocks[i] = cache.block_id() as u32;
+            block_device.write_block(self.start_block as usize + 1 + i, cache.data());
+        }
+        // commit point
+        self.write_header(&header, block_device);
+        // checkpoint
+        for cache in caches {
+            cache.lock().sync();
+        }
+        self.write_header(&JournalHeader::empty(), block_device);
+    }
+
+    /// Redo a committed transaction left by a crash
+    pub fn replay(&self, block_device: &Arc<dyn BlockDevice>) {
+        let mut header = JournalHeader::empty();
+        block_device.read_block(self.start_block as usize, header.as_bytes_mut());
+        if !header.is_committed() {
+            return;
+        }
+        let mut buf = [0u8; BLOCK_SZ];
+        for i in 0..header.count as usize {
+            block_device.read_block(self.start_block as usize + 1 + i, &mut buf);
+            let cache = get_block_cache(header.blocks[i] as usize, Arc::clone(block_device));
+            let mut cache = cache.lock();
+            cache.modify(0, |data_block: &mut DataBlock| {
+                data_block.copy_from_slice(&buf)
+            });
+            cache.sync();
+        }
+        self.write_header(&JournalHeader::empty(), block_device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ramdisk::RamDisk;
+    use crate::{fsck, EasyFileSystem, Inode, BLOCK_SZ};
+    use alloc::sync::Arc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    type Image = Vec<[u8; BLOCK_SZ]>;
+
+    const TOTAL_BLOCKS: u32 = 2048;
+
+    /// A filesystem with a directory `d` and a file `a` of 30 blocks of ones
+    fn base_image() -> Image {
+        let disk = Arc::new(RamDisk::new(TOTAL_BLOCKS as usize));
+        let efs = EasyFileSystem::create(disk.clone(), TOTAL_BLOCKS, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        root.mkdir("d").unwrap();
+        root.create("a").unwrap().write_at(0, &[1; 30 * BLOCK_SZ]);
+        drop(root);
+        drop(efs);
+        disk.image()
+    }
+
+    /// Run `op` over the root of `image` on a device that crashes after `writes` blocks,
+    /// return the image left and the number of blocks written
+    fn run(image: &Image, writes: Option<usize>, op: &dyn Fn(&Inode)) -> (Image, usize) {
+        let disk = Arc::new(RamDisk::from_image(image.clone()));
+        if let Some(writes) = writes {
+            disk.crash_after(writes);
+        }
+        let efs = EasyFileSystem::open(disk.clone());
+        op(&EasyFileSystem::root_inode(&efs));
+        drop(efs);
+        (disk.image(), disk.writes())
+    }
+
+    /// Run `op` crashing at every possible point, check that the filesystem is consistent
+    /// once the journal is replayed, then check it with `check`
+    fn crash_everywhere(op: &dyn Fn(&Inode), check: &dyn Fn(&Inode)) {
+        let image = base_image();
+        let (_, writes) = run(&image, None, op);
+        for crash in 0..=writes {
+            let (image, _) = run(&image, Some(crash), op);
+            let efs = EasyFileSystem::open(Arc::new(RamDisk::from_image(image)));
+            assert_eq!(fsck(&efs, false), [], "crash after {crash} blocks");
+            check(&EasyFileSystem::root_inode(&efs));
+        }
+    }
+
+    fn read_all(inode: &Inode) -> Vec<u8> {
+        let mut buf = vec![0; inode.stat().size as usize];
+        inode.read_at(0, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn crash_leaves_consistent_filesystem() {
+        crash_everywhere(
+            &|root| {
+                let a = root.find("a").unwrap();
+                // several parts, each in its own operation
+                a.write_at(10 * BLOCK_SZ, &[2; 200 * BLOCK_SZ]);
+                let d = root.find("d").unwrap();
+                for name in ["x", "y", "z"] {
+                    d.create(name).unwrap().write_at(0, &[3; 3 * BLOCK_SZ]);
+                }
+                d.unlink("y");
+                assert!(root.rename("a", &d, "x"));
+                d.find("z").unwrap().truncate(40 * BLOCK_SZ as u32);
+                root.sync();
+            },
+            &|_| {},
+        );
+    }
+
+    #[test]
+    fn crash_keeps_operation_atomic() {
+        crash_everywhere(
+            &|root| {
+                let d = root.find("d").unwrap();
+                assert!(root.rename("a", &d, "b"));
+                root.find("d")
+                    .unwrap()
+                    .find("b")
+                    .unwrap()
+                    .write_at(0, &[2; 10 * BLOCK_SZ]);
+                root.sync();
+            },
+            &|root| {
+                let d = root.find("d").unwrap();
+                // moved or not, never both or neither
+                let a = match (root.find("a"), d.find("b")) {
+                    (Some(a), None) | (None, Some(a)) => a,
+                    _ => panic!("rename is not atomic"),
+                };
+                let data = read_all(&a);
+                assert_eq!(data.len(), 30 * BLOCK_SZ);
+                // the write is small enough to be one operation
+                assert!(data[..10 * BLOCK_SZ].iter().all(|&b| b == data[0]));
+                assert!(data[10 * BLOCK_SZ..].iter().all(|&b| b == 1));
+            },
+        );
+    }
+
+    #[test]
+    fn sync_makes_operations_durable() {
+        let image = base_image();
+        let (image, _) = run(&image, None, &|root| {
+            root.create("b").unwrap().write_at(0, b"hello");
+            root.sync();
+            // operations after the last sync are committed when the filesystem is dropped
+            root.create("c").unwrap();
+        });
+        let efs = EasyFileSystem::open(Arc::new(RamDisk::from_image(image)));
+        let root = EasyFileSystem::root_inode(&efs);
+        assert_eq!(read_all(&root.find("b").unwrap()), b"hello");
+        assert!(root.find("c").is_some());
+        assert_eq!(fsck(&efs, false), []);
+    }
+}
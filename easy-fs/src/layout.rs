@@ -5,10 +5,12 @@ use core::fmt::{Debug, Formatter, Result};
 
 /// Magic number for sanity check
 const EFS_MAGIC: u32 = 0x3b800001;
+/// Version of the on-disk format, bumped when the layout changes
+const EFS_VERSION: u32 = 3;
 /// The max number of direct inodes
-const INODE_DIRECT_COUNT: usize = 28;
+const INODE_DIRECT_COUNT: usize = 23;
 /// The max length of inode name
-const NAME_LENGTH_LIMIT: usize = 27;
+pub const NAME_LENGTH_LIMIT: usize = 255;
 /// The max number of indirect1 inodes
 const INODE_INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
 /// The max number of indirect2 inodes
@@ -18,7 +20,6 @@ const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
 /// The upper bound of indirect1 inode index
 const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
 /// The upper bound of indirect2 inode indexs
-#[allow(unused)]
 const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
 /// Super block of a filesystem
 #[repr(C)]
@@ -29,6 +30,10 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    /// Size of the journal region at the end of the device, 0 if there is no journal
+    pub journal_blocks: u32,
+    /// Images made before versioning have 0 here
+    version: u32,
 }
 
 impl Debug for SuperBlock {
@@ -39,6 +44,8 @@ impl Debug for SuperBlock {
             .field("inode_area_blocks", &self.inode_area_blocks)
             .field("data_bitmap_blocks", &self.data_bitmap_blocks)
             .field("data_area_blocks", &self.data_area_blocks)
+            .field("journal_blocks", &self.journal_blocks)
+            .field("version", &self.version)
             .finish()
     }
 }
@@ -52,6 +59,7 @@ impl SuperBlock {
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
         data_area_blocks: u32,
+        journal_blocks: u32,
     ) {
         *self = Self {
             magic: EFS_MAGIC,
@@ -60,18 +68,32 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
+            journal_blocks,
+            version: EFS_VERSION,
         }
     }
     /// Check if a super block is valid using efs magic
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+    /// Check if the on-disk format is the one this crate reads and writes
+    pub fn is_supported(&self) -> bool {
+        self.version == EFS_VERSION
+    }
+    /// Version of the on-disk format
+    pub fn version(&self) -> u32 {
+        self.version
+    }
 }
 /// Type of a disk inode
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum DiskInodeType {
+    /// Regular file
     File,
+    /// Directory
     Directory,
+    /// Symbolic link, the path it refers to is stored as data
+    SymLink,
 }
 
 /// A indirect block
@@ -85,28 +107,55 @@ pub struct DiskInode {
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    /// Number of directory entries referring to this inode
+    pub nlink: u32,
+    /// Last access time, in seconds
+    pub atime: u32,
+    /// Last modification time, in seconds
+    pub mtime: u32,
+    /// Creation time, in seconds
+    pub crtime: u32,
+    /// Permission bits, such as `0o644`
+    pub mode: u32,
     type_: DiskInodeType,
 }
 
 impl DiskInode {
-    /// Initialize a disk inode, as well as all direct inodes under it
+    /// Initialize a disk inode created at `now`, as well as all direct inodes under it
     /// indirect1 and indirect2 block are allocated only when they are needed
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.nlink = 0;
+        self.atime = now;
+        self.mtime = now;
+        self.crtime = now;
+        self.mode = match type_ {
+            DiskInodeType::File => 0o644,
+            DiskInodeType::Directory => 0o755,
+            DiskInodeType::SymLink => 0o777,
+        };
         self.type_ = type_;
     }
     /// Whether this inode is a directory
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
     }
+    /// Type of this inode
+    pub fn type_(&self) -> DiskInodeType {
+        self.type_
+    }
     /// Whether this inode is a file
     #[allow(unused)]
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether this inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.type_ == DiskInodeType::SymLink
+    }
     /// Return block number correspond to size.
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
@@ -235,8 +284,137 @@ impl DiskInode {
             });
     }
 
+    /// Max size of a file
+    pub const MAX_SIZE: u32 = (INDIRECT2_BOUND * BLOCK_SZ) as u32;
+
+    /// Whether the type read from disk is valid
+    pub fn has_valid_type(&self) -> bool {
+        let raw = unsafe { *(core::ptr::addr_of!(self.type_) as *const u8) };
+        raw == DiskInodeType::File as u8
+            || raw == DiskInodeType::Directory as u8
+            || raw == DiskInodeType::SymLink as u8
+    }
+
+    /// Visit index blocks and data blocks of current disk inode in order,
+    /// until `claim` rejects one.
+    ///
+    /// Return the number of leading data blocks that are reachable
+    /// through accepted blocks only.
+    pub fn claim_blocks(
+        &self,
+        block_device: &Arc<dyn BlockDevice>,
+        mut claim: impl FnMut(u32) -> bool,
+    ) -> u32 {
+        let data_blocks = self.data_blocks() as usize;
+        // direct
+        for (i, block) in self.direct.iter().take(data_blocks).enumerate() {
+            if !claim(*block) {
+                return i as u32;
+            }
+        }
+        if data_blocks <= INODE_DIRECT_COUNT {
+            return data_blocks as u32;
+        }
+        // indirect1
+        if !claim(self.indirect1) {
+            return DIRECT_BOUND as u32;
+        }
+        let indirect1: IndirectBlock =
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect1: &IndirectBlock| *indirect1);
+        for (i, block) in indirect1
+            .iter()
+            .take(data_blocks - DIRECT_BOUND)
+            .enumerate()
+        {
+            if !claim(*block) {
+                return (DIRECT_BOUND + i) as u32;
+            }
+        }
+        if data_blocks <= INDIRECT1_BOUND {
+            return data_blocks as u32;
+        }
+        // indirect2
+        if !claim(self.indirect2) {
+            return INDIRECT1_BOUND as u32;
+        }
+        let indirect2: IndirectBlock =
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| *indirect2);
+        let rest = data_blocks - INDIRECT1_BOUND;
+        let indirect1_blocks = rest.div_ceil(INODE_INDIRECT1_COUNT);
+        for (a, indirect1_id) in indirect2.iter().take(indirect1_blocks).enumerate() {
+            let base = INDIRECT1_BOUND + a * INODE_INDIRECT1_COUNT;
+            if !claim(*indirect1_id) {
+                return base as u32;
+            }
+            let indirect1: IndirectBlock =
+                get_block_cache(*indirect1_id as usize, Arc::clone(block_device))
+                    .lock()
+                    .read(0, |indirect1: &IndirectBlock| *indirect1);
+            let count = rest - a * INODE_INDIRECT1_COUNT;
+            for (b, block) in indirect1.iter().take(count).enumerate() {
+                if !claim(*block) {
+                    return (base + b) as u32;
+                }
+            }
+        }
+        data_blocks as u32
+    }
+
+    /// Decrease the size of current disk inode to `new_size`,
+    /// return blocks after it that should be deallocated, including index blocks.
+    ///
+    /// The tail of the last kept block is zeroed, so the file reads zeros there if it grows again.
+    pub fn decrease_size(
+        &mut self,
+        new_size: u32,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        let mut v: Vec<u32> = (new_blocks..old_blocks)
+            .map(|inner_id| self.get_block_id(inner_id as u32, block_device))
+            .collect();
+        // sub indirect1 blocks under indirect2 that become empty
+        let indirect1_blocks = |data_blocks: usize| {
+            data_blocks
+                .saturating_sub(INDIRECT1_BOUND)
+                .div_ceil(INODE_INDIRECT1_COUNT)
+        };
+        let (a0, a1) = (indirect1_blocks(new_blocks), indirect1_blocks(old_blocks));
+        if a0 < a1 {
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| v.extend(&indirect2[a0..a1]));
+        }
+        if new_blocks <= INDIRECT1_BOUND && old_blocks > INDIRECT1_BOUND {
+            v.push(self.indirect2);
+            self.indirect2 = 0;
+        }
+        if new_blocks <= DIRECT_BOUND && old_blocks > DIRECT_BOUND {
+            v.push(self.indirect1);
+            self.indirect1 = 0;
+        }
+        for direct in self.direct.iter_mut().take(old_blocks).skip(new_blocks) {
+            *direct = 0;
+        }
+        let tail = new_size as usize % BLOCK_SZ;
+        if tail != 0 {
+            let block_id = self.get_block_id(new_blocks as u32 - 1, block_device);
+            get_block_cache(block_id as usize, Arc::clone(block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| data_block[tail..].fill(0));
+        }
+        self.size = new_size;
+        v
+    }
+
     /// Clear size to zero and return blocks that should be deallocated.
-    /// We will clear the block contents to zero later.
+    /// Freed blocks are cleared when they are allocated again.
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
         let mut v: Vec<u32> = Vec::new();
         let mut data_blocks = self.data_blocks() as usize;
@@ -259,10 +437,9 @@ impl DiskInode {
         // indirect1
         get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
+            .read(0, |indirect1: &IndirectBlock| {
                 while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
                     v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
                     current_blocks += 1;
                 }
             });
@@ -278,15 +455,16 @@ impl DiskInode {
         assert!(data_blocks <= INODE_INDIRECT2_COUNT);
         let a1 = data_blocks / INODE_INDIRECT1_COUNT;
         let b1 = data_blocks % INODE_INDIRECT1_COUNT;
+        // only read them, freed blocks need not be written back
         get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
             .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
+            .read(0, |indirect2: &IndirectBlock| {
                 // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
+                for entry in indirect2.iter().take(a1) {
                     v.push(*entry);
                     get_block_cache(*entry as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter() {
                                 v.push(*entry);
                             }
@@ -297,17 +475,44 @@ impl DiskInode {
                     v.push(indirect2[a1]);
                     get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
                         .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
+                        .read(0, |indirect1: &IndirectBlock| {
                             for entry in indirect1.iter().take(b1) {
                                 v.push(*entry);
                             }
                         });
-                    //indirect2[a1] = 0;
                 }
             });
         self.indirect2 = 0;
         v
     }
+    /// Call a function over the `inner_id`-th block of a directory to read it
+    pub fn read_dir_block<V>(
+        &self,
+        inner_id: u32,
+        block_device: &Arc<dyn BlockDevice>,
+        f: impl FnOnce(&DirBlock) -> V,
+    ) -> V {
+        get_block_cache(
+            self.get_block_id(inner_id, block_device) as usize,
+            Arc::clone(block_device),
+        )
+        .lock()
+        .read(0, f)
+    }
+    /// Call a function over the `inner_id`-th block of a directory to modify it
+    pub fn modify_dir_block<V>(
+        &self,
+        inner_id: u32,
+        block_device: &Arc<dyn BlockDevice>,
+        f: impl FnOnce(&mut DirBlock) -> V,
+    ) -> V {
+        get_block_cache(
+            self.get_block_id(inner_id, block_device) as usize,
+            Arc::clone(block_device),
+        )
+        .lock()
+        .modify(0, f)
+    }
     /// Read data from current disk inode
     pub fn read_at(
         &self,
@@ -388,47 +593,182 @@ impl DiskInode {
         write_size
     }
 }
-/// A directory entry
+/// Header of a directory entry, followed by its name.
+///
+/// Entries tile each block of a directory: `rec_len` covers the header, the name
+/// and the free space after them, which can hold new entries.
+/// An entry with an empty name only holds free space.
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct DirEntry {
-    name: [u8; NAME_LENGTH_LIMIT + 1],
     inode_number: u32,
+    rec_len: u16,
+    name_len: u16,
 }
-/// Size of a directory entry
-pub const DIRENT_SZ: usize = 32;
+/// Size of the header of a directory entry
+pub const DIRENT_SZ: usize = 8;
+/// Alignment of directory entries in a block
+const DIRENT_ALIGN: usize = 4;
 
 impl DirEntry {
-    /// Create an empty directory entry
-    pub fn empty() -> Self {
-        Self {
-            name: [0u8; NAME_LENGTH_LIMIT + 1],
-            inode_number: 0,
-        }
+    /// Bytes taken by an entry with a name of `name_len` bytes
+    pub fn size_for(name_len: usize) -> usize {
+        (DIRENT_SZ + name_len).next_multiple_of(DIRENT_ALIGN)
+    }
+    /// Get inode number of the entry
+    pub fn inode_number(&self) -> u32 {
+        self.inode_number
     }
-    /// Crate a directory entry from name and inode number
-    pub fn new(name: &str, inode_number: u32) -> Self {
-        let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
-        bytes[..name.len()].copy_from_slice(name.as_bytes());
-        Self {
-            name: bytes,
-            inode_number,
+    /// Whether the entry only holds free space, e.g. left by a removed file
+    pub fn is_empty(&self) -> bool {
+        self.name_len == 0
+    }
+    /// Bytes covered by the entry, including free space after the name
+    pub fn rec_len(&self) -> usize {
+        self.rec_len as usize
+    }
+    /// Bytes actually used by the entry, 0 if it is empty
+    pub fn used_len(&self) -> usize {
+        if self.is_empty() {
+            0
+        } else {
+            Self::size_for(self.name_len as usize)
         }
     }
-    /// Serialize into bytes
-    pub fn as_bytes(&self) -> &[u8] {
-        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+}
+
+/// A block of a directory
+#[repr(C)]
+pub struct DirBlock([u8; BLOCK_SZ]);
+
+impl DirBlock {
+    /// Make the block a single empty entry
+    pub fn init(&mut self) {
+        self.set_header(
+            0,
+            DirEntry {
+                inode_number: 0,
+                rec_len: BLOCK_SZ as u16,
+                name_len: 0,
+            },
+        );
     }
-    /// Serialize into mutable bytes
-    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
-        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    fn header(&self, offset: usize) -> DirEntry {
+        // entries are not aligned in the block cache
+        unsafe { core::ptr::read_unaligned(self.0[offset..].as_ptr() as *const DirEntry) }
     }
-    /// Get name of the entry
-    pub fn name(&self) -> &str {
-        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
-        core::str::from_utf8(&self.name[..len]).unwrap()
+    fn set_header(&mut self, offset: usize, entry: DirEntry) {
+        unsafe { core::ptr::write_unaligned(self.0[offset..].as_mut_ptr() as *mut DirEntry, entry) }
     }
-    /// Get inode number of the entry
-    pub fn inode_number(&self) -> u32 {
-        self.inode_number
+    /// Iterate over entries in the block with their offsets and names.
+    ///
+    /// Iteration stops at the first malformed entry.
+    pub fn entries(&self) -> impl Iterator<Item = (usize, DirEntry, &str)> + '_ {
+        let mut offset = 0;
+        core::iter::from_fn(move || {
+            if offset + DIRENT_SZ > BLOCK_SZ {
+                return None;
+            }
+            let entry = self.header(offset);
+            let rec_len = entry.rec_len();
+            if rec_len < DIRENT_SZ
+                || rec_len & (DIRENT_ALIGN - 1) != 0
+                || offset + rec_len > BLOCK_SZ
+                || entry.used_len() > rec_len
+            {
+                return None;
+            }
+            let name_start = offset + DIRENT_SZ;
+            let name =
+                core::str::from_utf8(&self.0[name_start..name_start + entry.name_len as usize])
+                    .ok()?;
+            let item = (offset, entry, name);
+            offset += rec_len;
+            Some(item)
+        })
+    }
+    /// Whether entries in the block are well-formed and cover the whole block
+    pub fn is_valid(&self) -> bool {
+        self.entries()
+            .map(|(_, entry, _)| entry.rec_len())
+            .sum::<usize>()
+            == BLOCK_SZ
+    }
+    /// Size of the largest entry that can be inserted into the block
+    pub fn max_free(&self) -> usize {
+        self.entries()
+            .map(|(_, entry, _)| entry.rec_len() - entry.used_len())
+            .max()
+            .unwrap_or(0)
+    }
+    /// Insert an entry into the first space large enough, return its offset.
+    /// Return `None` if there is no room for it.
+    pub fn insert(&mut self, name: &str, inode_number: u32) -> Option<usize> {
+        let size = DirEntry::size_for(name.len());
+        let (offset, entry) = self
+            .entries()
+            .map(|(offset, entry, _)| (offset, entry))
+            .find(|(_, entry)| entry.rec_len() - entry.used_len() >= size)?;
+        // take over an empty entry, or split the free space after a used one
+        let (offset, rec_len) = if entry.is_empty() {
+            (offset, entry.rec_len())
+        } else {
+            let used_len = entry.used_len();
+            self.set_header(
+                offset,
+                DirEntry {
+                    rec_len: used_len as u16,
+                    ..entry
+                },
+            );
+            (offset + used_len, entry.rec_len() - used_len)
+        };
+        self.set_header(
+            offset,
+            DirEntry {
+                inode_number,
+                rec_len: rec_len as u16,
+                name_len: name.len() as u16,
+            },
+        );
+        self.0[offset + DIRENT_SZ..offset + DIRENT_SZ + name.len()]
+            .copy_from_slice(name.as_bytes());
+        Some(offset)
+    }
+    /// Remove the entry at `offset`, its space is merged into the entry before it
+    pub fn remove(&mut self, offset: usize) {
+        let entry = self.header(offset);
+        let prev = self
+            .entries()
+            .find(|(prev, prev_entry, _)| prev + prev_entry.rec_len() == offset)
+            .map(|(prev, prev_entry, _)| (prev, prev_entry));
+        match prev {
+            Some((prev, prev_entry)) => self.set_header(
+                prev,
+                DirEntry {
+                    rec_len: (prev_entry.rec_len() + entry.rec_len()) as u16,
+                    ..prev_entry
+                },
+            ),
+            None => self.set_header(
+                offset,
+                DirEntry {
+                    inode_number: 0,
+                    rec_len: entry.rec_len,
+                    name_len: 0,
+                },
+            ),
+        }
+    }
+    /// Make the entry at `offset` refer to another inode
+    pub fn set_inode_number(&mut self, offset: usize, inode_number: u32) {
+        let entry = self.header(offset);
+        self.set_header(
+            offset,
+            DirEntry {
+                inode_number,
+                ..entry
+            },
+        );
     }
 }
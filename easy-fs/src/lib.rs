@@ -5,16 +5,30 @@ extern crate alloc;
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod dir;
 mod efs;
-mod file;
+mod fsck;
+mod journal;
 mod layout;
+#[cfg(test)]
+mod ramdisk;
 mod vfs;
+mod vfs_impl;
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+use block_cache::{
+    block_cache_sync_all, block_cache_sync_device, dirty_block_caches, get_block_cache,
+    BLOCK_CACHE_MANAGER,
+};
+pub use block_cache::{BlockKey, CacheConfig, ClockPolicy, FifoPolicy, LruPolicy, ReplacePolicy};
 pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
-pub use file::*;
+use dir::{DirIndexes, DirSlot};
+use efs::OP_BLOCKS;
+pub use efs::{EasyFileSystem, Geometry, JOURNAL_BLOCKS};
+pub use fsck::{fsck, Problem};
+use journal::Journal;
+pub use layout::DiskInodeType;
 use layout::*;
-pub use vfs::Inode;
+pub use vfs::{Inode, Stat, SYMLINK_LIMIT};
+pub use vfs_impl::EasyFs;
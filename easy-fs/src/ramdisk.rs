@@ -0,0 +1,63 @@
+use super::{BlockDevice, BLOCK_SZ};
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+type DataBlock = [u8; BLOCK_SZ];
+
+/// A block device in memory for tests, which crashes by dropping writes
+/// once it has written a given number of blocks
+pub struct RamDisk {
+    blocks: Mutex<Vec<DataBlock>>,
+    /// Blocks still written before the crash, `None` if it never crashes
+    budget: Mutex<Option<usize>>,
+    /// Number of blocks written
+    writes: Mutex<usize>,
+}
+
+impl RamDisk {
+    /// A zeroed device of `blocks` blocks
+    pub fn new(blocks: usize) -> Self {
+        Self::from_image(vec![[0; BLOCK_SZ]; blocks])
+    }
+
+    /// A device holding `image`
+    pub fn from_image(image: Vec<DataBlock>) -> Self {
+        Self {
+            blocks: Mutex::new(image),
+            budget: Mutex::new(None),
+            writes: Mutex::new(0),
+        }
+    }
+
+    /// Blocks on the device, as they would be found after a crash
+    pub fn image(&self) -> Vec<DataBlock> {
+        self.blocks.lock().clone()
+    }
+
+    /// Drop all writes after the next `writes` blocks
+    pub fn crash_after(&self, writes: usize) {
+        *self.budget.lock() = Some(writes);
+    }
+
+    /// Number of blocks written so far, including dropped ones
+    pub fn writes(&self) -> usize {
+        *self.writes.lock()
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.blocks.lock()[block_id]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        *self.writes.lock() += 1;
+        match &mut *self.budget.lock() {
+            Some(0) => return,
+            Some(budget) => *budget -= 1,
+            None => {}
+        }
+        self.blocks.lock()[block_id].copy_from_slice(buf);
+    }
+}
@@ -1,13 +1,49 @@
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    get_block_cache, BlockDevice, DirBlock, DirEntry, DirSlot, DiskInode, DiskInodeType,
+    EasyFileSystem, BLOCK_SZ, NAME_LENGTH_LIMIT, OP_BLOCKS,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use spin::{Mutex, MutexGuard};
+/// Metadata of an inode
+#[derive(Clone, Copy, Debug)]
+pub struct Stat {
+    /// Inode number
+    pub ino: u32,
+    /// Type of the inode
+    pub type_: DiskInodeType,
+    /// Number of hard links
+    pub nlink: u32,
+    /// Size in bytes
+    pub size: u32,
+    /// Permission bits, such as `0o644`
+    pub mode: u32,
+    /// Last access time, in seconds
+    pub atime: u32,
+    /// Last modification time, in seconds
+    pub mtime: u32,
+    /// Creation time, in seconds
+    pub crtime: u32,
+}
+
+/// The max number of symbolic links followed when resolving a path
+pub const SYMLINK_LIMIT: usize = 8;
+
+/// Access time is written back on read when it is older than this, in seconds
+const ATIME_INTERVAL: u32 = 24 * 60 * 60;
+
+/// The max number of index blocks modified by writing a part of a file:
+/// indirect1, indirect2 and two indirect1 blocks under it
+const INDEX_BLOCKS: usize = 4;
+
 /// Virtual filesystem layer over easy-fs
+///
+/// An inode whose last link is removed stays on disk while an `Inode` refers to it,
+/// and is freed with its data when the last one is dropped.
 pub struct Inode {
+    inode_id: u32,
     block_id: usize,
     block_offset: usize,
     fs: Arc<Mutex<EasyFileSystem>>,
@@ -15,18 +51,20 @@ pub struct Inode {
 }
 
 impl Inode {
-    /// Create a vfs inode
-    pub fn new(
-        block_id: u32,
-        block_offset: usize,
-        fs: Arc<Mutex<EasyFileSystem>>,
-        block_device: Arc<dyn BlockDevice>,
+    /// Create a vfs inode on `efs`, whose lock is held as `fs`
+    pub(crate) fn new(
+        inode_id: u32,
+        efs: &Arc<Mutex<EasyFileSystem>>,
+        fs: &mut EasyFileSystem,
     ) -> Self {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        fs.hold_inode(inode_id);
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
-            fs,
-            block_device,
+            fs: Arc::clone(efs),
+            block_device: Arc::clone(&fs.block_device),
         }
     }
 
@@ -44,38 +82,177 @@ impl Inode {
             .modify(self.block_offset, f)
     }
 
-    /// Find inode under a disk inode by name
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+    /// Get the inode number of current inode
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
+    /// Whether current inode is a directory
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    /// Whether current inode is a regular file
+    pub fn is_file(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_file())
+    }
+
+    /// Whether current inode is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_symlink())
+    }
+
+    /// Get metadata of current inode
+    pub fn stat(&self) -> Stat {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| Stat {
+            ino: self.inode_id,
+            type_: disk_inode.type_(),
+            nlink: disk_inode.nlink,
+            size: disk_inode.size,
+            mode: disk_inode.mode,
+            atime: disk_inode.atime,
+            mtime: disk_inode.mtime,
+            crtime: disk_inode.crtime,
+        })
+    }
+
+    /// Set permission bits of current inode, bits other than `0o7777` are ignored
+    pub fn set_mode(&self, mode: u32) {
+        let fs = self.fs.lock();
+        fs.begin_op(1);
+        self.modify_disk_inode(|disk_inode| disk_inode.mode = mode & 0o7777);
+    }
+
+    /// Create a vfs inode for the given inode number on the same filesystem.
+    ///
+    /// It must not be dropped while the filesystem is locked, see [`Drop`] for `Inode`.
+    fn get_inode(&self, inode_id: u32, fs: &mut EasyFileSystem) -> Arc<Inode> {
+        Arc::new(Self::new(inode_id, &self.fs, fs))
+    }
+
+    /// Find a directory entry under the disk inode of current inode by name,
+    /// through the index of the directory.
+    fn find_dirent(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+        fs: &mut EasyFileSystem,
+    ) -> Option<DirSlot> {
         // assert it is a directory
         assert!(disk_inode.is_dir());
-        let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
-        for i in 0..file_count {
-            assert_eq!(
-                disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device,),
-                DIRENT_SZ,
-            );
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
-            }
-        }
-        None
+        fs.dir_indexes
+            .get(self.inode_id, disk_inode, &self.block_device)
+            .find(name)
+    }
+
+    /// Find inode under a disk inode by name
+    fn find_inode_id(
+        &self,
+        name: &str,
+        disk_inode: &DiskInode,
+        fs: &mut EasyFileSystem,
+    ) -> Option<u32> {
+        self.find_dirent(name, disk_inode, fs)
+            .map(|slot| slot.inode_number)
+    }
+
+    /// Find a directory entry under current inode by name,
+    /// `None` if current inode is not a directory
+    fn find_child(&self, name: &str, fs: &mut EasyFileSystem) -> Option<DirSlot> {
+        self.find_child_by_id(self.inode_id, name, fs)
+    }
+
+    /// Find a directory entry by name under the directory with the given inode number,
+    /// `None` if it is not a directory
+    fn find_child_by_id(
+        &self,
+        dir_id: u32,
+        name: &str,
+        fs: &mut EasyFileSystem,
+    ) -> Option<DirSlot> {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(dir_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(block_offset, |disk_inode: &DiskInode| {
+                if disk_inode.is_dir() {
+                    fs.dir_indexes
+                        .get(dir_id, disk_inode, &self.block_device)
+                        .find(name)
+                } else {
+                    None
+                }
+            })
     }
 
     /// Find inode under current inode by name
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
-        let fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
-        })
+        let mut fs = self.fs.lock();
+        self.find_child(name, &mut fs)
+            .map(|slot| self.get_inode(slot.inode_number, &mut fs))
+    }
+
+    /// Find inode by a path relative to current inode.
+    ///
+    /// Components are separated by `/`, empty components and leading `/` are ignored,
+    /// `.` and `..` are resolved through the directory entries on disk.
+    /// Symbolic links are followed, at most [`SYMLINK_LIMIT`] times in total.
+    pub fn lookup(&self, path: &str) -> Option<Arc<Inode>> {
+        self.walk(path, true, &mut 0)
+    }
+
+    /// Find inode by a path relative to current inode like [`Inode::lookup`],
+    /// but a symbolic link as the last component is not followed.
+    pub fn lookup_nofollow(&self, path: &str) -> Option<Arc<Inode>> {
+        self.walk(path, false, &mut 0)
+    }
+
+    /// Resolve `path` from current inode, counting followed symbolic links in `links`
+    fn walk(&self, path: &str, follow_last: bool, links: &mut usize) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let mut current = self.get_inode(self.inode_id, &mut fs);
+        drop(fs);
+        let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+        while let Some(name) = names.next() {
+            let next = current.find(name)?;
+            let is_last = names.peek().is_none();
+            if !next.is_symlink() || (is_last && !follow_last) {
+                current = next;
+                continue;
+            }
+            *links += 1;
+            if *links > SYMLINK_LIMIT {
+                return None;
+            }
+            let target = next.readlink()?;
+            // an absolute target starts from the root directory
+            let base = if target.starts_with('/') {
+                let mut fs = self.fs.lock();
+                self.get_inode(0, &mut fs)
+            } else {
+                current
+            };
+            current = base.walk(&target, true, links)?;
+        }
+        Some(current)
+    }
+
+    /// Find the directory that should contain the last component of `path`.
+    ///
+    /// Return the directory inode and the name of the last component,
+    /// which is not required to exist.
+    pub fn lookup_parent<'a>(&self, path: &'a str) -> Option<(Arc<Inode>, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() {
+            return None;
+        }
+        self.lookup(parent)
+            .filter(|parent| parent.is_dir())
+            .map(|parent| (parent, name))
     }
 
     /// Increase the size of a disk inode
@@ -96,93 +273,584 @@ impl Inode {
         disk_inode.increase_size(new_size, v, &self.block_device);
     }
 
-    /// Create inode under current inode by name.
-    /// Attention: use find previously to ensure the new file not existing.
+    /// Add a directory entry to a directory disk inode of current inode.
+    /// It takes the first free space large enough, the directory grows by a block if none is.
+    fn append_dirent(
+        &self,
+        name: &str,
+        inode_id: u32,
+        dir_inode: &mut DiskInode,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) {
+        let size = DirEntry::size_for(name.len());
+        let block = match fs
+            .dir_indexes
+            .get(self.inode_id, dir_inode, &self.block_device)
+            .block_for(size)
+        {
+            Some(block) => block,
+            None => {
+                let block = dir_inode.size / BLOCK_SZ as u32;
+                self.increase_size(dir_inode.size + BLOCK_SZ as u32, dir_inode, fs);
+                dir_inode.modify_dir_block(block, &self.block_device, DirBlock::init);
+                block
+            }
+        };
+        // write dirent
+        let (offset, free) = dir_inode.modify_dir_block(block, &self.block_device, |dir_block| {
+            (
+                dir_block.insert(name, inode_id).unwrap(),
+                dir_block.max_free(),
+            )
+        });
+        let index = fs
+            .dir_indexes
+            .get(self.inode_id, dir_inode, &self.block_device);
+        index.insert(
+            name,
+            DirSlot {
+                block,
+                offset,
+                inode_number: inode_id,
+            },
+        );
+        index.set_free(block, free);
+        dir_inode.mtime = EasyFileSystem::now();
+    }
+
+    /// Whether `name` can be added into current inode as a new entry
+    fn can_add(&self, name: &str, fs: &mut EasyFileSystem) -> bool {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return false;
+        }
+        self.read_disk_inode(|disk_inode| {
+            disk_inode.is_dir() && self.find_inode_id(name, disk_inode, fs).is_none()
+        })
+    }
+
+    /// Call a function over the disk inode with the given inode number to read it
+    fn read_inode_by_id<V>(
+        &self,
+        inode_id: u32,
+        fs: &MutexGuard<EasyFileSystem>,
+        f: impl FnOnce(&DiskInode) -> V,
+    ) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .read(block_offset, f)
+    }
+
+    /// Call a function over the disk inode with the given inode number
+    fn modify_inode_by_id<V>(
+        &self,
+        inode_id: u32,
+        fs: &MutexGuard<EasyFileSystem>,
+        f: impl FnOnce(&mut DiskInode) -> V,
+    ) -> V {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(block_offset, f)
+    }
+
+    /// Create an inode of `type_` under current inode by name.
+    fn create_inode(
+        &self,
+        name: &str,
+        type_: DiskInodeType,
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> Option<Arc<Inode>> {
+        if !self.can_add(name, fs) {
+            return None;
+        }
+        // create a new inode
+        let new_inode_id = fs.alloc_inode();
+        // initialize inode, it is referred by the new entry
+        self.modify_inode_by_id(new_inode_id, fs, |new_inode| {
+            new_inode.initialize(type_, EasyFileSystem::now());
+            new_inode.nlink = 1;
+        });
+        // append file in the dirent
+        self.modify_disk_inode(|dir_inode| {
+            self.append_dirent(name, new_inode_id, dir_inode, fs);
+        });
+        Some(self.get_inode(new_inode_id, fs))
+    }
+
+    /// Create a file under current inode by name.
+    /// Return `None` if the name already exists or current inode is not a directory.
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
         let mut fs = self.fs.lock();
-        // create a new file
-        // alloc a inode with an indirect block
-        let new_inode_id = fs.alloc_inode();
-        // initialize inode
-        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+        fs.begin_op(OP_BLOCKS);
+        self.create_inode(name, DiskInodeType::File, &mut fs)
+        // release efs lock automatically by compiler
+    }
+
+    /// Create a directory under current inode by name, with `.` and `..` in it.
+    /// Return `None` if the name already exists or current inode is not a directory.
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        fs.begin_op(OP_BLOCKS);
+        let inode = self.create_inode(name, DiskInodeType::Directory, &mut fs);
+        if let Some(inode) = &inode {
+            inode.init_dir(self.inode_id, &mut fs);
+        }
+        inode
+    }
+
+    /// Create a symbolic link under current inode by name, which refers to `target`.
+    /// Return `None` if the name already exists, current inode is not a directory,
+    /// or `target` is empty or longer than a block.
+    pub fn symlink(&self, name: &str, target: &str) -> Option<Arc<Inode>> {
+        if target.is_empty() || target.len() > BLOCK_SZ {
+            return None;
+        }
+        let mut fs = self.fs.lock();
+        // the target takes a data block besides the entry
+        fs.begin_op(OP_BLOCKS + 2);
+        let inode = self.create_inode(name, DiskInodeType::SymLink, &mut fs);
+        if let Some(inode) = &inode {
+            inode.modify_disk_inode(|disk_inode| {
+                inode.increase_size(target.len() as u32, disk_inode, &mut fs);
+                disk_inode.write_at(0, target.as_bytes(), &self.block_device);
             });
-        self.modify_disk_inode(|root_inode| {
-            // append file in the dirent
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            // increase size
-            self.increase_size(new_size as u32, root_inode, &mut fs);
-            // write dirent
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
+        }
+        inode
+    }
+
+    /// Get the path a symbolic link refers to, `None` if current inode is not a symbolic link
+    pub fn readlink(&self) -> Option<String> {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_symlink() {
+                return None;
+            }
+            let mut buf = vec![0u8; disk_inode.size as usize];
+            disk_inode.read_at(0, &mut buf, &self.block_device);
+            String::from_utf8(buf).ok()
+        })
+    }
+
+    /// Fill `.` and `..` into an empty directory
+    pub(crate) fn init_dir(&self, parent_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        self.modify_disk_inode(|dir_inode| {
+            self.append_dirent(".", self.inode_id, dir_inode, fs);
+            self.append_dirent("..", parent_id, dir_inode, fs);
+            dir_inode.nlink += 1;
+            if parent_id == self.inode_id {
+                dir_inode.nlink += 1;
+            }
         });
+        if parent_id != self.inode_id {
+            self.modify_inode_by_id(parent_id, fs, |parent| parent.nlink += 1);
+        }
+    }
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        block_cache_sync_all();
-        // return inode
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
-        // release efs lock automatically by compiler
+    /// Create a hard link `name` under current inode to a regular file `target`.
+    /// Return `false` if the name is invalid or exists, or `target` is a directory
+    /// or lives on another filesystem.
+    pub fn link(&self, name: &str, target: &Inode) -> bool {
+        if !Arc::ptr_eq(&self.fs, &target.fs) {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        if !target.read_disk_inode(|disk_inode| disk_inode.is_file())
+            || !self.can_add(name, &mut fs)
+        {
+            return false;
+        }
+        fs.begin_op(OP_BLOCKS);
+        self.modify_disk_inode(|dir_inode| {
+            self.append_dirent(name, target.inode_id, dir_inode, &mut fs);
+        });
+        target.modify_disk_inode(|disk_inode| disk_inode.nlink += 1);
+        true
+    }
+
+    /// Remove the entry `name` of a regular file or a symbolic link under current inode.
+    /// Data blocks and the inode are freed once the last link is removed.
+    /// Return `false` if the entry does not exist or is a directory.
+    pub fn unlink(&self, name: &str) -> bool {
+        let mut fs = self.fs.lock();
+        let slot = match self.find_child(name, &mut fs) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        if self.read_inode_by_id(slot.inode_number, &fs, |disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        fs.begin_op(self.remove_op_blocks(slot.inode_number, &fs));
+        self.remove_dirent(name, slot, &mut fs);
+        true
+    }
+
+    /// The max number of blocks modified by an operation removing an entry
+    /// that refers to `inode_id`, whose blocks may be freed
+    fn remove_op_blocks(&self, inode_id: u32, fs: &MutexGuard<EasyFileSystem>) -> usize {
+        let blocks = self.read_inode_by_id(inode_id, fs, |disk_inode| {
+            DiskInode::total_blocks(disk_inode.size)
+        });
+        OP_BLOCKS + fs.bitmap_blocks(blocks as usize)
+    }
+
+    /// Remove the entry `name` at `slot` of current directory.
+    /// Data blocks and the inode it refers to are freed once the last link is removed,
+    /// or later when the last `Inode` referring to it is dropped.
+    fn remove_dirent(&self, name: &str, slot: DirSlot, fs: &mut MutexGuard<EasyFileSystem>) {
+        let inode_id = slot.inode_number;
+        self.clear_dirent(name, slot, fs);
+        let unlinked = self.modify_inode_by_id(inode_id, fs, |disk_inode| {
+            disk_inode.nlink -= 1;
+            disk_inode.nlink == 0
+        });
+        if unlinked && !fs.is_held(inode_id) {
+            self.free_inode(inode_id, fs);
+        }
+    }
+
+    /// Free the data blocks and the inode with the given inode number
+    fn free_inode(&self, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let data_blocks_dealloc = self.modify_inode_by_id(inode_id, fs, |disk_inode| {
+            disk_inode.clear_size(&self.block_device)
+        });
+        for data_block in data_blocks_dealloc.into_iter() {
+            fs.dealloc_data(data_block);
+        }
+        fs.dealloc_inode(inode_id);
+    }
+
+    /// Clear the entry `name` at `slot` of current directory, its space can be reused
+    fn clear_dirent(&self, name: &str, slot: DirSlot, fs: &mut EasyFileSystem) {
+        self.modify_disk_inode(|dir_inode| {
+            let free = dir_inode.modify_dir_block(slot.block, &self.block_device, |dir_block| {
+                dir_block.remove(slot.offset);
+                dir_block.max_free()
+            });
+            let index = fs
+                .dir_indexes
+                .get(self.inode_id, dir_inode, &self.block_device);
+            index.remove(name);
+            index.set_free(slot.block, free);
+            dir_inode.mtime = EasyFileSystem::now();
+        });
+    }
+
+    /// Move the entry `old_name` under current inode to `new_name` under `new_dir`.
+    ///
+    /// An existing `new_name` is replaced if neither of them is a directory.
+    /// Moving a directory updates its `..` and link counts of both parents.
+    /// Return `false` if the entry does not exist, `new_dir` lives on another filesystem,
+    /// the target is an existing directory, or a directory would be moved into itself.
+    pub fn rename(&self, old_name: &str, new_dir: &Inode, new_name: &str) -> bool {
+        if !Arc::ptr_eq(&self.fs, &new_dir.fs)
+            || [old_name, new_name]
+                .iter()
+                .any(|name| *name == "." || *name == "..")
+        {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let old_slot = match self.find_child(old_name, &mut fs) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let inode_id = old_slot.inode_number;
+        if !new_dir.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        let is_dir = |inode_id: u32, fs: &MutexGuard<EasyFileSystem>| {
+            self.read_inode_by_id(inode_id, fs, |disk_inode| disk_inode.is_dir())
+        };
+        let moving_dir = is_dir(inode_id, &fs);
+        let target = new_dir.find_child(new_name, &mut fs);
+        fs.begin_op(match target {
+            Some(slot) => self.remove_op_blocks(slot.inode_number, &fs),
+            None => OP_BLOCKS,
+        });
+        match target {
+            // the same file
+            Some(slot) if slot.inode_number == inode_id => return true,
+            Some(slot) if moving_dir || is_dir(slot.inode_number, &fs) => return false,
+            Some(slot) => new_dir.remove_dirent(new_name, slot, &mut fs),
+            None if !new_dir.can_add(new_name, &mut fs) => return false,
+            None => {}
+        }
+        // a directory cannot be moved under itself
+        if moving_dir {
+            let mut ancestor = new_dir.inode_id;
+            while ancestor != 0 {
+                if ancestor == inode_id {
+                    return false;
+                }
+                ancestor = match self.find_child_by_id(ancestor, "..", &mut fs) {
+                    Some(slot) => slot.inode_number,
+                    None => break,
+                };
+            }
+        }
+        new_dir.modify_disk_inode(|dir_inode| {
+            new_dir.append_dirent(new_name, inode_id, dir_inode, &mut fs);
+        });
+        self.clear_dirent(old_name, old_slot, &mut fs);
+        if moving_dir && self.inode_id != new_dir.inode_id {
+            let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(block_offset, |dir_inode: &mut DiskInode| {
+                    let index = fs.dir_indexes.get(inode_id, dir_inode, &self.block_device);
+                    if let Some(slot) = index.find("..") {
+                        let slot = DirSlot {
+                            inode_number: new_dir.inode_id,
+                            ..slot
+                        };
+                        index.insert("..", slot);
+                        dir_inode.modify_dir_block(slot.block, &self.block_device, |dir_block| {
+                            dir_block.set_inode_number(slot.offset, new_dir.inode_id)
+                        });
+                    }
+                });
+            self.modify_disk_inode(|dir_inode| dir_inode.nlink -= 1);
+            new_dir.modify_disk_inode(|dir_inode| dir_inode.nlink += 1);
+        }
+        true
     }
 
     /// List inodes by id under current inode
     pub fn readdir(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
             let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device,),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
+            for block in 0..disk_inode.size / BLOCK_SZ as u32 {
+                disk_inode.read_dir_block(block, &self.block_device, |dir_block| {
+                    for (_, dirent, name) in dir_block.entries() {
+                        if !dirent.is_empty() {
+                            v.push(String::from(name));
+                        }
+                    }
+                });
             }
             v
         })
     }
 
-    /// Read data from current inode
+    /// Read data from current inode.
+    ///
+    /// The access time is only written back if it is older than the modification time
+    /// or than a day, so that most reads do not write the disk.
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
-        let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        let fs = self.fs.lock();
+        let now = EasyFileSystem::now();
+        let (size, touch) = self.read_disk_inode(|disk_inode| {
+            (
+                disk_inode.read_at(offset, buf, &self.block_device),
+                disk_inode.atime < disk_inode.mtime
+                    || now.saturating_sub(disk_inode.atime) >= ATIME_INTERVAL,
+            )
+        });
+        if touch {
+            fs.begin_op(1);
+            self.modify_disk_inode(|disk_inode| disk_inode.atime = now);
+        }
+        size
     }
 
-    /// Write data to current inode
+    /// Write data to current inode.
+    ///
+    /// With a journal, large writes are split into operations that fit in a transaction,
+    /// a crash may leave some of the parts written.
+    /// Nothing is written if the data would end beyond the maximum file size.
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
         let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
+        self.write_locked(offset, buf, &mut fs)
+    }
+
+    /// Write data to the end of current inode.
+    ///
+    /// The size is read under the same lock as the write, so concurrent appends never overlap.
+    pub fn append(&self, buf: &[u8]) -> usize {
+        let mut fs = self.fs.lock();
+        let size = self.read_disk_inode(|disk_inode| disk_inode.size as usize);
+        self.write_locked(size, buf, &mut fs)
+    }
+
+    /// Write data to current inode while holding the filesystem lock.
+    fn write_locked(
+        &self,
+        offset: usize,
+        buf: &[u8],
+        fs: &mut MutexGuard<EasyFileSystem>,
+    ) -> usize {
+        match offset.checked_add(buf.len()) {
+            Some(end) if end <= DiskInode::MAX_SIZE as usize => {}
+            _ => return 0,
+        }
+        let part_len = Self::part_len(fs).unwrap_or(buf.len().max(1));
+        let mut size = 0;
+        for part in buf.chunks(part_len) {
+            let offset = offset + size;
+            fs.begin_op(Self::write_op_blocks(offset, part.len(), fs));
+            size += self.modify_disk_inode(|disk_inode| {
+                self.increase_size((offset + part.len()) as u32, disk_inode, fs);
+                disk_inode.mtime = EasyFileSystem::now();
+                disk_inode.write_at(offset, part, &self.block_device)
+            });
+        }
         size
     }
 
+    /// Length of the parts that large writes are split into, so that writing a part
+    /// fits in a transaction. `None` if there is no journal.
+    fn part_len(fs: &EasyFileSystem) -> Option<usize> {
+        // a part spans one more block than its length when it is not aligned,
+        // see `write_op_blocks` for the rest
+        fs.journal_capacity()
+            .map(|capacity| ((capacity - 1) / 2 - INDEX_BLOCKS - 1).max(1) * BLOCK_SZ)
+    }
+
+    /// The max number of blocks modified by writing `len` bytes at `offset`:
+    /// data blocks, index blocks, the inode and bitmap blocks of blocks allocated
+    fn write_op_blocks(offset: usize, len: usize, fs: &EasyFileSystem) -> usize {
+        let blocks = (offset + len).div_ceil(BLOCK_SZ) - offset / BLOCK_SZ + INDEX_BLOCKS;
+        blocks + 1 + fs.bitmap_blocks(blocks)
+    }
+
+    /// Set the size of current inode to `len`.
+    ///
+    /// Blocks after `len` are freed, a file grows with zeros.
+    /// Like writes, a file grows in parts with a journal.
+    /// Return `false` if current inode is not a regular file or `len` is too large.
+    pub fn truncate(&self, len: u32) -> bool {
+        let mut fs = self.fs.lock();
+        if len > DiskInode::MAX_SIZE || !self.read_disk_inode(|disk_inode| disk_inode.is_file()) {
+            return false;
+        }
+        let mut size = self.read_disk_inode(|disk_inode| disk_inode.size);
+        if len <= size {
+            let blocks = DiskInode::total_blocks(size) - DiskInode::total_blocks(len);
+            // the inode, the last kept block and bitmap blocks
+            fs.begin_op(2 + fs.bitmap_blocks(blocks as usize));
+            self.modify_disk_inode(|disk_inode| {
+                for data_block in disk_inode.decrease_size(len, &self.block_device) {
+                    fs.dealloc_data(data_block);
+                }
+                disk_inode.mtime = EasyFileSystem::now();
+            });
+            return true;
+        }
+        let part_len = Self::part_len(&fs).unwrap_or(len as usize) as u32;
+        while size < len {
+            let end = len.min(size + part_len);
+            fs.begin_op(Self::write_op_blocks(
+                size as usize,
+                (end - size) as usize,
+                &fs,
+            ));
+            self.modify_disk_inode(|disk_inode| {
+                self.increase_size(end, disk_inode, &mut fs);
+                disk_inode.mtime = EasyFileSystem::now();
+            });
+            size = end;
+        }
+        true
+    }
+
     /// Clear the data in current inode
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
+        let size = self.read_disk_inode(|disk_inode| disk_inode.size);
+        fs.begin_op(1 + fs.bitmap_blocks(DiskInode::total_blocks(size) as usize));
         self.modify_disk_inode(|disk_inode| {
-            let size = disk_inode.size;
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
             assert!(data_blocks_dealloc.len() == DiskInode::total_blocks(size) as usize);
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.mtime = EasyFileSystem::now();
         });
-        block_cache_sync_all();
+    }
+
+    /// Make all operations on the filesystem that current inode lives on durable.
+    ///
+    /// With a journal, operations are kept in the block cache until their transaction
+    /// is committed here or when the journal is about to be full.
+    /// Without one, modified blocks are written back here or when they are evicted.
+    pub fn sync(&self) {
+        self.fs.lock().sync();
+    }
+}
+
+impl Drop for Inode {
+    /// Free the inode if its last link has been removed and no other `Inode` refers to it.
+    ///
+    /// It locks the filesystem, so an `Inode` is never dropped with the lock held.
+    fn drop(&mut self) {
+        let mut fs = self.fs.lock();
+        if fs.release_inode(self.inode_id)
+            && self.read_disk_inode(|disk_inode| disk_inode.nlink == 0)
+        {
+            fs.begin_op(self.remove_op_blocks(self.inode_id, &fs));
+            self.free_inode(self.inode_id, &mut fs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ramdisk::RamDisk;
+
+    /// Number of free data blocks
+    fn free_blocks(efs: &Arc<Mutex<EasyFileSystem>>) -> usize {
+        let fs = efs.lock();
+        (0..fs.data_bitmap.maximum())
+            .filter(|bit| !fs.data_bitmap.test(&fs.block_device, *bit))
+            .count()
+    }
+
+    #[test]
+    fn unlinked_file_lives_until_dropped() {
+        let efs = EasyFileSystem::create(Arc::new(RamDisk::new(2048)), 2048, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let free = free_blocks(&efs);
+        let a = root.create("a").unwrap();
+        a.write_at(0, &[1; 4 * BLOCK_SZ]);
+        assert!(root.unlink("a"));
+        assert!(root.find("a").is_none());
+        assert_eq!(a.stat().nlink, 0);
+        // new files never get the blocks of the open file
+        let b = root.create("b").unwrap();
+        b.write_at(0, &[2; 4 * BLOCK_SZ]);
+        let mut buf = [0; 4 * BLOCK_SZ];
+        assert_eq!(a.read_at(0, &mut buf), buf.len());
+        assert_eq!(buf, [1; 4 * BLOCK_SZ]);
+        assert_eq!(crate::fsck(&efs, false), []);
+        drop(a);
+        assert!(root.unlink("b"));
+        drop(b);
+        assert_eq!(free_blocks(&efs), free);
+        assert_eq!(crate::fsck(&efs, false), []);
+    }
+
+    #[test]
+    fn renamed_over_file_lives_until_dropped() {
+        let efs = EasyFileSystem::create(Arc::new(RamDisk::new(2048)), 2048, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        root.create("a").unwrap().write_at(0, b"new");
+        let b = root.create("b").unwrap();
+        b.write_at(0, b"old");
+        let free = free_blocks(&efs);
+        assert!(root.rename("a", &root, "b"));
+        let mut buf = [0; 3];
+        b.read_at(0, &mut buf);
+        assert_eq!(&buf, b"old");
+        root.find("b").unwrap().read_at(0, &mut buf);
+        assert_eq!(&buf, b"new");
+        // the inode is shared by every `Inode` referring to it
+        let inode_id = b.inode_id();
+        drop(b);
+        assert_eq!(free_blocks(&efs), free + 1);
+        let fs = efs.lock();
+        assert!(!fs.inode_bitmap.test(&fs.block_device, inode_id as usize));
     }
 }
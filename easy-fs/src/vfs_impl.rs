@@ -0,0 +1,125 @@
+use super::{DiskInodeType, EasyFileSystem, Inode};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use rcore_vfs::{FileSystem, FileType, Inode as VfsInode, Metadata};
+use spin::Mutex;
+
+/// An easy-fs that can be mounted to the VFS
+pub struct EasyFs {
+    efs: Arc<Mutex<EasyFileSystem>>,
+}
+
+impl EasyFs {
+    /// Wrap an opened easy-fs
+    pub fn new(efs: Arc<Mutex<EasyFileSystem>>) -> Self {
+        Self { efs }
+    }
+}
+
+impl FileSystem for EasyFs {
+    fn root_inode(&self) -> Arc<dyn VfsInode> {
+        Arc::new(EasyFileSystem::root_inode(&self.efs))
+    }
+
+    fn sync(&self) {
+        self.efs.lock().sync();
+    }
+}
+
+/// Get the easy-fs inode behind a VFS inode, `None` if it lives on another kind of filesystem
+fn downcast(inode: &Arc<dyn VfsInode>) -> Option<&Inode> {
+    inode.as_any().downcast_ref::<Inode>()
+}
+
+impl VfsInode for Inode {
+    fn metadata(&self) -> Metadata {
+        let stat = self.stat();
+        Metadata {
+            ino: stat.ino as u64,
+            type_: match stat.type_ {
+                DiskInodeType::File => FileType::File,
+                DiskInodeType::Directory => FileType::Directory,
+                DiskInodeType::SymLink => FileType::SymLink,
+            },
+            nlink: stat.nlink,
+            size: stat.size as u64,
+            mode: stat.mode,
+            atime: stat.atime as u64,
+            mtime: stat.mtime as u64,
+            crtime: stat.crtime as u64,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        Inode::read_at(self, offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        Inode::write_at(self, offset, buf)
+    }
+
+    fn append(&self, buf: &[u8]) -> usize {
+        Inode::append(self, buf)
+    }
+
+    fn truncate(&self, len: usize) -> bool {
+        len <= u32::MAX as usize && Inode::truncate(self, len as u32)
+    }
+
+    fn sync(&self) {
+        Inode::sync(self);
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<dyn VfsInode>> {
+        Inode::find(self, name).map(|inode| inode as Arc<dyn VfsInode>)
+    }
+
+    fn create(&self, name: &str, type_: FileType) -> Option<Arc<dyn VfsInode>> {
+        let inode = match type_ {
+            FileType::File => Inode::create(self, name),
+            FileType::Directory => Inode::mkdir(self, name),
+            FileType::SymLink | FileType::CharDevice | FileType::BlockDevice => None,
+        };
+        inode.map(|inode| inode as Arc<dyn VfsInode>)
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn VfsInode>> {
+        Inode::symlink(self, name, target).map(|inode| inode as Arc<dyn VfsInode>)
+    }
+
+    fn readlink(&self) -> Option<String> {
+        Inode::readlink(self)
+    }
+
+    fn link(&self, name: &str, target: &Arc<dyn VfsInode>) -> bool {
+        match downcast(target) {
+            Some(target) => Inode::link(self, name, target),
+            None => false,
+        }
+    }
+
+    fn unlink(&self, name: &str) -> bool {
+        Inode::unlink(self, name)
+    }
+
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn VfsInode>, new_name: &str) -> bool {
+        match downcast(new_dir) {
+            Some(new_dir) => Inode::rename(self, old_name, new_dir, new_name),
+            None => false,
+        }
+    }
+
+    fn readdir(&self) -> Option<Vec<String>> {
+        if self.is_dir() {
+            Some(Inode::readdir(self))
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,163 @@
+use super::{u16_at, u32_at, Volume};
+use alloc::{string::String, vec, vec::Vec};
+
+/// 卷标
+const ATTR_VOLUME_ID: u8 = 0x08;
+/// 目录
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+/// 长文件名目录项的属性
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// 目录项的字节数
+const ENTRY_SZ: usize = 32;
+
+/// 一个长文件名目录项中 13 个 UTF-16 字符的位置
+pub(crate) const LONG_NAME_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// 目录中的一项，长文件名已经和短目录项合并
+pub(crate) struct DirEntry {
+    /// 有长文件名时是长文件名，否则是 `8.3` 短名
+    pub name: String,
+    /// 属性
+    pub attr: u8,
+    /// 起始簇，空文件为 0
+    pub first_cluster: u32,
+    /// 文件字节数，目录为 0
+    pub size: u32,
+    /// 创建时间
+    pub crtime: u64,
+    /// 修改时间
+    pub mtime: u64,
+    /// 访问时间，只记录日期
+    pub atime: u64,
+    /// 短目录项在卷上的编号，用作 inode 编号
+    pub location: u64,
+}
+
+/// 短名的校验和，长文件名目录项中记录它以确认属于哪个短目录项
+pub(crate) fn checksum(short_name: &[u8]) -> u8 {
+    short_name.iter().fold(0u8, |sum, b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*b)
+    })
+}
+
+/// 去掉短名中用于补齐的空格，`lower` 时转为小写
+fn short_part(bytes: &[u8], lower: bool) -> impl Iterator<Item = char> + '_ {
+    let len = bytes
+        .iter()
+        .rposition(|b| *b != b' ')
+        .map_or(0, |pos| pos + 1);
+    bytes[..len].iter().map(move |b| {
+        if lower {
+            b.to_ascii_lowercase() as char
+        } else {
+            *b as char
+        }
+    })
+}
+
+/// 把 `8.3` 短名转为字符串，按 Windows NT 的标志位转为小写
+fn short_name(raw: &[u8]) -> String {
+    let mut base = [0u8; 8];
+    base.copy_from_slice(&raw[..8]);
+    // 0x05 开头表示第一个字节是 0xE5
+    if base[0] == 0x05 {
+        base[0] = 0xE5;
+    }
+    let mut name: String = short_part(&base, raw[12] & 0x08 != 0).collect();
+    let ext = short_part(&raw[8..11], raw[12] & 0x10 != 0);
+    let mut ext = ext.peekable();
+    if ext.peek().is_some() {
+        name.push('.');
+        name.extend(ext);
+    }
+    name
+}
+
+/// 把 FAT 的日期和时间转为 Unix 时间，单位为秒
+fn unix_time(date: u16, time: u16) -> u64 {
+    if date == 0 {
+        return 0;
+    }
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0xF).clamp(1, 12) as i64;
+    let day = (date & 0x1F).max(1) as i64;
+    // 从 3 月开始计算一年中的天数，闰日在年末
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let seconds =
+        (time >> 11) as i64 * 3600 + ((time >> 5) & 0x3F) as i64 * 60 + (time & 0x1F) as i64 * 2;
+    (days * 86400 + seconds) as u64
+}
+
+/// 读出簇链 `clusters` 上的目录中的所有项，不含 `.` 和 `..`
+pub(crate) fn read_dir(volume: &Volume, clusters: &[u32]) -> Vec<DirEntry> {
+    let mut entries = Vec::new();
+    let mut buf = vec![0u8; volume.cluster_size()];
+    let per_cluster = buf.len() / ENTRY_SZ;
+    let mut long_name: Vec<u16> = Vec::new();
+    let mut long_checksum = None;
+    for &cluster in clusters {
+        volume.read_cluster(cluster, 0, &mut buf);
+        for index in 0..per_cluster {
+            let raw = &buf[index * ENTRY_SZ..(index + 1) * ENTRY_SZ];
+            match raw[0] {
+                // 之后没有目录项了
+                0x00 => return entries,
+                // 已删除
+                0xE5 => {
+                    long_checksum = None;
+                    continue;
+                }
+                _ => {}
+            }
+            let attr = raw[11];
+            if attr & 0x3F == ATTR_LONG_NAME {
+                let order = (raw[0] & 0x1F) as usize;
+                // 长文件名的各项倒序存放，最后一项先出现
+                if raw[0] & 0x40 != 0 {
+                    long_name = vec![0xFFFF; order * 13];
+                    long_checksum = Some(raw[13]);
+                }
+                if order == 0 || order * 13 > long_name.len() || long_checksum != Some(raw[13]) {
+                    long_checksum = None;
+                    continue;
+                }
+                for (i, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                    long_name[(order - 1) * 13 + i] = u16_at(raw, *offset);
+                }
+                continue;
+            }
+            let long = long_checksum.take() == Some(checksum(&raw[..11]));
+            if attr & ATTR_VOLUME_ID != 0 || raw[0] == b'.' {
+                continue;
+            }
+            let name = if long {
+                let len = long_name
+                    .iter()
+                    .position(|c| *c == 0 || *c == 0xFFFF)
+                    .unwrap_or(long_name.len());
+                char::decode_utf16(long_name[..len].iter().copied())
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            } else {
+                short_name(raw)
+            };
+            entries.push(DirEntry {
+                name,
+                attr,
+                first_cluster: (u16_at(raw, 20) as u32) << 16 | u16_at(raw, 26) as u32,
+                size: u32_at(raw, 28),
+                crtime: unix_time(u16_at(raw, 16), u16_at(raw, 14)),
+                mtime: unix_time(u16_at(raw, 24), u16_at(raw, 22)),
+                atime: unix_time(u16_at(raw, 18), 0),
+                location: (cluster as usize * per_cluster + index) as u64,
+            });
+        }
+    }
+    entries
+}
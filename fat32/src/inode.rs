@@ -0,0 +1,143 @@
+use super::dir::{read_dir, DirEntry, ATTR_DIRECTORY};
+use super::Volume;
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+use rcore_vfs::{FileType, Inode, Metadata};
+use spin::Once;
+
+/// 根目录的 inode 编号，其他文件用目录项的位置编号，不会与它重复
+const ROOT_INO: u64 = 1;
+
+/// FAT32 卷上的文件或目录
+pub struct FatInode {
+    volume: Arc<Volume>,
+    ino: u64,
+    attr: u8,
+    first_cluster: u32,
+    size: u32,
+    crtime: u64,
+    mtime: u64,
+    atime: u64,
+    /// 簇链，第一次读时从 FAT 表中读出
+    clusters: Once<Vec<u32>>,
+}
+
+impl FatInode {
+    /// 卷的根目录
+    pub(crate) fn root(volume: &Arc<Volume>) -> Self {
+        Self {
+            volume: volume.clone(),
+            ino: ROOT_INO,
+            attr: ATTR_DIRECTORY,
+            first_cluster: volume.root_cluster,
+            size: 0,
+            crtime: 0,
+            mtime: 0,
+            atime: 0,
+            clusters: Once::new(),
+        }
+    }
+
+    /// 目录项指向的文件或目录
+    fn from_entry(volume: &Arc<Volume>, entry: DirEntry) -> Self {
+        Self {
+            volume: volume.clone(),
+            ino: entry.location,
+            attr: entry.attr,
+            first_cluster: entry.first_cluster,
+            size: entry.size,
+            crtime: entry.crtime,
+            mtime: entry.mtime,
+            atime: entry.atime,
+            clusters: Once::new(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn clusters(&self) -> &[u32] {
+        self.clusters
+            .call_once(|| self.volume.chain(self.first_cluster))
+    }
+
+    /// 目录中的所有项，不是目录时为空
+    fn entries(&self) -> Vec<DirEntry> {
+        if self.is_dir() {
+            read_dir(&self.volume, self.clusters())
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Metadata {
+        // 卷只读，权限位中没有写权限
+        let (type_, nlink, size, mode) = if self.is_dir() {
+            let size = self.clusters().len() * self.volume.cluster_size();
+            (FileType::Directory, 2, size as u64, 0o555)
+        } else {
+            (FileType::File, 1, self.size as u64, 0o444)
+        };
+        Metadata {
+            ino: self.ino,
+            type_,
+            nlink,
+            size,
+            mode,
+            atime: self.atime,
+            mtime: self.mtime,
+            crtime: self.crtime,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.size as usize;
+        if self.is_dir() || offset >= size {
+            return 0;
+        }
+        let end = size.min(offset + buf.len());
+        let cluster_size = self.volume.cluster_size();
+        let clusters = self.clusters();
+        let mut pos = offset;
+        while pos < end {
+            // 簇链比文件短时只读到链尾
+            let cluster = match clusters.get(pos / cluster_size) {
+                Some(cluster) => *cluster,
+                None => break,
+            };
+            let start = pos % cluster_size;
+            let len = (cluster_size - start).min(end - pos);
+            self.volume
+                .read_cluster(cluster, start, &mut buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        pos - offset
+    }
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.entries()
+            .into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .map(|entry| Arc::new(FatInode::from_entry(&self.volume, entry)) as Arc<dyn Inode>)
+    }
+
+    fn readdir(&self) -> Option<Vec<String>> {
+        if !self.is_dir() {
+            return None;
+        }
+        let mut names = Vec::from([String::from("."), String::from("..")]);
+        names.extend(self.entries().into_iter().map(|entry| entry.name));
+        Some(names)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
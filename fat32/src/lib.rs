@@ -0,0 +1,403 @@
+//! 只读的 FAT32 文件系统
+//!
+//! 可以读取 `mkfs.vfat`、mtools 等宿主机工具制作的镜像，支持长文件名，按名字查找时不区分大小写。
+//! 镜像不能有分区表，扇区大小需为 512 字节，写操作都会失败。
+
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+extern crate alloc;
+
+mod dir;
+mod inode;
+
+pub use inode::FatInode;
+
+use alloc::{sync::Arc, vec::Vec};
+use easy_fs::{BlockDevice, BLOCK_SZ};
+use rcore_vfs::{FileSystem, Inode};
+use spin::Mutex;
+
+/// FAT 表项中表示簇链结束的最小值
+const CLUSTER_END: u32 = 0x0FFF_FFF8;
+
+/// FAT 表项的有效位
+const CLUSTER_MASK: u32 = 0x0FFF_FFFF;
+
+/// 一个 FAT32 卷
+pub struct Fat32 {
+    volume: Arc<Volume>,
+}
+
+/// 卷的参数，从引导扇区读出
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    /// 每簇扇区数
+    sectors_per_cluster: u32,
+    /// 第一个 FAT 表的起始扇区
+    fat_start: u32,
+    /// 2 号簇的起始扇区
+    data_start: u32,
+    /// 根目录的起始簇
+    root_cluster: u32,
+    /// 数据区的簇数
+    cluster_count: u32,
+    /// 最近读过的 FAT 扇区，顺序读一条簇链时多数表项在同一个扇区
+    fat_cache: Mutex<Option<(u32, [u8; BLOCK_SZ])>>,
+}
+
+/// 读出小端序的 u16
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+/// 读出小端序的 u32
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+impl Fat32 {
+    /// 检查引导扇区，是 FAT32 卷时打开它，否则返回 `None`
+    pub fn open(device: Arc<dyn BlockDevice>) -> Option<Self> {
+        let mut boot = [0u8; BLOCK_SZ];
+        device.read_block(0, &mut boot);
+        if boot[510] != 0x55 || boot[511] != 0xAA || u16_at(&boot, 11) as usize != BLOCK_SZ {
+            return None;
+        }
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved_sectors = u16_at(&boot, 14) as u32;
+        let fat_count = boot[16] as u32;
+        // FAT32 的根目录不在固定区域，FAT12/16 的表长字段为 0
+        if !sectors_per_cluster.is_power_of_two()
+            || reserved_sectors == 0
+            || fat_count == 0
+            || u16_at(&boot, 17) != 0
+            || u16_at(&boot, 22) != 0
+        {
+            return None;
+        }
+        let total_sectors = match u16_at(&boot, 19) {
+            0 => u32_at(&boot, 32),
+            sectors => sectors as u32,
+        };
+        let fat_start = reserved_sectors;
+        let fat_size = u32_at(&boot, 36);
+        // 引导扇区不可信，算出的数据区位置溢出时不是合法的卷
+        let data_start = fat_count
+            .checked_mul(fat_size)
+            .and_then(|fats| fats.checked_add(fat_start))?;
+        if fat_size == 0 || total_sectors <= data_start {
+            return None;
+        }
+        // 簇数还不能超过 FAT 表能记录的表项数，0 号和 1 号表项不对应簇
+        let fat_entries = fat_size as u64 * (BLOCK_SZ / 4) as u64 - 2;
+        let cluster_count = ((total_sectors - data_start) / sectors_per_cluster) as u64;
+        let volume = Volume {
+            device,
+            sectors_per_cluster,
+            fat_start,
+            data_start,
+            root_cluster: u32_at(&boot, 44),
+            cluster_count: cluster_count.min(fat_entries) as u32,
+            fat_cache: Mutex::new(None),
+        };
+        if !volume.is_data_cluster(volume.root_cluster) {
+            return None;
+        }
+        Some(Self {
+            volume: Arc::new(volume),
+        })
+    }
+}
+
+impl FileSystem for Fat32 {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        Arc::new(FatInode::root(&self.volume))
+    }
+
+    fn sync(&self) {}
+}
+
+impl Volume {
+    /// 每簇字节数
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * BLOCK_SZ
+    }
+
+    /// `cluster` 是否是数据区中的簇
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.cluster_count
+    }
+
+    /// 簇链中 `cluster` 的下一簇，链结束或表项损坏时返回 `None`
+    fn next_cluster(&self, cluster: u32) -> Option<u32> {
+        let offset = cluster as usize * 4;
+        let sector = self.fat_start + (offset / BLOCK_SZ) as u32;
+        let mut cache = self.fat_cache.lock();
+        let data = match &mut *cache {
+            Some((cached, data)) if *cached == sector => data,
+            cache => {
+                let mut data = [0u8; BLOCK_SZ];
+                self.device.read_block(sector as usize, &mut data);
+                &mut cache.insert((sector, data)).1
+            }
+        };
+        let next = u32_at(data, offset % BLOCK_SZ) & CLUSTER_MASK;
+        if next < CLUSTER_END && self.is_data_cluster(next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// 从 `first` 开始的簇链，链上有环时在簇数用完后截断
+    fn chain(&self, first: u32) -> Vec<u32> {
+        let mut clusters = Vec::new();
+        let mut cluster = Some(first).filter(|cluster| self.is_data_cluster(*cluster));
+        while let Some(current) = cluster {
+            if clusters.len() as u32 >= self.cluster_count {
+                break;
+            }
+            clusters.push(current);
+            cluster = self.next_cluster(current);
+        }
+        clusters
+    }
+
+    /// 读出簇 `cluster` 中从 `offset` 开始的数据，不能跨簇
+    fn read_cluster(&self, cluster: u32, offset: usize, buf: &mut [u8]) {
+        let first_sector = self.data_start + (cluster - 2) * self.sectors_per_cluster;
+        let mut sector_data = [0u8; BLOCK_SZ];
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done;
+            let sector = first_sector + (pos / BLOCK_SZ) as u32;
+            let start = pos % BLOCK_SZ;
+            let len = (BLOCK_SZ - start).min(buf.len() - done);
+            self.device.read_block(sector as usize, &mut sector_data);
+            buf[done..done + len].copy_from_slice(&sector_data[start..start + len]);
+            done += len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{string::String, vec};
+    use dir::{checksum, LONG_NAME_OFFSETS};
+    use rcore_vfs::FileType;
+
+    /// 内存中的块设备
+    struct RamDisk(Mutex<Vec<[u8; BLOCK_SZ]>>);
+
+    impl BlockDevice for RamDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock()[block_id]);
+        }
+
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock()[block_id].copy_from_slice(buf);
+        }
+    }
+
+    /// 镜像的扇区数
+    const SECTORS: usize = 64;
+    /// FAT 表所在扇区
+    const FAT_SECTOR: usize = 2;
+    /// 2 号簇所在扇区，每簇一个扇区
+    const DATA_SECTOR: usize = 3;
+
+    /// 2000-01-01 的 FAT 日期
+    const DATE_2000: u16 = (20 << 9) | (1 << 5) | 1;
+
+    /// 只有引导扇区的空镜像：每簇一个扇区，保留 2 个扇区，一个 1 扇区的 FAT 表，根目录在 2 号簇
+    fn boot_sector() -> Vec<[u8; BLOCK_SZ]> {
+        let mut disk = vec![[0u8; BLOCK_SZ]; SECTORS];
+        let boot = &mut disk[0];
+        boot[11..13].copy_from_slice(&(BLOCK_SZ as u16).to_le_bytes());
+        boot[13] = 1;
+        boot[14..16].copy_from_slice(&2u16.to_le_bytes());
+        boot[16] = 1;
+        boot[32..36].copy_from_slice(&(SECTORS as u32).to_le_bytes());
+        boot[36..40].copy_from_slice(&1u32.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        disk
+    }
+
+    /// 把 FAT 表中 `cluster` 的表项设为 `next`
+    fn set_fat(disk: &mut [[u8; BLOCK_SZ]], cluster: usize, next: u32) {
+        disk[FAT_SECTOR][cluster * 4..cluster * 4 + 4].copy_from_slice(&next.to_le_bytes());
+    }
+
+    /// 短目录项
+    fn short_entry(name: &[u8; 11], flags: u8, cluster: u32, size: u32) -> [u8; 32] {
+        let mut raw = [0u8; 32];
+        raw[..11].copy_from_slice(name);
+        raw[11] = 0x20;
+        raw[12] = flags;
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[24..26].copy_from_slice(&DATE_2000.to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// `name` 的长文件名目录项，按在目录中存放的顺序排列
+    fn long_entries(name: &str, short_name: &[u8; 11]) -> Vec<[u8; 32]> {
+        let mut chars: Vec<u16> = name.encode_utf16().collect();
+        // 名字没有占满最后一项时以 0 结尾，其余位置填 0xFFFF
+        let count = chars.len().div_ceil(13);
+        if chars.len() < count * 13 {
+            chars.push(0);
+        }
+        chars.resize(count * 13, 0xFFFF);
+        (1..=count)
+            .rev()
+            .map(|order| {
+                let mut raw = [0u8; 32];
+                raw[0] = order as u8 | if order == count { 0x40 } else { 0 };
+                raw[11] = 0x0F;
+                raw[13] = checksum(short_name);
+                for (i, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                    let c = chars[(order - 1) * 13 + i];
+                    raw[*offset..*offset + 2].copy_from_slice(&c.to_le_bytes());
+                }
+                raw
+            })
+            .collect()
+    }
+
+    /// 测试用的卷：
+    ///
+    /// - `UPPER.TXT`：2 号簇之后的 3 号簇，5 个字节；
+    /// - `lower.txt`：短名带小写标志，空文件；
+    /// - `A long file name.txt`：长文件名，4、5、6 号簇，1200 个字节；
+    /// - `LOOP.BIN`：7、8 号簇互相指向，簇链有环；
+    /// - 一个已删除的目录项。
+    fn image() -> Arc<dyn BlockDevice> {
+        let mut disk = boot_sector();
+        set_fat(&mut disk, 0, 0x0FFF_FFF8);
+        set_fat(&mut disk, 1, 0x0FFF_FFFF);
+        set_fat(&mut disk, 2, 0x0FFF_FFFF);
+        set_fat(&mut disk, 3, 0x0FFF_FFFF);
+        set_fat(&mut disk, 4, 5);
+        set_fat(&mut disk, 5, 6);
+        set_fat(&mut disk, 6, 0x0FFF_FFFF);
+        set_fat(&mut disk, 7, 8);
+        set_fat(&mut disk, 8, 7);
+
+        let long_short = *b"ALONGF~1TXT";
+        let mut entries = vec![
+            short_entry(b"UPPER   TXT", 0, 3, 5),
+            short_entry(b"LOWER   TXT", 0x18, 0, 0),
+        ];
+        let mut deleted = short_entry(b"GONE    TXT", 0, 3, 5);
+        deleted[0] = 0xE5;
+        entries.push(deleted);
+        entries.extend(long_entries("A long file name.txt", &long_short));
+        entries.push(short_entry(&long_short, 0, 4, 1200));
+        entries.push(short_entry(b"LOOP    BIN", 0, 7, 4 * BLOCK_SZ as u32));
+        for (i, raw) in entries.iter().enumerate() {
+            disk[DATA_SECTOR][i * 32..(i + 1) * 32].copy_from_slice(raw);
+        }
+
+        disk[DATA_SECTOR + 1][..5].copy_from_slice(b"hello");
+        for (i, byte) in (0..1200).map(|i| (i % 251) as u8).enumerate() {
+            disk[DATA_SECTOR + 2 + i / BLOCK_SZ][i % BLOCK_SZ] = byte;
+        }
+        disk[DATA_SECTOR + 5].fill(b'a');
+        disk[DATA_SECTOR + 6].fill(b'b');
+        Arc::new(RamDisk(Mutex::new(disk)))
+    }
+
+    fn read_all(inode: &Arc<dyn Inode>) -> Vec<u8> {
+        let mut buf = vec![0u8; inode.metadata().size as usize];
+        let len = inode.read_at(0, &mut buf);
+        buf.truncate(len);
+        buf
+    }
+
+    #[test]
+    fn short_and_long_names() {
+        let root = Fat32::open(image()).unwrap().root_inode();
+        let names = root.readdir().unwrap();
+        assert_eq!(
+            names,
+            [
+                ".",
+                "..",
+                "UPPER.TXT",
+                "lower.txt",
+                "A long file name.txt",
+                "LOOP.BIN"
+            ]
+            .map(String::from)
+        );
+        // 查找不区分大小写
+        let upper = root.find("upper.txt").unwrap();
+        assert_eq!(read_all(&upper), b"hello");
+        let stat = upper.metadata();
+        assert_eq!(stat.type_, FileType::File);
+        assert_eq!(stat.mtime, 946_684_800);
+        assert!(root.find("a LONG file NAME.TXT").is_some());
+        assert!(root.find("lower.txt").unwrap().metadata().size == 0);
+        assert!(root.find("gone.txt").is_none());
+        // 只读
+        assert_eq!(upper.write_at(0, b"x"), 0);
+    }
+
+    #[test]
+    fn multi_cluster_file() {
+        let root = Fat32::open(image()).unwrap().root_inode();
+        let file = root.find("A long file name.txt").unwrap();
+        let expected: Vec<u8> = (0..1200).map(|i| (i % 251) as u8).collect();
+        assert_eq!(read_all(&file), expected);
+        // 跨簇读取中间的一段
+        let mut buf = [0u8; 100];
+        assert_eq!(file.read_at(470, &mut buf), 100);
+        assert_eq!(buf[..], expected[470..570]);
+        assert_eq!(file.read_at(1150, &mut buf), 50);
+        assert_eq!(file.read_at(1200, &mut buf), 0);
+    }
+
+    #[test]
+    fn cyclic_chain_terminates() {
+        let root = Fat32::open(image()).unwrap().root_inode();
+        let file = root.find("loop.bin").unwrap();
+        let data = read_all(&file);
+        assert_eq!(data.len(), 4 * BLOCK_SZ);
+        assert!(data[..BLOCK_SZ].iter().all(|b| *b == b'a'));
+        assert!(data[BLOCK_SZ..2 * BLOCK_SZ].iter().all(|b| *b == b'b'));
+    }
+
+    #[test]
+    fn rejects_bad_boot_sectors() {
+        let open = |disk: Vec<[u8; BLOCK_SZ]>| Fat32::open(Arc::new(RamDisk(Mutex::new(disk))));
+        assert!(open(boot_sector()).is_some());
+        // 没有签名
+        let mut disk = boot_sector();
+        disk[0][510] = 0;
+        assert!(open(disk).is_none());
+        // FAT 表的总长度溢出
+        let mut disk = boot_sector();
+        disk[0][16] = 2;
+        disk[0][36..40].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(open(disk).is_none());
+        // FAT 表长度为 0
+        let mut disk = boot_sector();
+        disk[0][36..40].copy_from_slice(&0u32.to_le_bytes());
+        assert!(open(disk).is_none());
+        // 根目录不在数据区
+        let mut disk = boot_sector();
+        disk[0][44..48].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(open(disk).is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use alloc::{sync::Arc, vec::Vec};
+
+/// 按需加载的页的数据来源。
+pub trait BackingFile: Send + Sync {
+    /// 从 `offset` 处读出数据填满 `buf`，返回读出的字节数，不足的部分由调用者填零。
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+}
+
+impl BackingFile for &'static [u8] {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        read_slice(self, offset, buf)
+    }
+}
+
+/// 读入内存的文件，例如 `exec` 时读入的 ELF 文件。
+impl BackingFile for Vec<u8> {
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        read_slice(self, offset, buf)
+    }
+}
+
+/// 从 `data` 的 `offset` 处读出数据填写 `buf`，返回读出的字节数。
+fn read_slice(data: &[u8], offset: usize, buf: &mut [u8]) -> usize {
+    let data = data.get(offset..).unwrap_or(&[]);
+    let len = buf.len().min(data.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    len
+}
+
+/// 虚拟地址块的内容在文件中的位置。
+#[derive(Clone)]
+pub struct Backing {
+    /// 数据所在的文件
+    pub file: Arc<dyn BackingFile>,
+    /// 数据在文件中的偏移
+    pub offset: usize,
+    /// 数据的字节数
+    pub len: usize,
+    /// 数据在虚拟地址块中的起始偏移，之前和之后的部分填零
+    pub page_offset: usize,
+}
+
+impl Backing {
+    /// 虚拟地址块的起点后移 `bytes` 字节之后，数据在文件中的位置。
+    pub(crate) fn skip(&self, bytes: usize) -> Self {
+        if bytes <= self.page_offset {
+            Self {
+                page_offset: self.page_offset - bytes,
+                ..self.clone()
+            }
+        } else {
+            let skip = (bytes - self.page_offset).min(self.len);
+            Self {
+                file: self.file.clone(),
+                offset: self.offset + skip,
+                len: self.len - skip,
+                page_offset: 0,
+            }
+        }
+    }
+
+    /// 用数据填写虚拟地址块中 `base` 处开始的 `page`，`page` 已经填零。
+    pub(crate) fn fill(&self, base: usize, page: &mut [u8]) {
+        let start = base.max(self.page_offset);
+        let end = (base + page.len()).min(self.page_offset + self.len);
+        if start < end {
+            self.file.read_at(
+                self.offset + (start - self.page_offset),
+                &mut page[start - base..end - base],
+            );
+        }
+    }
+}
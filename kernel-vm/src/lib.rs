@@ -3,16 +3,28 @@
 #![no_std]
 #![deny(warnings, missing_docs)]
 
+mod backing;
 mod space;
 
+extern crate alloc;
+
 pub extern crate page_table;
-pub use space::AddressSpace;
+pub use backing::{Backing, BackingFile};
+pub use space::{AddressSpace, Area, AreaKind};
 
 use core::ptr::NonNull;
 use page_table::{Pte, VmFlags, VmMeta, PPN};
 
 /// 物理页管理。
+///
+/// 物理页可以被多个地址空间共享，由页管理器维护每个物理页的引用计数。
 pub trait PageManager<Meta: VmMeta> {
+    /// 页表项中表示可写的属性。
+    const WRITABLE: VmFlags<Meta>;
+
+    /// 标记写时复制页的属性，需要是硬件忽略的位。
+    const COW: VmFlags<Meta>;
+
     /// 新建根页表页。
     fn new_root() -> Self;
 
@@ -37,7 +49,15 @@ pub trait PageManager<Meta: VmMeta> {
     /// 为地址空间分配 `len` 个物理页。
     fn allocate(&mut self, len: usize, flags: &mut VmFlags<Meta>) -> NonNull<u8>;
 
+    /// 物理页 `ppn` 被又一个地址空间共享，增加它的引用计数。
+    fn share(&mut self, ppn: PPN<Meta>);
+
+    /// 获取物理页 `ppn` 的引用计数。
+    fn ref_count(&self, ppn: PPN<Meta>) -> usize;
+
     /// 从地址空间释放 `pte` 指示的 `len` 个物理页。
+    ///
+    /// 每个物理页的引用计数减一，减到零的页被回收，返回回收的页数。
     fn deallocate(&mut self, pte: Pte<Meta>, len: usize) -> usize;
 
     /// 释放根页表。
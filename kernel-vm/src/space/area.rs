@@ -0,0 +1,109 @@
+use crate::Backing;
+use alloc::sync::Arc;
+use core::{fmt, ops::Range};
+use page_table::{VmFlags, VmMeta, VPN};
+
+/// 虚拟地址块的用途。
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AreaKind {
+    /// 内核的代码、数据和设备
+    Kernel,
+    /// 从 ELF 文件加载的段
+    Elf,
+    /// 栈
+    Stack,
+    /// 堆
+    Heap,
+    /// `mmap` 映射的内存
+    Mmap,
+}
+
+/// 虚拟地址块。
+#[derive(Clone)]
+pub struct Area<Meta: VmMeta> {
+    /// 虚页号范围
+    pub range: Range<VPN<Meta>>,
+    /// 块中页的属性
+    pub flags: VmFlags<Meta>,
+    /// 块的用途
+    pub kind: AreaKind,
+    /// 块中的页是否第一次访问时才分配
+    pub lazy: bool,
+    /// 按需分配的页的数据来源，没有来源的部分填零
+    pub backing: Option<Backing>,
+}
+
+impl<Meta: VmMeta> Area<Meta> {
+    /// 块是否与 `range` 相交。
+    #[inline]
+    pub fn overlaps(&self, range: &Range<VPN<Meta>>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+
+    /// 在 `at` 处把块拆成两块，自身保留前一块，返回后一块。
+    pub(super) fn split_off(&mut self, at: VPN<Meta>) -> Self {
+        let skip = (at.val() - self.range.start.val()) << Meta::PAGE_BITS;
+        let tail = Self {
+            range: at..self.range.end,
+            flags: self.flags,
+            kind: self.kind,
+            lazy: self.lazy,
+            backing: self.backing.as_ref().map(|backing| backing.skip(skip)),
+        };
+        self.range.end = at;
+        tail
+    }
+
+    /// 把紧接在后面的 `next` 并入自身，属性、用途或数据来源接不上时返回 `false`，不做任何修改。
+    pub(super) fn merge(&mut self, next: &Self) -> bool {
+        if self.range.end != next.range.start
+            || self.flags != next.flags
+            || self.kind != next.kind
+            || self.lazy != next.lazy
+        {
+            return false;
+        }
+        let size = (self.range.end.val() - self.range.start.val()) << Meta::PAGE_BITS;
+        let backing = match (&self.backing, &next.backing) {
+            (None, None) => None,
+            // 前一块的数据一直到块末尾，后一块的数据从块开头紧接着开始
+            (Some(a), Some(b))
+                if Arc::ptr_eq(&a.file, &b.file)
+                    && b.page_offset == 0
+                    && a.page_offset <= size
+                    && a.page_offset + a.len >= size
+                    && a.offset + (size - a.page_offset) == b.offset =>
+            {
+                Some(Backing {
+                    len: size - a.page_offset + b.len,
+                    ..a.clone()
+                })
+            }
+            _ => return false,
+        };
+        self.range.end = next.range.end;
+        self.backing = backing;
+        true
+    }
+}
+
+impl<Meta: VmMeta> fmt::Display for Area<Meta> {
+    /// 按 `起始地址-结束地址 属性 用途` 的格式显示，按需分配的块还显示数据在文件中的位置。
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#012x}-{:#012x} ",
+            self.range.start.base().val(),
+            self.range.end.base().val()
+        )?;
+        Meta::fmt_flags(f, self.flags.val())?;
+        write!(f, " {:?}", self.kind)?;
+        if self.lazy {
+            write!(f, " lazy")?;
+        }
+        if let Some(backing) = &self.backing {
+            write!(f, " file {:#x}+{:#x}", backing.offset, backing.len)?;
+        }
+        Ok(())
+    }
+}
@@ -1,19 +1,20 @@
+mod area;
 mod mapper;
+mod updater;
 mod visitor;
 
-extern crate alloc;
-
-use crate::PageManager;
+use crate::{Backing, PageManager};
 use alloc::vec::Vec;
+pub use area::{Area, AreaKind};
 use core::{fmt, ops::Range, ptr::NonNull};
 use mapper::Mapper;
-use page_table::{PageTable, PageTableFormatter, Pos, VAddr, VmFlags, VmMeta, PPN, VPN};
+use page_table::{PageTable, PageTableFormatter, Pos, Pte, VAddr, VmFlags, VmMeta, PPN, VPN};
+use updater::Updater;
 use visitor::Visitor;
 
 /// 地址空间。
 pub struct AddressSpace<Meta: VmMeta, M: PageManager<Meta>> {
-    /// 虚拟地址块
-    pub areas: Vec<Range<VPN<Meta>>>,
+    areas: Vec<Area<Meta>>,
     page_manager: M,
 }
 
@@ -39,9 +40,59 @@ impl<Meta: VmMeta, M: PageManager<Meta>> AddressSpace<Meta, M> {
         unsafe { PageTable::from_root(self.page_manager.root_ptr()) }
     }
 
+    /// 地址空间中的虚拟地址块，按起始地址排序。
+    #[inline]
+    pub fn areas(&self) -> &[Area<Meta>] {
+        &self.areas
+    }
+
+    /// 查找包含 `vpn` 的虚拟地址块。
+    pub fn area(&self, vpn: VPN<Meta>) -> Option<&Area<Meta>> {
+        let i = self.areas.partition_point(|area| area.range.end <= vpn);
+        self.areas.get(i).filter(|area| area.range.contains(&vpn))
+    }
+
+    /// 与 `range` 相交的虚拟地址块，按起始地址排序。
+    pub fn areas_in(&self, range: Range<VPN<Meta>>) -> impl Iterator<Item = &Area<Meta>> {
+        let i = self
+            .areas
+            .partition_point(|area| area.range.end <= range.start);
+        self.areas[i..]
+            .iter()
+            .take_while(move |area| area.range.start < range.end)
+    }
+
+    /// 从 `start` 开始向上寻找 `count` 页不与任何虚拟地址块相交的虚页号范围。
+    pub fn find_free(&self, start: VPN<Meta>, count: usize) -> Option<Range<VPN<Meta>>> {
+        let mut range = start..start + count;
+        while let Some(area) = self.areas.iter().find(|area| area.overlaps(&range)) {
+            range = area.range.end..area.range.end + count;
+        }
+        Some(range).filter(|range| range.end.val() <= VPN::<Meta>::MAX.val() + 1)
+    }
+
     /// 向地址空间增加映射关系。
-    pub fn map_extern(&mut self, range: Range<VPN<Meta>>, pbase: PPN<Meta>, flags: VmFlags<Meta>) {
-        self.areas.push(range.start..range.end);
+    ///
+    /// `range` 不能与已有的虚拟地址块相交。
+    pub fn map_extern(
+        &mut self,
+        range: Range<VPN<Meta>>,
+        pbase: PPN<Meta>,
+        flags: VmFlags<Meta>,
+        kind: AreaKind,
+    ) {
+        assert!(self.insert(Area {
+            range: range.clone(),
+            flags,
+            kind,
+            lazy: false,
+            backing: None,
+        }));
+        self.map_pages(range, pbase, flags);
+    }
+
+    /// 在页表中建立映射关系，不记录虚拟地址块。
+    fn map_pages(&mut self, range: Range<VPN<Meta>>, pbase: PPN<Meta>, flags: VmFlags<Meta>) {
         let count = range.end.val() - range.start.val();
         let mut root = self.root();
         let mut mapper = Mapper::new(self, pbase..pbase + count, flags);
@@ -53,82 +104,410 @@ impl<Meta: VmMeta, M: PageManager<Meta>> AddressSpace<Meta, M> {
     }
 
     /// 分配新的物理页，拷贝数据并建立映射。
+    ///
+    /// 物理页逐页分配，与回收时逐页释放一致。
+    /// `range` 不能与已有的虚拟地址块相交。
     pub fn map(
         &mut self,
         range: Range<VPN<Meta>>,
         data: &[u8],
         offset: usize,
-        mut flags: VmFlags<Meta>,
+        flags: VmFlags<Meta>,
+        kind: AreaKind,
     ) {
         let count = range.end.val() - range.start.val();
-        let size = count << Meta::PAGE_BITS;
-        assert!(size >= data.len() + offset);
-        let page = self.page_manager.allocate(count, &mut flags);
-        unsafe {
-            use core::slice::from_raw_parts_mut as slice;
-            let mut ptr = page.as_ptr();
-            slice(ptr, offset).fill(0);
-            ptr = ptr.add(offset);
-            slice(ptr, data.len()).copy_from_slice(data);
-            ptr = ptr.add(data.len());
-            slice(ptr, page.as_ptr().add(size).offset_from(ptr) as _).fill(0);
+        let page_size = 1 << Meta::PAGE_BITS;
+        assert!(count * page_size >= data.len() + offset);
+        assert!(self.insert(Area {
+            range: range.clone(),
+            flags,
+            kind,
+            lazy: false,
+            backing: None,
+        }));
+        for (i, vpn) in (range.start.val()..range.end.val()).enumerate() {
+            let mut page_flags = flags;
+            let page = self.page_manager.allocate(1, &mut page_flags);
+            let bytes = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), page_size) };
+            bytes.fill(0);
+            // 数据落在这一页里的部分
+            let base = i * page_size;
+            let start = offset.max(base);
+            let end = (offset + data.len()).min(base + page_size);
+            if start < end {
+                bytes[start - base..end - base]
+                    .copy_from_slice(&data[start - offset..end - offset]);
+            }
+            let ppn = self.page_manager.v_to_p(page);
+            self.map_pages(VPN::new(vpn)..VPN::new(vpn + 1), ppn, page_flags);
         }
-        self.map_extern(range, self.page_manager.v_to_p(page), flags)
+    }
+
+    /// 增加按需分配的虚拟地址块，其中的页第一次被访问时才分配物理页。
+    ///
+    /// 页的内容从 `backing` 读出，没有数据来源的部分填零。
+    /// `range` 为空或与已有的虚拟地址块相交时失败，不做任何修改。
+    pub fn map_lazy(
+        &mut self,
+        range: Range<VPN<Meta>>,
+        backing: Option<Backing>,
+        flags: VmFlags<Meta>,
+        kind: AreaKind,
+    ) -> bool {
+        self.insert(Area {
+            range,
+            flags,
+            kind,
+            lazy: true,
+            backing,
+        })
+    }
+
+    /// 撤销 `range` 中的映射。
+    ///
+    /// 与 `range` 相交的虚拟地址块被裁剪或拆成两块，属于这个地址空间的物理页和不再使用的页表页被释放。
+    /// 页表修改后需要刷新 TLB 才能生效。
+    pub fn unmap(&mut self, range: Range<VPN<Meta>>) {
+        if range.start >= range.end {
+            return;
+        }
+        let mut areas = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            if area.overlaps(&range) {
+                if area.range.start < range.start {
+                    let rest = area.split_off(range.start);
+                    areas.push(area);
+                    area = rest;
+                }
+                if range.end < area.range.end {
+                    areas.push(area.split_off(range.end));
+                }
+            } else {
+                areas.push(area);
+            }
+        }
+        self.areas = areas;
+        let root = self.root();
+        self.unmap_table(root, &(range.start.val()..range.end.val()));
+    }
+
+    /// 把 `range` 中页的属性改为 `flags`。
+    ///
+    /// `range` 中有不属于任何虚拟地址块的页时失败，不做任何修改。
+    /// 写时复制的页仍保持只读，第一次写入时再分开。
+    /// 页表修改后需要刷新 TLB 才能生效。
+    pub fn protect(&mut self, range: Range<VPN<Meta>>, flags: VmFlags<Meta>) -> bool {
+        let mut vpn = range.start;
+        while vpn < range.end {
+            match self.area(vpn) {
+                Some(area) => vpn = area.range.end,
+                None => return false,
+            }
+        }
+        let mut areas = Vec::new();
+        for mut area in core::mem::take(&mut self.areas) {
+            if area.overlaps(&range) {
+                if area.range.start < range.start {
+                    let rest = area.split_off(range.start);
+                    areas.push(area);
+                    area = rest;
+                }
+                let tail = if range.end < area.range.end {
+                    Some(area.split_off(range.end))
+                } else {
+                    None
+                };
+                let old = area.flags;
+                area.flags = flags;
+                let mut vpn = area.range.start;
+                while vpn < area.range.end {
+                    if let Some(pte) = self.find(vpn) {
+                        // 保留页管理器设置的属性，只替换虚拟地址块的属性
+                        let mut new = (pte.flags() ^ (pte.flags() & old)) | flags;
+                        if new.contains(M::COW) && new.contains(M::WRITABLE) {
+                            new ^= M::WRITABLE;
+                        }
+                        self.update(vpn, new.build_pte(pte.ppn()));
+                    }
+                    vpn += 1;
+                }
+                areas.push(area);
+                areas.extend(tail);
+            } else {
+                areas.push(area);
+            }
+        }
+        self.areas = areas;
+        self.coalesce();
+        true
     }
 
     /// 检查 `flags` 的属性要求，然后将地址空间中的一个虚地址翻译成当前地址空间中的指针。
-    pub fn translate<T>(&self, addr: VAddr<Meta>, flags: VmFlags<Meta>) -> Option<NonNull<T>> {
+    ///
+    /// 按需分配的页还没有分配时先分配。
+    /// 要求可写时，先把写时复制的页分开，内核写入的数据不会被其他地址空间看到。
+    pub fn translate<T>(&mut self, addr: VAddr<Meta>, flags: VmFlags<Meta>) -> Option<NonNull<T>> {
+        let vpn = addr.floor();
+        let pte = match self.find(vpn) {
+            Some(pte)
+                if flags.contains(M::WRITABLE)
+                    && pte.flags().contains(M::COW)
+                    && self.permits(vpn, flags) =>
+            {
+                self.unshare(vpn, pte)
+            }
+            Some(pte) => pte,
+            None => self.populate(vpn)?,
+        };
+        if !pte.flags().contains(flags) {
+            return None;
+        }
+        Some(unsafe {
+            NonNull::new_unchecked(
+                self.page_manager
+                    .p_to_v::<u8>(pte.ppn())
+                    .as_ptr()
+                    .add(addr.offset())
+                    .cast(),
+            )
+        })
+    }
+
+    /// 检查 `flags` 的属性要求，然后将地址空间中从 `addr` 开始的 `len` 个字节逐页翻译成当前地址空间中的若干段。
+    ///
+    /// 相邻的虚页不一定映射到相邻的物理页，所以每一段都不跨页。
+    pub fn translate_buffer(
+        &mut self,
+        addr: VAddr<Meta>,
+        len: usize,
+        flags: VmFlags<Meta>,
+    ) -> Option<Vec<NonNull<[u8]>>> {
+        let page_size = 1 << Meta::PAGE_BITS;
+        let mut addr = addr.val();
+        let end = addr.checked_add(len)?;
+        let mut segments = Vec::new();
+        while addr < end {
+            let len = (page_size - addr % page_size).min(end - addr);
+            let ptr = self.translate::<u8>(VAddr::new(addr), flags)?;
+            segments.push(NonNull::slice_from_raw_parts(ptr, len));
+            addr += len;
+        }
+        Some(segments)
+    }
+
+    /// 处理以 `flags` 的属性要求访问 `addr` 引起的页异常。
+    ///
+    /// `addr` 落在按需分配的虚拟地址块中还没有分配的页上时，分配物理页并填写内容；
+    /// 写入写时复制的页时，物理页仍被其他地址空间共享就复制一份，否则直接独占，然后恢复写权限。
+    /// 返回 `false` 表示这是非法访问，调用者应结束访问者。
+    /// 页表修改后需要刷新 TLB 才能生效。
+    pub fn handle_page_fault(&mut self, addr: VAddr<Meta>, flags: VmFlags<Meta>) -> bool {
+        let vpn = addr.floor();
+        if !self.permits(vpn, flags) {
+            return false;
+        }
+        match self.find(vpn) {
+            Some(pte) if flags.contains(M::WRITABLE) && pte.flags().contains(M::COW) => {
+                self.unshare(vpn, pte);
+                true
+            }
+            // 页表项已经满足要求的访问不会引起异常
+            Some(_) => false,
+            None => self.populate(vpn).is_some(),
+        }
+    }
+
+    /// 把地址空间中的映射复制到 `new_addrspace`，两者以写时复制的方式共享物理页。
+    ///
+    /// 共享的页在两个地址空间中都改为只读并标记为写时复制，任何一方第一次写入时再分开。
+    /// 只读的页也要标记，之后改为可写时不会写到共享的页上。
+    /// 不属于这个地址空间的页没有引用计数，仍然按虚拟地址块的属性复制一份。
+    /// 按需分配的页还没有分配的，在两个地址空间中分别按需分配。
+    pub fn cloneself(&mut self, new_addrspace: &mut AddressSpace<Meta, M>) {
+        for area in self.areas.clone() {
+            let range = area.range.clone();
+            let area_flags = area.flags;
+            new_addrspace.areas.push(area);
+            let mut vpn = range.start;
+            while vpn < range.end {
+                if let Some(pte) = self.find(vpn) {
+                    let mut flags = pte.flags();
+                    let ppn = if self.page_manager.check_owned(pte) {
+                        if flags.contains(M::WRITABLE) {
+                            flags ^= M::WRITABLE;
+                        }
+                        flags |= M::COW;
+                        self.update(vpn, flags.build_pte(pte.ppn()));
+                        self.page_manager.share(pte.ppn());
+                        pte.ppn()
+                    } else {
+                        flags = area_flags;
+                        let page = new_addrspace.page_manager.allocate(1, &mut flags);
+                        self.copy_page(pte.ppn(), page);
+                        new_addrspace.page_manager.v_to_p(page)
+                    };
+                    new_addrspace.map_pages(vpn..vpn + 1, ppn, flags);
+                }
+                vpn += 1;
+            }
+        }
+    }
+
+    /// 按起始地址把 `area` 插入虚拟地址块列表，并与前后接得上的块合并。
+    ///
+    /// `area` 为空或与已有的块相交时返回 `false`，不做任何修改。
+    fn insert(&mut self, area: Area<Meta>) -> bool {
+        if area.range.start >= area.range.end || self.areas_in(area.range.clone()).next().is_some()
+        {
+            return false;
+        }
+        let mut i = self
+            .areas
+            .partition_point(|a| a.range.start < area.range.start);
+        if i > 0 && self.areas[i - 1].merge(&area) {
+            i -= 1;
+        } else {
+            self.areas.insert(i, area);
+        }
+        if i + 1 < self.areas.len() {
+            let next = self.areas[i + 1].clone();
+            if self.areas[i].merge(&next) {
+                self.areas.remove(i + 1);
+            }
+        }
+        true
+    }
+
+    /// 合并所有前后接得上的虚拟地址块。
+    fn coalesce(&mut self) {
+        let mut areas: Vec<Area<Meta>> = Vec::with_capacity(self.areas.len());
+        for area in core::mem::take(&mut self.areas) {
+            let merged = match areas.last_mut() {
+                Some(last) => last.merge(&area),
+                None => false,
+            };
+            if !merged {
+                areas.push(area);
+            }
+        }
+        self.areas = areas;
+    }
+
+    /// `vpn` 所在的虚拟地址块是否允许以 `flags` 的属性要求访问。
+    fn permits(&self, vpn: VPN<Meta>, flags: VmFlags<Meta>) -> bool {
+        self.area(vpn)
+            .is_some_and(|area| area.flags.contains(flags))
+    }
+
+    /// 查找 `vpn` 处有效的页表项。
+    fn find(&self, vpn: VPN<Meta>) -> Option<Pte<Meta>> {
         let mut visitor = Visitor::new(self);
-        self.root().walk(Pos::new(addr.floor(), 0), &mut visitor);
-        visitor
-            .ans()
-            .filter(|pte| pte.flags().contains(flags))
-            .map(|pte| unsafe {
-                NonNull::new_unchecked(
-                    self.page_manager
-                        .p_to_v::<u8>(pte.ppn())
-                        .as_ptr()
-                        .add(addr.offset())
-                        .cast(),
-                )
-            })
-    }
-
-    /// 遍历地址空间，将其中的地址映射添加进自己的地址空间中，重新分配物理页并拷贝所有数据及代码
-    pub fn cloneself(&self, new_addrspace: &mut AddressSpace<Meta, M>) {
-        let root = self.root();
-        let areas = &self.areas;
-        for (_, range) in areas.iter().enumerate() {
-            let mut visitor = Visitor::new(self);
-            // 虚拟地址块的首地址的 vpn
-            let vpn = range.start;
-            // 利用 visitor 访问页表，并获取这个虚拟地址块的页属性
-            root.walk(Pos::new(vpn, 0), &mut visitor);
-            // 利用 visitor 获取这个虚拟地址块的页属性，以及起始地址
-            let (mut flags, mut data_ptr) = visitor
-                .ans()
-                .filter(|pte| pte.is_valid())
-                .map(|pte| {
-                    (pte.flags(), unsafe {
-                        NonNull::new_unchecked(self.page_manager.p_to_v::<u8>(pte.ppn()).as_ptr())
-                    })
-                })
-                .unwrap();
-            let vpn_range = range.start..range.end;
-            // 虚拟地址块中页数量
-            let count = range.end.val() - range.start.val();
-            let size = count << Meta::PAGE_BITS;
-            // 分配 count 个 flags 属性的物理页面
-            let paddr = new_addrspace.page_manager.allocate(count, &mut flags);
-            let ppn = new_addrspace.page_manager.v_to_p(paddr);
-            unsafe {
-                use core::slice::from_raw_parts_mut as slice;
-                let data = slice(data_ptr.as_mut(), size);
-                let ptr = paddr.as_ptr();
-                slice(ptr, size).copy_from_slice(data);
+        self.root().walk(Pos::new(vpn, 0), &mut visitor);
+        visitor.ans()
+    }
+
+    /// 为按需分配的虚拟地址块中 `vpn` 处的页分配物理页并填写内容，返回新的页表项。
+    ///
+    /// `vpn` 不在按需分配的虚拟地址块中时返回 `None`。
+    fn populate(&mut self, vpn: VPN<Meta>) -> Option<Pte<Meta>> {
+        let area = self.area(vpn).filter(|area| area.lazy)?;
+        let base = (vpn.val() - area.range.start.val()) << Meta::PAGE_BITS;
+        let backing = area.backing.clone();
+        let mut flags = area.flags;
+        let page = self.page_manager.allocate(1, &mut flags);
+        let data = unsafe { core::slice::from_raw_parts_mut(page.as_ptr(), 1 << Meta::PAGE_BITS) };
+        data.fill(0);
+        if let Some(backing) = backing {
+            backing.fill(base, data);
+        }
+        let ppn = self.page_manager.v_to_p(page);
+        self.map_pages(vpn..vpn + 1, ppn, flags);
+        Some(flags.build_pte(ppn))
+    }
+
+    /// 撤销 `table` 中与虚页号范围 `range` 相交的映射，释放属于这个地址空间的物理页和清空的页表页。
+    ///
+    /// 返回 `table` 中是否已经没有有效的页表项。
+    fn unmap_table(&mut self, mut table: PageTable<Meta>, range: &Range<usize>) -> bool {
+        let level = table.level();
+        let pages = Meta::bytes_in_page(level) >> Meta::PAGE_BITS;
+        let base = table.range().start.val();
+        let mut empty = true;
+        for i in 0..1 << Meta::LEVEL_BITS[level] {
+            let pte = table[i];
+            if !pte.is_valid() {
+                continue;
+            }
+            let start = base + i * pages;
+            if start + pages <= range.start || range.end <= start {
+                empty = false;
+            } else if pte.is_leaf() {
+                if self.page_manager.check_owned(pte) {
+                    self.page_manager.deallocate(pte, pages);
+                }
+                table[i] = Pte::ZERO;
+            } else if self.page_manager.check_owned(pte) {
+                let sub = unsafe {
+                    PageTable::from_raw_parts(
+                        self.page_manager.p_to_v(pte.ppn()),
+                        VPN::new(start),
+                        level - 1,
+                    )
+                };
+                if self.unmap_table(sub, range) {
+                    self.page_manager.deallocate(pte, 1);
+                    table[i] = Pte::ZERO;
+                } else {
+                    empty = false;
+                }
+            } else {
+                // 不属于这个地址空间的页表，只是借用
+                empty = false;
             }
-            new_addrspace.map_extern(vpn_range, ppn, flags);
         }
+        empty
+    }
+
+    /// 把 `vpn` 处有效的页表项替换为 `pte`。
+    fn update(&mut self, vpn: VPN<Meta>, pte: Pte<Meta>) {
+        let mut root = self.root();
+        let mut updater = Updater::new(self, pte);
+        root.walk_mut(Pos::new(vpn, 0), &mut updater);
+        assert!(updater.ans());
+    }
+
+    /// 让 `vpn` 处的写时复制页 `pte` 可写，返回新的页表项。
+    fn unshare(&mut self, vpn: VPN<Meta>, pte: Pte<Meta>) -> Pte<Meta> {
+        let mut flags = (pte.flags() ^ M::COW) | M::WRITABLE;
+        let ppn = if self.page_manager.ref_count(pte.ppn()) > 1 {
+            // 还有其他地址空间共享这一页，复制一份，放弃对原来那一页的引用
+            let page = self.page_manager.allocate(1, &mut flags);
+            self.copy_page(pte.ppn(), page);
+            self.page_manager.deallocate(pte, 1);
+            self.page_manager.v_to_p(page)
+        } else {
+            pte.ppn()
+        };
+        let pte = flags.build_pte(ppn);
+        self.update(vpn, pte);
+        pte
+    }
+
+    /// 把物理页 `ppn` 的内容拷贝到 `page`。
+    fn copy_page(&self, ppn: PPN<Meta>, page: NonNull<u8>) {
+        let src = self.page_manager.p_to_v::<u8>(ppn);
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), page.as_ptr(), 1 << Meta::PAGE_BITS)
+        };
+    }
+}
+
+impl<Meta: VmMeta, M: PageManager<Meta>> Drop for AddressSpace<Meta, M> {
+    /// 释放地址空间中属于它的物理页和页表。
+    fn drop(&mut self) {
+        let root = self.root();
+        self.unmap_table(root, &(0..usize::MAX));
+        self.page_manager.drop_root();
     }
 }
 
@@ -0,0 +1,52 @@
+use crate::{AddressSpace, PageManager};
+use core::ptr::NonNull;
+use page_table::{Decorator, Pos, Pte, Update, VmMeta};
+
+/// 把一个有效的页表项替换为 `pte`，不会分配页表页。
+pub(super) struct Updater<'a, Meta: VmMeta, M: PageManager<Meta>> {
+    space: &'a AddressSpace<Meta, M>,
+    pte: Pte<Meta>,
+    done: bool,
+}
+
+impl<'a, Meta: VmMeta, M: PageManager<Meta>> Updater<'a, Meta, M> {
+    #[inline]
+    pub fn new(space: &'a AddressSpace<Meta, M>, pte: Pte<Meta>) -> Self {
+        Self {
+            space,
+            pte,
+            done: false,
+        }
+    }
+
+    #[inline]
+    pub fn ans(self) -> bool {
+        self.done
+    }
+}
+
+impl<Meta: VmMeta, M: PageManager<Meta>> Decorator<Meta> for Updater<'_, Meta, M> {
+    #[inline]
+    fn arrive(&mut self, pte: &mut Pte<Meta>, _target_hint: Pos<Meta>) -> Pos<Meta> {
+        if pte.is_valid() {
+            *pte = self.pte;
+            self.done = true;
+        }
+        Pos::stop()
+    }
+
+    #[inline]
+    fn meet(
+        &mut self,
+        _level: usize,
+        pte: Pte<Meta>,
+        _target_hint: Pos<Meta>,
+    ) -> Option<NonNull<Pte<Meta>>> {
+        Some(self.space.page_manager.p_to_v(pte.ppn()))
+    }
+
+    #[inline]
+    fn block(&mut self, _level: usize, _pte: Pte<Meta>, _target_hint: Pos<Meta>) -> Update<Meta> {
+        Update::Target(Pos::stop())
+    }
+}
@@ -1,3 +1,52 @@
 ﻿pub const STDIN: usize = 0;
 pub const STDOUT: usize = 1;
 pub const STDDEBUG: usize = 2;
+
+/// `lseek` 从文件开头移动读写位置
+pub const SEEK_SET: usize = 0;
+/// `lseek` 从当前位置移动读写位置
+pub const SEEK_CUR: usize = 1;
+/// `lseek` 从文件末尾移动读写位置
+pub const SEEK_END: usize = 2;
+
+bitflags::bitflags! {
+    /// 文件类型，取值同 Linux 的 `st_mode` 高位。
+    #[derive(Default)]
+    pub struct StatMode: u32 {
+        const NULL = 0;
+        /// 目录
+        const DIR = 0o040000;
+        /// 普通文件
+        const FILE = 0o100000;
+        /// 符号链接
+        const LINK = 0o120000;
+        /// 字符设备
+        const CHAR = 0o020000;
+        /// 块设备
+        const BLOCK = 0o060000;
+    }
+}
+
+/// 文件状态，see <https://man7.org/linux/man-pages/man2/fstat.2.html>.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct Stat {
+    /// 文件所在设备号
+    pub dev: u64,
+    /// inode 号
+    pub ino: u64,
+    /// 文件类型
+    pub mode: StatMode,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 文件字节数
+    pub size: u64,
+    /// 权限位，如 `0o644`
+    pub perm: u32,
+    /// 最近访问时间，单位秒
+    pub atime: u64,
+    /// 最近修改时间，单位秒
+    pub mtime: u64,
+    /// 创建时间，单位秒
+    pub crtime: u64,
+}
@@ -45,6 +45,68 @@ pub trait IO: Sync {
     fn close(&self, caller: Caller, fd: usize) -> isize {
         unimplemented!()
     }
+    fn lseek(&self, caller: Caller, fd: usize, offset: isize, whence: usize) -> isize {
+        unimplemented!()
+    }
+    fn pread(&self, caller: Caller, fd: usize, buf: usize, count: usize, offset: usize) -> isize {
+        unimplemented!()
+    }
+    fn pwrite(&self, caller: Caller, fd: usize, buf: usize, count: usize, offset: usize) -> isize {
+        unimplemented!()
+    }
+    fn pipe2(&self, caller: Caller, pipefd: usize, flags: usize) -> isize {
+        unimplemented!()
+    }
+    fn dup(&self, caller: Caller, fd: usize) -> isize {
+        unimplemented!()
+    }
+    fn dup3(&self, caller: Caller, oldfd: usize, newfd: usize, flags: usize) -> isize {
+        unimplemented!()
+    }
+    fn mkdir(&self, caller: Caller, path: usize) -> isize {
+        unimplemented!()
+    }
+    fn link(&self, caller: Caller, src: usize, dst: usize) -> isize {
+        unimplemented!()
+    }
+    fn unlink(&self, caller: Caller, path: usize) -> isize {
+        unimplemented!()
+    }
+    fn symlink(&self, caller: Caller, target: usize, linkpath: usize) -> isize {
+        unimplemented!()
+    }
+    fn readlink(&self, caller: Caller, path: usize, buf: usize, bufsiz: usize) -> isize {
+        unimplemented!()
+    }
+    fn rename(&self, caller: Caller, oldpath: usize, newpath: usize) -> isize {
+        unimplemented!()
+    }
+    fn fstat(&self, caller: Caller, fd: usize, st: usize) -> isize {
+        unimplemented!()
+    }
+    fn ftruncate(&self, caller: Caller, fd: usize, len: usize) -> isize {
+        unimplemented!()
+    }
+    fn fsync(&self, caller: Caller, fd: usize) -> isize {
+        unimplemented!()
+    }
+    fn sync(&self, caller: Caller) -> isize {
+        unimplemented!()
+    }
+    fn mount(
+        &self,
+        caller: Caller,
+        source: usize,
+        target: usize,
+        fstype: usize,
+        flags: usize,
+        data: usize,
+    ) -> isize {
+        unimplemented!()
+    }
+    fn umount(&self, caller: Caller, target: usize, flags: usize) -> isize {
+        unimplemented!()
+    }
 }
 
 pub trait Memory: Sync {
@@ -64,6 +126,10 @@ pub trait Memory: Sync {
     fn munmap(&self, caller: Caller, addr: usize, length: usize) -> isize {
         unimplemented!()
     }
+
+    fn mprotect(&self, caller: Caller, addr: usize, length: usize, prot: i32) -> isize {
+        unimplemented!()
+    }
 }
 
 pub trait Scheduling: Sync {
@@ -199,6 +265,30 @@ pub fn handle(caller: Caller, id: SyscallId, args: [usize; 6]) -> SyscallResult
         Id::READ => IO.call(id, |io| io.read(caller, args[0], args[1], args[2])),
         Id::OPENAT => IO.call(id, |io| io.open(caller, args[0], args[1])),
         Id::CLOSE => IO.call(id, |io| io.close(caller, args[0])),
+        Id::LSEEK => IO.call(id, |io| io.lseek(caller, args[0], args[1] as _, args[2])),
+        Id::PREAD64 => IO.call(id, |io| {
+            io.pread(caller, args[0], args[1], args[2], args[3])
+        }),
+        Id::PWRITE64 => IO.call(id, |io| {
+            io.pwrite(caller, args[0], args[1], args[2], args[3])
+        }),
+        Id::PIPE2 => IO.call(id, |io| io.pipe2(caller, args[0], args[1])),
+        Id::DUP => IO.call(id, |io| io.dup(caller, args[0])),
+        Id::DUP3 => IO.call(id, |io| io.dup3(caller, args[0], args[1], args[2])),
+        Id::MKDIRAT => IO.call(id, |io| io.mkdir(caller, args[0])),
+        Id::LINKAT => IO.call(id, |io| io.link(caller, args[0], args[1])),
+        Id::UNLINKAT => IO.call(id, |io| io.unlink(caller, args[0])),
+        Id::SYMLINKAT => IO.call(id, |io| io.symlink(caller, args[0], args[1])),
+        Id::READLINKAT => IO.call(id, |io| io.readlink(caller, args[0], args[1], args[2])),
+        Id::RENAMEAT2 => IO.call(id, |io| io.rename(caller, args[0], args[1])),
+        Id::FSTAT => IO.call(id, |io| io.fstat(caller, args[0], args[1])),
+        Id::FTRUNCATE => IO.call(id, |io| io.ftruncate(caller, args[0], args[1])),
+        Id::FSYNC => IO.call(id, |io| io.fsync(caller, args[0])),
+        Id::SYNC => IO.call(id, |io| io.sync(caller)),
+        Id::MOUNT => IO.call(id, |io| {
+            io.mount(caller, args[0], args[1], args[2], args[3], args[4])
+        }),
+        Id::UMOUNT2 => IO.call(id, |io| io.umount(caller, args[0], args[1])),
         Id::EXIT => PROCESS.call(id, |proc| proc.exit(caller, args[0])),
         Id::CLONE => PROCESS.call(id, |proc| proc.fork(caller)),
         Id::EXECVE => PROCESS.call(id, |proc| proc.exec(caller, args[0], args[1])),
@@ -213,6 +303,9 @@ pub fn handle(caller: Caller, id: SyscallId, args: [usize; 6]) -> SyscallResult
             let [addr, length, prot, flags, fd, offset] = args;
             memory.mmap(caller, addr, length, prot as _, flags as _, fd as _, offset)
         }),
+        Id::MPROTECT => MEMORY.call(id, |memory| {
+            memory.mprotect(caller, args[0], args[1], args[2] as _)
+        }),
         Id::KILL => SIGNAL.call(id, |signal| signal.kill(caller, args[0] as _, args[1] as _)),
         Id::RT_SIGACTION => SIGNAL.call(id, |signal| {
             signal.sigaction(caller, args[0] as _, args[1], args[2])
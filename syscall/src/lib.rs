@@ -5,10 +5,12 @@
 compile_error!("You can only use one of `supervisor` or `user` features at a time");
 
 mod io;
+mod memory;
 mod syscalls;
 mod time;
 
 pub use io::*;
+pub use memory::*;
 pub use signal_defs::{SignalAction, SignalNo, MAX_SIG};
 pub use time::*;
 
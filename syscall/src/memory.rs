@@ -0,0 +1,32 @@
+//! see <https://github.com/torvalds/linux/blob/master/include/uapi/asm-generic/mman-common.h>.
+
+bitflags::bitflags! {
+    /// 映射的保护属性，`mmap` 和 `mprotect` 的 `prot` 参数。
+    pub struct ProtFlags: u32 {
+        /// 不可访问
+        const NONE = 0;
+        /// 可读
+        const READ = 1 << 0;
+        /// 可写
+        const WRITE = 1 << 1;
+        /// 可执行
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags::bitflags! {
+    /// 映射的类型，`mmap` 的 `flags` 参数。
+    pub struct MapFlags: u32 {
+        /// 与其他进程共享修改
+        const SHARED = 1 << 0;
+        /// 私有映射，修改不写回文件，fork 后写时复制
+        const PRIVATE = 1 << 1;
+        /// 必须映射到给定的地址，覆盖原有的映射
+        const FIXED = 1 << 4;
+        /// 不映射文件，内容为零
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+/// `mmap` 失败的返回值
+pub const MAP_FAILED: isize = -1;
@@ -1,4 +1,4 @@
-use crate::{ClockId, SignalAction, SignalNo, SyscallId, TimeSpec};
+use crate::{ClockId, MapFlags, ProtFlags, SignalAction, SignalNo, Stat, SyscallId, TimeSpec};
 use bitflags::*;
 use native::*;
 
@@ -20,6 +20,8 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const APPEND = 1 << 11;
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -39,6 +41,217 @@ pub fn close(fd: usize) -> isize {
     unsafe { syscall1(SyscallId::CLOSE, fd) }
 }
 
+/// 移动读写位置，`whence` 取 [`SEEK_SET`](crate::SEEK_SET)、[`SEEK_CUR`](crate::SEEK_CUR)
+/// 或 [`SEEK_END`](crate::SEEK_END)，返回新的位置。
+#[inline]
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    unsafe { syscall3(SyscallId::LSEEK, fd, offset as usize, whence) }
+}
+
+/// 从 `offset` 处读出数据，不移动读写位置。
+#[inline]
+pub fn pread(fd: usize, buffer: &mut [u8], offset: usize) -> isize {
+    unsafe {
+        syscall4(
+            SyscallId::PREAD64,
+            fd,
+            buffer.as_mut_ptr() as usize,
+            buffer.len(),
+            offset,
+        )
+    }
+}
+
+/// 从 `offset` 处写入数据，不移动读写位置。
+#[inline]
+pub fn pwrite(fd: usize, buffer: &[u8], offset: usize) -> isize {
+    unsafe {
+        syscall4(
+            SyscallId::PWRITE64,
+            fd,
+            buffer.as_ptr() as usize,
+            buffer.len(),
+            offset,
+        )
+    }
+}
+
+/// 创建管道，读端和写端的文件描述符依次写入 `pipe_fd`。
+#[inline]
+pub fn pipe(pipe_fd: &mut [usize; 2]) -> isize {
+    pipe2(pipe_fd, OpenFlags::empty())
+}
+
+/// 创建管道，`flags` 可以包含 [`OpenFlags::CLOEXEC`]。
+#[inline]
+pub fn pipe2(pipe_fd: &mut [usize; 2], flags: OpenFlags) -> isize {
+    unsafe {
+        syscall2(
+            SyscallId::PIPE2,
+            pipe_fd.as_mut_ptr() as usize,
+            flags.bits as usize,
+        )
+    }
+}
+
+/// 复制文件描述符，返回最小的空闲描述符。
+#[inline]
+pub fn dup(fd: usize) -> isize {
+    unsafe { syscall1(SyscallId::DUP, fd) }
+}
+
+/// 复制文件描述符 `oldfd` 到 `newfd`，`newfd` 已打开时先关闭它，`flags` 可以包含 [`OpenFlags::CLOEXEC`]。
+#[inline]
+pub fn dup3(oldfd: usize, newfd: usize, flags: OpenFlags) -> isize {
+    unsafe { syscall3(SyscallId::DUP3, oldfd, newfd, flags.bits as usize) }
+}
+
+/// 创建目录，`path` 需以 `\0` 结尾。
+#[inline]
+pub fn mkdir(path: &str) -> isize {
+    unsafe { syscall1(SyscallId::MKDIRAT, path.as_ptr() as usize) }
+}
+
+/// 为文件 `src` 创建硬链接 `dst`，路径均需以 `\0` 结尾。
+#[inline]
+pub fn link(src: &str, dst: &str) -> isize {
+    unsafe {
+        syscall2(
+            SyscallId::LINKAT,
+            src.as_ptr() as usize,
+            dst.as_ptr() as usize,
+        )
+    }
+}
+
+/// 删除文件的一个链接，`path` 需以 `\0` 结尾。
+#[inline]
+pub fn unlink(path: &str) -> isize {
+    unsafe { syscall1(SyscallId::UNLINKAT, path.as_ptr() as usize) }
+}
+
+/// 创建指向 `target` 的符号链接 `linkpath`，路径均需以 `\0` 结尾。
+#[inline]
+pub fn symlink(target: &str, linkpath: &str) -> isize {
+    unsafe {
+        syscall2(
+            SyscallId::SYMLINKAT,
+            target.as_ptr() as usize,
+            linkpath.as_ptr() as usize,
+        )
+    }
+}
+
+/// 读出符号链接 `path` 指向的路径，`path` 需以 `\0` 结尾，返回写入 `buf` 的字节数。
+#[inline]
+pub fn readlink(path: &str, buf: &mut [u8]) -> isize {
+    unsafe {
+        syscall3(
+            SyscallId::READLINKAT,
+            path.as_ptr() as usize,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+        )
+    }
+}
+
+/// 把 `oldpath` 移动或改名为 `newpath`，路径均需以 `\0` 结尾。
+///
+/// `newpath` 已存在时会被替换，但不能是目录。
+#[inline]
+pub fn rename(oldpath: &str, newpath: &str) -> isize {
+    unsafe {
+        syscall2(
+            SyscallId::RENAMEAT2,
+            oldpath.as_ptr() as usize,
+            newpath.as_ptr() as usize,
+        )
+    }
+}
+
+/// see <https://man7.org/linux/man-pages/man2/fstat.2.html>.
+#[inline]
+pub fn fstat(fd: usize, st: &mut Stat) -> isize {
+    unsafe { syscall2(SyscallId::FSTAT, fd, st as *mut _ as usize) }
+}
+
+/// see <https://man7.org/linux/man-pages/man2/fsync.2.html>.
+#[inline]
+pub fn fsync(fd: usize) -> isize {
+    unsafe { syscall1(SyscallId::FSYNC, fd) }
+}
+
+/// see <https://man7.org/linux/man-pages/man2/ftruncate.2.html>.
+#[inline]
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    unsafe { syscall2(SyscallId::FTRUNCATE, fd, len) }
+}
+
+/// see <https://man7.org/linux/man-pages/man2/sync.2.html>.
+#[inline]
+pub fn sync() -> isize {
+    unsafe { syscall0(SyscallId::SYNC) }
+}
+
+/// 把 `source` 上类型为 `fstype` 的文件系统挂载到目录 `target`，字符串均需以 `\0` 结尾。
+#[inline]
+pub fn mount(source: &str, target: &str, fstype: &str) -> isize {
+    unsafe {
+        syscall5(
+            SyscallId::MOUNT,
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            0,
+            0,
+        )
+    }
+}
+
+/// 卸载挂载在 `target` 上的文件系统，`target` 需以 `\0` 结尾。
+#[inline]
+pub fn umount(target: &str) -> isize {
+    unsafe { syscall2(SyscallId::UMOUNT2, target.as_ptr() as usize, 0) }
+}
+
+/// 把文件 `fd` 从 `offset` 开始的内容映射到内存，返回映射的起始地址，失败时返回 [`MAP_FAILED`](crate::MAP_FAILED)。
+///
+/// `flags` 包含 [`MapFlags::ANONYMOUS`] 时不映射文件，`fd` 应为 `-1`。
+/// 没有 [`MapFlags::FIXED`] 时由内核选择地址，`addr` 被忽略。
+#[inline]
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: ProtFlags,
+    flags: MapFlags,
+    fd: isize,
+    offset: usize,
+) -> isize {
+    unsafe {
+        syscall6(
+            SyscallId::MMAP,
+            addr,
+            len,
+            prot.bits() as usize,
+            flags.bits() as usize,
+            fd as usize,
+            offset,
+        )
+    }
+}
+
+/// 撤销 `addr` 开始 `len` 字节中的映射。
+#[inline]
+pub fn munmap(addr: usize, len: usize) -> isize {
+    unsafe { syscall2(SyscallId::MUNMAP, addr, len) }
+}
+
+/// 把 `addr` 开始 `len` 字节中映射的保护属性改为 `prot`。
+#[inline]
+pub fn mprotect(addr: usize, len: usize, prot: ProtFlags) -> isize {
+    unsafe { syscall3(SyscallId::MPROTECT, addr, len, prot.bits() as usize) }
+}
+
 /// see <https://man7.org/linux/man-pages/man2/exit.2.html>.
 #[inline]
 pub fn exit(exit_code: i32) -> isize {
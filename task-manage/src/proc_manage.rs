@@ -1,4 +1,4 @@
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 
 use super::id::ProcId;
 use super::manager::Manage;
@@ -16,6 +16,8 @@ pub struct PManager<P, MP: Manage<P, ProcId> + Schedule<ProcId>> {
     manager: Option<MP>,
     // 当前正在运行的进程 ID
     current: Option<ProcId>,
+    // 阻塞的进程，不在调度队列中，被唤醒后才重新入队
+    blocked: BTreeSet<ProcId>,
     phantom_data: PhantomData<P>,
 }
 
@@ -26,6 +28,7 @@ impl<P, MP: Manage<P, ProcId> + Schedule<ProcId>> PManager<P, MP> {
             rel_map: BTreeMap::new(),
             manager: None,
             current: None,
+            blocked: BTreeSet::new(),
             phantom_data: PhantomData::<P>,
         }
     }
@@ -52,6 +55,18 @@ impl<P, MP: Manage<P, ProcId> + Schedule<ProcId>> PManager<P, MP> {
         self.manager.as_mut().unwrap().add(id);
         self.current = None;
     }
+    /// 让当前进程阻塞，直到 [`re_enque`](Self::re_enque) 唤醒它
+    pub fn make_current_blocked(&mut self) {
+        let id = self.current.unwrap();
+        self.blocked.insert(id);
+        self.current = None;
+    }
+    /// 唤醒阻塞的进程，让它重新入队；进程没有阻塞或已经结束时什么也不做
+    pub fn re_enque(&mut self, id: ProcId) {
+        if self.blocked.remove(&id) {
+            self.manager.as_mut().unwrap().add(id);
+        }
+    }
     /// 结束当前进程，只会删除进程的内容，以及与当前进程相关的关系
     pub fn make_current_exited(&mut self, exit_code: isize) {
         let id = self.current.unwrap();
@@ -0,0 +1,510 @@
+//! 内存文件系统
+//!
+//! 文件内容和目录树都放在内核堆上，不经过块设备，卸载或关机后全部丢失。
+//! 可以挂载到 `/tmp` 存放临时文件，也可以作为没有块设备的章节（ch5）的根文件系统。
+//! 只依赖 `alloc`，可以在宿主机上测试。
+
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+extern crate alloc;
+
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+use core::sync::atomic::{AtomicU64, Ordering};
+use rcore_vfs::{FileSystem, FileType, Inode, Metadata};
+use spin::Mutex;
+
+/// 文件名的最大字节数，与 easy-fs 一致
+pub const NAME_LENGTH_LIMIT: usize = 255;
+
+/// 文件的最大字节数，避免移到很远的位置写入或截断时耗尽内核堆
+pub const FILE_SIZE_LIMIT: usize = 16 << 20;
+
+/// 一个内存文件系统实例
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+/// 同一个文件系统中所有 inode 共享的状态
+struct Shared {
+    /// 下一个 inode 编号
+    next_ino: AtomicU64,
+    /// 读取当前时间，单位为秒
+    clock: fn() -> u64,
+    /// 修改目录树的操作互斥进行，避免同时锁住多个目录时死锁
+    tree: Mutex<()>,
+}
+
+impl Shared {
+    fn now(&self) -> u64 {
+        (self.clock)()
+    }
+}
+
+impl TmpFs {
+    /// 创建一个只有空的根目录的文件系统，`clock` 返回以秒为单位的当前时间
+    pub fn new(clock: fn() -> u64) -> Self {
+        let fs = Arc::new(Shared {
+            next_ino: AtomicU64::new(1),
+            clock,
+            tree: Mutex::new(()),
+        });
+        Self {
+            root: TmpInode::new(&fs, Content::Directory(BTreeMap::new()), 0o755),
+        }
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+
+    fn sync(&self) {}
+}
+
+/// inode 的内容
+enum Content {
+    /// 文件数据
+    File(Vec<u8>),
+    /// 目录项，名字到 inode
+    Directory(BTreeMap<String, Arc<TmpInode>>),
+    /// 符号链接指向的路径
+    SymLink(String),
+}
+
+/// inode 中可变的部分
+struct TmpInodeInner {
+    content: Content,
+    nlink: u32,
+    mode: u32,
+    atime: u64,
+    mtime: u64,
+    crtime: u64,
+}
+
+/// 内存文件系统中的文件、目录或符号链接
+///
+/// 目录持有子项的引用，最后一个硬链接删除、且没有打开的文件时内存被释放。
+pub struct TmpInode {
+    ino: u64,
+    /// 自身的引用，建立硬链接时用
+    this: Weak<TmpInode>,
+    fs: Arc<Shared>,
+    inner: Mutex<TmpInodeInner>,
+}
+
+impl TmpInode {
+    fn new(fs: &Arc<Shared>, content: Content, mode: u32) -> Arc<Self> {
+        let now = fs.now();
+        let nlink = match content {
+            Content::Directory(_) => 2,
+            _ => 1,
+        };
+        Arc::new_cyclic(|this| Self {
+            ino: fs.next_ino.fetch_add(1, Ordering::Relaxed),
+            this: this.clone(),
+            fs: fs.clone(),
+            inner: Mutex::new(TmpInodeInner {
+                content,
+                nlink,
+                mode,
+                atime: now,
+                mtime: now,
+                crtime: now,
+            }),
+        })
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self.inner.lock().content, Content::Directory(_))
+    }
+
+    /// 取得同一文件系统中的 inode
+    fn downcast<'a>(&self, inode: &'a Arc<dyn Inode>) -> Option<&'a TmpInode> {
+        inode
+            .as_any()
+            .downcast_ref::<TmpInode>()
+            .filter(|inode| Arc::ptr_eq(&inode.fs, &self.fs))
+    }
+
+    /// 在目录中按名字查找
+    fn find_entry(&self, name: &str) -> Option<Arc<TmpInode>> {
+        match &self.inner.lock().content {
+            Content::Directory(entries) => entries.get(name).cloned(),
+            _ => None,
+        }
+    }
+
+    /// 目录 `self` 之下（含自身）是否有 `target`
+    fn contains(&self, target: &TmpInode) -> bool {
+        if core::ptr::eq(self, target) {
+            return true;
+        }
+        let subdirs: Vec<Arc<TmpInode>> = match &self.inner.lock().content {
+            Content::Directory(entries) => entries
+                .values()
+                .filter(|inode| inode.is_dir())
+                .cloned()
+                .collect(),
+            _ => return false,
+        };
+        subdirs.iter().any(|dir| dir.contains(target))
+    }
+
+    /// 在目录中加入新建的 `inode`，名字不合法或已存在时失败
+    fn add(&self, name: &str, inode: Arc<TmpInode>) -> Option<Arc<dyn Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return None;
+        }
+        let _tree = self.fs.tree.lock();
+        let mut inner = self.inner.lock();
+        let is_dir = inode.is_dir();
+        match &mut inner.content {
+            Content::Directory(entries) if !entries.contains_key(name) => {
+                entries.insert(String::from(name), inode.clone());
+            }
+            _ => return None,
+        }
+        if is_dir {
+            inner.nlink += 1;
+        }
+        inner.mtime = self.fs.now();
+        Some(inode)
+    }
+}
+
+impl Inode for TmpInode {
+    fn metadata(&self) -> Metadata {
+        let inner = self.inner.lock();
+        let (type_, size) = match &inner.content {
+            Content::File(data) => (FileType::File, data.len()),
+            Content::Directory(entries) => (FileType::Directory, entries.len()),
+            Content::SymLink(target) => (FileType::SymLink, target.len()),
+        };
+        Metadata {
+            ino: self.ino,
+            type_,
+            nlink: inner.nlink,
+            size: size as u64,
+            mode: inner.mode,
+            atime: inner.atime,
+            mtime: inner.mtime,
+            crtime: inner.crtime,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let mut inner = self.inner.lock();
+        let len = match &inner.content {
+            Content::File(data) if offset < data.len() => {
+                let len = buf.len().min(data.len() - offset);
+                buf[..len].copy_from_slice(&data[offset..offset + len]);
+                len
+            }
+            _ => 0,
+        };
+        inner.atime = self.fs.now();
+        len
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut inner = self.inner.lock();
+        match &mut inner.content {
+            Content::File(data) => match offset.checked_add(buf.len()) {
+                Some(end) if end <= FILE_SIZE_LIMIT => {
+                    if data.len() < end {
+                        data.resize(end, 0);
+                    }
+                    data[offset..end].copy_from_slice(buf);
+                }
+                _ => return 0,
+            },
+            _ => return 0,
+        }
+        inner.mtime = self.fs.now();
+        buf.len()
+    }
+
+    fn append(&self, buf: &[u8]) -> usize {
+        let mut inner = self.inner.lock();
+        match &mut inner.content {
+            Content::File(data) if data.len() + buf.len() <= FILE_SIZE_LIMIT => {
+                data.extend_from_slice(buf);
+            }
+            _ => return 0,
+        }
+        inner.mtime = self.fs.now();
+        buf.len()
+    }
+
+    fn truncate(&self, len: usize) -> bool {
+        let mut inner = self.inner.lock();
+        match &mut inner.content {
+            Content::File(data) if len <= FILE_SIZE_LIMIT => {
+                data.resize(len, 0);
+                data.shrink_to_fit();
+            }
+            _ => return false,
+        }
+        inner.mtime = self.fs.now();
+        true
+    }
+
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.find_entry(name).map(|inode| inode as Arc<dyn Inode>)
+    }
+
+    fn create(&self, name: &str, type_: FileType) -> Option<Arc<dyn Inode>> {
+        let inode = match type_ {
+            FileType::File => TmpInode::new(&self.fs, Content::File(Vec::new()), 0o644),
+            FileType::Directory => {
+                TmpInode::new(&self.fs, Content::Directory(BTreeMap::new()), 0o755)
+            }
+            FileType::SymLink | FileType::CharDevice | FileType::BlockDevice => return None,
+        };
+        self.add(name, inode)
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn Inode>> {
+        let inode = TmpInode::new(&self.fs, Content::SymLink(String::from(target)), 0o777);
+        self.add(name, inode)
+    }
+
+    fn readlink(&self) -> Option<String> {
+        match &self.inner.lock().content {
+            Content::SymLink(target) => Some(target.clone()),
+            _ => None,
+        }
+    }
+
+    fn link(&self, name: &str, target: &Arc<dyn Inode>) -> bool {
+        let inode = match self.downcast(target) {
+            Some(inode) if matches!(inode.inner.lock().content, Content::File(_)) => inode,
+            _ => return false,
+        };
+        let inode = match inode.this.upgrade() {
+            Some(inode) => inode,
+            None => return false,
+        };
+        if self.add(name, inode.clone()).is_none() {
+            return false;
+        }
+        inode.inner.lock().nlink += 1;
+        true
+    }
+
+    fn unlink(&self, name: &str) -> bool {
+        let _tree = self.fs.tree.lock();
+        let mut inner = self.inner.lock();
+        let entries = match &mut inner.content {
+            Content::Directory(entries) => entries,
+            _ => return false,
+        };
+        match entries.get(name) {
+            Some(inode) if !inode.is_dir() => {
+                inode.inner.lock().nlink -= 1;
+                entries.remove(name);
+            }
+            _ => return false,
+        }
+        inner.mtime = self.fs.now();
+        true
+    }
+
+    fn rename(&self, old_name: &str, new_dir: &Arc<dyn Inode>, new_name: &str) -> bool {
+        let new_dir = match self.downcast(new_dir) {
+            Some(new_dir) if new_dir.is_dir() => new_dir,
+            _ => return false,
+        };
+        if new_name.is_empty() || new_name.len() > NAME_LENGTH_LIMIT || new_name.contains('/') {
+            return false;
+        }
+        let _tree = self.fs.tree.lock();
+        let inode = match self.find_entry(old_name) {
+            Some(inode) => inode,
+            None => return false,
+        };
+        let moving_dir = inode.is_dir();
+        match new_dir.find_entry(new_name) {
+            // 同一个文件
+            Some(old) if Arc::ptr_eq(&old, &inode) => return true,
+            Some(old) if moving_dir || old.is_dir() => return false,
+            Some(old) => old.inner.lock().nlink -= 1,
+            None => {}
+        }
+        // 目录不能移动到自己之下
+        if moving_dir && inode.contains(new_dir) {
+            return false;
+        }
+        let now = self.fs.now();
+        let same_dir = core::ptr::eq(self, new_dir);
+        {
+            let mut inner = self.inner.lock();
+            if let Content::Directory(entries) = &mut inner.content {
+                entries.remove(old_name);
+            }
+            if moving_dir && !same_dir {
+                inner.nlink -= 1;
+            }
+            inner.mtime = now;
+        }
+        let mut inner = new_dir.inner.lock();
+        if let Content::Directory(entries) = &mut inner.content {
+            entries.insert(String::from(new_name), inode);
+        }
+        if moving_dir && !same_dir {
+            inner.nlink += 1;
+        }
+        inner.mtime = now;
+        true
+    }
+
+    fn readdir(&self) -> Option<Vec<String>> {
+        match &self.inner.lock().content {
+            Content::Directory(entries) => {
+                let mut names = Vec::with_capacity(entries.len() + 2);
+                names.push(String::from("."));
+                names.push(String::from(".."));
+                names.extend(entries.keys().cloned());
+                Some(names)
+            }
+            _ => None,
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_fs() -> (TmpFs, Arc<dyn Inode>) {
+        let fs = TmpFs::new(|| 0);
+        let root = fs.root_inode();
+        (fs, root)
+    }
+
+    fn nlink(inode: &Arc<dyn Inode>) -> u32 {
+        inode.metadata().nlink
+    }
+
+    fn read_all(inode: &Arc<dyn Inode>) -> Vec<u8> {
+        let mut buf = alloc::vec![0; inode.metadata().size as usize];
+        assert_eq!(inode.read_at(0, &mut buf), buf.len());
+        buf
+    }
+
+    #[test]
+    fn create_read_write() {
+        let (_fs, root) = new_fs();
+        let file = root.create("a", FileType::File).unwrap();
+        // 名字重复、不合法或不在目录中创建都失败
+        assert!(root.create("a", FileType::File).is_none());
+        assert!(root.create("", FileType::File).is_none());
+        assert!(root.create("x/y", FileType::File).is_none());
+        assert!(file.create("b", FileType::File).is_none());
+
+        // 越过文件末尾写入，中间补零
+        assert_eq!(file.write_at(2, b"cd"), 2);
+        assert_eq!(read_all(&file), b"\0\0cd");
+        assert_eq!(file.append(b"ef"), 2);
+        assert_eq!(read_all(&file), b"\0\0cdef");
+        let mut buf = [0u8; 4];
+        assert_eq!(file.read_at(4, &mut buf), 2);
+        assert_eq!(file.read_at(6, &mut buf), 0);
+        assert!(file.truncate(3));
+        assert_eq!(read_all(&file), b"\0\0c");
+        assert_eq!(file.write_at(FILE_SIZE_LIMIT, b"x"), 0);
+        assert!(!file.truncate(FILE_SIZE_LIMIT + 1));
+
+        assert_eq!(root.readdir().unwrap(), [".", "..", "a"]);
+        assert!(Arc::ptr_eq(&root.find("a").unwrap(), &file));
+    }
+
+    #[test]
+    fn link_and_unlink() {
+        let (_fs, root) = new_fs();
+        let file = root.create("a", FileType::File).unwrap();
+        file.write_at(0, b"data");
+        assert!(root.link("b", &file));
+        assert_eq!(nlink(&file), 2);
+        assert!(!root.link("b", &file));
+
+        assert!(root.unlink("a"));
+        assert!(!root.unlink("a"));
+        assert_eq!(nlink(&file), 1);
+        assert_eq!(read_all(&root.find("b").unwrap()), b"data");
+        assert!(root.unlink("b"));
+        assert_eq!(nlink(&file), 0);
+        assert_eq!(root.readdir().unwrap(), [".", ".."]);
+
+        // 目录不能建立硬链接，也不能用 unlink 删除
+        let dir = root.create("d", FileType::Directory).unwrap();
+        assert!(!root.link("e", &dir));
+        assert!(!root.unlink("d"));
+    }
+
+    #[test]
+    fn directory_link_counts() {
+        let (_fs, root) = new_fs();
+        assert_eq!(nlink(&root), 2);
+        let d = root.create("d", FileType::Directory).unwrap();
+        assert_eq!(nlink(&root), 3);
+        assert_eq!(nlink(&d), 2);
+        d.create("e", FileType::Directory).unwrap();
+        d.create("f", FileType::File).unwrap();
+        assert_eq!(nlink(&d), 3);
+    }
+
+    #[test]
+    fn rename_files() {
+        let (_fs, root) = new_fs();
+        let d = root.create("d", FileType::Directory).unwrap();
+        let a = root.create("a", FileType::File).unwrap();
+        assert!(root.rename("a", &root, "b"));
+        assert!(root.find("a").is_none());
+        assert!(root.rename("b", &d, "c"));
+        assert!(Arc::ptr_eq(&d.find("c").unwrap(), &a));
+        assert!(!root.rename("b", &d, "c"));
+
+        // 替换已有的文件，被替换的文件少一个链接
+        let old = d.create("old", FileType::File).unwrap();
+        assert!(d.rename("c", &d, "old"));
+        assert_eq!(nlink(&old), 0);
+        assert_eq!(nlink(&a), 1);
+        assert_eq!(d.readdir().unwrap(), [".", "..", "old"]);
+        // 同一个文件改名为自己
+        assert!(d.rename("old", &d, "old"));
+        assert!(!d.rename("old", &root, "d"));
+    }
+
+    #[test]
+    fn rename_directories() {
+        let (_fs, root) = new_fs();
+        let a = root.create("a", FileType::Directory).unwrap();
+        let b = root.create("b", FileType::Directory).unwrap();
+        let c = a.create("c", FileType::Directory).unwrap();
+        assert!(a.rename("c", &b, "c"));
+        assert_eq!(nlink(&a), 2);
+        assert_eq!(nlink(&b), 3);
+        assert_eq!(nlink(&c), 2);
+        assert_eq!(nlink(&root), 4);
+
+        // 目录不能替换已有的项，也不能移动到自己之下
+        assert!(!root.rename("a", &root, "b"));
+        assert!(!root.rename("b", &b, "x"));
+        assert!(!root.rename("b", &c, "x"));
+        assert!(Arc::ptr_eq(&root.find("b").unwrap(), &b));
+        assert!(b.find("x").is_none() && c.find("x").is_none());
+    }
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+use user_lib::{exit, fork, wait};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = 3 * PAGE_SIZE;
+
+static mut DATA: [u8; LEN] = [0; LEN];
+
+/// 检查 `data` 中的每个字节都是 `byte`
+fn all_eq(data: *mut u8, len: usize, byte: u8) -> bool {
+    (0..len).all(|i| unsafe { read_volatile(data.add(i)) } == byte)
+}
+
+/// 把 `data` 中的每个字节都写为 `byte`
+fn fill(data: *mut u8, len: usize, byte: u8) {
+    (0..len).for_each(|i| unsafe { write_volatile(data.add(i), byte) });
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let data = addr_of_mut!(DATA) as *mut u8;
+    let mut stack = [0u8; 64];
+    let stack = stack.as_mut_ptr();
+    fill(data, LEN, b'p');
+    fill(stack, 64, b'p');
+
+    let pid = fork();
+    if pid == 0 {
+        // 子进程看到 fork 之前的数据
+        assert!(all_eq(data, LEN, b'p'));
+        assert!(all_eq(stack, 64, b'p'));
+        // 只写后两页，第一页仍和父进程共享
+        fill(unsafe { data.add(PAGE_SIZE) }, 2 * PAGE_SIZE, b'c');
+        fill(stack, 64, b'c');
+        assert!(all_eq(data, PAGE_SIZE, b'p'));
+        // 孙进程的写入不影响子进程
+        let pid = fork();
+        if pid == 0 {
+            fill(data, LEN, b'g');
+            exit(0);
+        }
+        let mut exit_code: i32 = 0;
+        assert_eq!(wait(&mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        assert!(all_eq(data, PAGE_SIZE, b'p'));
+        assert!(all_eq(unsafe { data.add(PAGE_SIZE) }, 2 * PAGE_SIZE, b'c'));
+        exit(0);
+    }
+    assert!(pid > 0);
+    // 内核写入 `exit_code` 时也要先分开写时复制的栈页
+    let mut exit_code: i32 = -1;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    // 子进程的写入不影响父进程
+    assert!(all_eq(data, LEN, b'p'));
+    assert!(all_eq(stack, 64, b'p'));
+    println!("forktest_cow pass.");
+    0
+}
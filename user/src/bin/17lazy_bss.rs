@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+use user_lib::{clock_gettime, ClockId, TimeSpec};
+
+const PAGE_SIZE: usize = 4096;
+/// 比物理内存还大，只有按需分配才能加载
+const LEN: usize = 64 << 20;
+const STEP: usize = 1 << 20;
+
+static mut BIG: [u8; LEN] = [0; LEN];
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let big = addr_of_mut!(BIG) as *mut u8;
+    // 第一次访问的页都是零
+    for i in (0..LEN).step_by(STEP) {
+        assert_eq!(unsafe { read_volatile(big.add(i)) }, 0);
+        unsafe { write_volatile(big.add(i), (i / STEP) as u8 + 1) };
+    }
+    for i in (0..LEN).step_by(STEP) {
+        assert_eq!(unsafe { read_volatile(big.add(i)) }, (i / STEP) as u8 + 1);
+    }
+    // 内核写入还没有访问过的页
+    let time = unsafe { big.add(LEN - PAGE_SIZE) } as *mut TimeSpec;
+    assert_eq!(clock_gettime(ClockId::CLOCK_MONOTONIC, time), 0);
+    assert!(unsafe { read_volatile(time) } > TimeSpec::ZERO);
+    println!("lazy_bss pass.");
+    0
+}
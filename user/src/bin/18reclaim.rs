@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{addr_of_mut, write_volatile};
+use user_lib::{exit, fork, wait};
+
+const PAGE_SIZE: usize = 4096;
+/// 每个子进程用掉的内存
+const LEN: usize = 4 << 20;
+/// 所有子进程加起来远超物理内存，只有退出时回收才能跑完
+const TIMES: usize = 100;
+
+static mut DATA: [u8; LEN] = [0; LEN];
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let data = addr_of_mut!(DATA) as *mut u8;
+    for i in 0..TIMES {
+        let pid = fork();
+        if pid == 0 {
+            for j in (0..LEN).step_by(PAGE_SIZE) {
+                unsafe { write_volatile(data.add(j), i as u8) };
+            }
+            exit(0);
+        }
+        assert!(pid > 0);
+        let mut exit_code: i32 = -1;
+        assert_eq!(wait(&mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+    }
+    println!("reclaim pass.");
+    0
+}
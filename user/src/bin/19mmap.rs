@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{read_volatile, write_volatile};
+use user_lib::{exit, fork, mmap, mprotect, munmap, wait, MapFlags, ProtFlags, MAP_FAILED};
+
+const PAGE_SIZE: usize = 4096;
+const LEN: usize = 4 * PAGE_SIZE;
+
+fn anonymous(addr: usize, len: usize, prot: ProtFlags, flags: MapFlags) -> isize {
+    mmap(
+        addr,
+        len,
+        prot,
+        flags | MapFlags::PRIVATE | MapFlags::ANONYMOUS,
+        -1,
+        0,
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let rw = ProtFlags::READ | ProtFlags::WRITE;
+    // 匿名映射的页第一次读出都是零
+    let addr = anonymous(0, LEN, rw, MapFlags::empty());
+    assert_ne!(addr, MAP_FAILED);
+    let addr = addr as usize;
+    assert_eq!(addr % PAGE_SIZE, 0);
+    let data = addr as *mut u8;
+    for i in (0..LEN).step_by(PAGE_SIZE) {
+        assert_eq!(unsafe { read_volatile(data.add(i)) }, 0);
+        unsafe { write_volatile(data.add(i), (i / PAGE_SIZE) as u8 + 1) };
+    }
+
+    // 子进程的写入不影响父进程
+    let pid = fork();
+    if pid == 0 {
+        for i in (0..LEN).step_by(PAGE_SIZE) {
+            assert_eq!(
+                unsafe { read_volatile(data.add(i)) },
+                (i / PAGE_SIZE) as u8 + 1
+            );
+            unsafe { write_volatile(data.add(i), 0xff) };
+        }
+        exit(0);
+    }
+    assert!(pid > 0);
+    let mut exit_code: i32 = -1;
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    for i in (0..LEN).step_by(PAGE_SIZE) {
+        assert_eq!(
+            unsafe { read_volatile(data.add(i)) },
+            (i / PAGE_SIZE) as u8 + 1
+        );
+    }
+
+    // 改为只读后仍可读，写入会使进程被杀死
+    assert_eq!(mprotect(addr + PAGE_SIZE, PAGE_SIZE, ProtFlags::READ), 0);
+    assert_eq!(unsafe { read_volatile(data.add(PAGE_SIZE)) }, 2);
+    let pid = fork();
+    if pid == 0 {
+        unsafe { write_volatile(data.add(PAGE_SIZE), 0) };
+        exit(0);
+    }
+    assert_eq!(wait(&mut exit_code), pid);
+    assert_ne!(exit_code, 0);
+    assert_eq!(mprotect(addr + PAGE_SIZE, PAGE_SIZE, rw), 0);
+    unsafe { write_volatile(data.add(PAGE_SIZE), 0x42) };
+    assert_eq!(unsafe { read_volatile(data.add(PAGE_SIZE)) }, 0x42);
+
+    // 撤销中间一页，再用固定地址映射回来，内容清零
+    assert_eq!(munmap(addr + 2 * PAGE_SIZE, PAGE_SIZE), 0);
+    let fixed = anonymous(addr + 2 * PAGE_SIZE, PAGE_SIZE, rw, MapFlags::FIXED);
+    assert_eq!(fixed, (addr + 2 * PAGE_SIZE) as isize);
+    assert_eq!(unsafe { read_volatile(data.add(2 * PAGE_SIZE)) }, 0);
+    assert_eq!(unsafe { read_volatile(data.add(3 * PAGE_SIZE)) }, 4);
+
+    // 不支持的参数
+    assert_eq!(
+        anonymous(0, LEN, ProtFlags::NONE, MapFlags::empty()),
+        MAP_FAILED
+    );
+    assert_eq!(
+        anonymous(0, LEN, ProtFlags::WRITE, MapFlags::empty()),
+        MAP_FAILED
+    );
+    assert_eq!(anonymous(0, 0, rw, MapFlags::empty()), MAP_FAILED);
+    assert_eq!(anonymous(addr + 1, LEN, rw, MapFlags::FIXED), MAP_FAILED);
+    assert_eq!(
+        mmap(0, LEN, rw, MapFlags::SHARED | MapFlags::ANONYMOUS, -1, 0),
+        MAP_FAILED
+    );
+    assert_eq!(munmap(addr + 1, PAGE_SIZE), -1);
+
+    assert_eq!(munmap(addr, LEN), 0);
+    println!("mmap pass.");
+    0
+}
@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{read, write, STDIN, STDOUT};
+
+/// 把标准输入复制到标准输出，直到文件尾，用于在 shell 中测试管道，如 `00hello_world | cat`
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let mut buffer = [0u8; 256];
+    loop {
+        let len = read(STDIN, &mut buffer);
+        if len <= 0 {
+            return len as i32;
+        }
+        write(STDOUT, &buffer[..len as usize]);
+    }
+}
@@ -0,0 +1,79 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, lseek, open, read, write, OpenFlags, Stat, StatMode, SEEK_END, SEEK_SET,
+};
+
+/// 打开设备文件，`path` 需以 `\0` 结尾
+fn open_dev(path: &str, flags: OpenFlags) -> usize {
+    let fd = open(path, flags);
+    assert!(fd > 0);
+    fd as usize
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let mut stat = Stat::default();
+    let mut buffer = [0xffu8; 16];
+
+    // 空设备读到文件尾，写入的数据被丢弃
+    let fd = open_dev("/dev/null\0", OpenFlags::RDWR);
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.mode, StatMode::CHAR);
+    assert_eq!(write(fd, b"discarded"), 9);
+    assert_eq!(read(fd, &mut buffer), 0);
+    close(fd);
+
+    // 零设备读出的都是 0
+    let fd = open_dev("/dev/zero\0", OpenFlags::RDONLY);
+    assert_eq!(read(fd, &mut buffer), 16);
+    assert_eq!(buffer, [0; 16]);
+    close(fd);
+
+    // 两次读出的随机数不同
+    let fd = open_dev("/dev/random\0", OpenFlags::RDONLY);
+    let mut other = [0u8; 16];
+    assert_eq!(read(fd, &mut buffer), 16);
+    assert_eq!(read(fd, &mut other), 16);
+    assert_ne!(buffer, other);
+    close(fd);
+
+    // 控制台和标准输出是同一个设备
+    let fd = open_dev("/dev/console\0", OpenFlags::WRONLY);
+    let mut console = Stat::default();
+    assert_eq!(fstat(fd, &mut console), 0);
+    assert_eq!(fstat(1, &mut stat), 0);
+    assert_eq!(console.mode, StatMode::CHAR);
+    assert_eq!(console.ino, stat.ino);
+    assert_eq!(write(fd, b"written to /dev/console\n"), 24);
+    close(fd);
+
+    // 块设备可以定位读，长度是磁盘的容量
+    let fd = open_dev("/dev/vda\0", OpenFlags::RDONLY);
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.mode, StatMode::BLOCK);
+    let size = lseek(fd, 0, SEEK_END);
+    assert!(size > 0 && size % 512 == 0);
+    assert_eq!(size as u64, stat.size);
+    assert_eq!(read(fd, &mut buffer), 0);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut block = [0u8; 512];
+    assert_eq!(read(fd, &mut block), 512);
+    close(fd);
+    // 块设备上挂载着根文件系统，不能直接写入
+    let fd = open_dev("/dev/vda\0", OpenFlags::RDWR);
+    assert_eq!(write(fd, &block), 0);
+    close(fd);
+
+    // 不能创建设备文件
+    assert_eq!(
+        open("/dev/new\0", OpenFlags::CREATE | OpenFlags::WRONLY),
+        -1
+    );
+    println!("filetest_dev passed!");
+    0
+}
@@ -0,0 +1,40 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, open, read, write, OpenFlags};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    assert_eq!(mkdir("dir_a\0"), 0);
+    assert_eq!(mkdir("dir_a/dir_b\0"), 0);
+    // 重复创建、父目录不存在都应失败
+    assert_eq!(mkdir("dir_a\0"), -1);
+    assert_eq!(mkdir("dir_x/dir_y\0"), -1);
+
+    let test_str = "Hello, directory!";
+    let path = "/dir_a/dir_b/file\0";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    // 经由 `.` 和 `..` 访问同一个文件
+    let fd = open("dir_a/./dir_b/../dir_b/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // 普通文件不能作为路径中间的目录，目录也不能被当作文件创建
+    assert_eq!(open("dir_a/dir_b/file/x\0", OpenFlags::RDONLY), -1);
+    assert_eq!(open("dir_a/dir_b/file/x\0", OpenFlags::CREATE), -1);
+    assert_eq!(open("dir_a\0", OpenFlags::CREATE | OpenFlags::WRONLY), -1);
+    println!("filetest_dir passed!");
+    0
+}
@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, dup3, exec, exit, fork, fstat, open, read, wait, write, OpenFlags, Stat, StatMode,
+    STDDEBUG, STDIN, STDOUT,
+};
+
+/// 执行新程序后这两个描述符分别保留和关闭，由 filetest_dup_exec 检查
+const KEEP_FD: usize = 10;
+const CLOEXEC_FD: usize = 11;
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    // 标准输入输出是指向控制台的字符设备
+    let mut stat = Stat::default();
+    for fd in [STDIN, STDOUT, STDDEBUG] {
+        assert_eq!(fstat(fd, &mut stat), 0);
+        assert_eq!(stat.mode, StatMode::CHAR);
+    }
+
+    // dup 得到的描述符共享读写位置
+    let fd = open(
+        "dup_file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let copy = dup(fd);
+    assert!(copy > 0);
+    let copy = copy as usize;
+    assert_eq!(write(fd, b"hello"), 5);
+    assert_eq!(write(copy, b", dup!"), 6);
+    close(copy);
+
+    // 关闭后描述符可以复用，新描述符取最小的空闲编号
+    assert_eq!(dup(fd), copy as isize);
+    close(copy);
+    close(fd);
+
+    let fd = open("dup_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 16];
+    assert_eq!(dup3(fd, fd, OpenFlags::empty()), -1);
+    assert_eq!(dup3(usize::MAX, 20, OpenFlags::empty()), -1);
+    assert_eq!(dup3(fd, 20, OpenFlags::empty()), 20);
+    assert_eq!(read(fd, &mut buffer[..2]), 2);
+    assert_eq!(read(20, &mut buffer[2..5]), 3);
+    assert_eq!(&buffer[..5], b"hello");
+    close(20);
+    assert_eq!(read(20, &mut buffer), -1);
+
+    // fork 之后父子进程共享读写位置
+    if fork() == 0 {
+        assert_eq!(read(fd, &mut buffer[..2]), 2);
+        assert_eq!(&buffer[..2], b", ");
+        exit(0);
+        unreachable!();
+    }
+    let mut exit_code: i32 = 0;
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 0);
+    assert_eq!(read(fd, &mut buffer), 4);
+    assert_eq!(&buffer[..4], b"dup!");
+    close(fd);
+
+    // 把标准输出重定向到文件，再恢复
+    let fd = open(
+        "dup_file\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let saved = dup(STDOUT);
+    assert!(saved > 0);
+    let saved = saved as usize;
+    assert_eq!(dup3(fd, STDOUT, OpenFlags::empty()), STDOUT as isize);
+    print!("redirected");
+    assert_eq!(dup3(saved, STDOUT, OpenFlags::empty()), STDOUT as isize);
+    close(saved);
+    close(fd);
+    let fd = open("dup_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(read(fd, &mut buffer), 10);
+    assert_eq!(&buffer[..10], b"redirected");
+
+    // 执行新程序时只关闭标记了 CLOEXEC 的描述符
+    assert_eq!(dup3(fd, KEEP_FD, OpenFlags::empty()), KEEP_FD as isize);
+    assert_eq!(
+        dup3(fd, CLOEXEC_FD, OpenFlags::CLOEXEC),
+        CLOEXEC_FD as isize
+    );
+    close(fd);
+    if fork() == 0 {
+        exec("filetest_dup_exec");
+        exit(-4);
+        unreachable!();
+    }
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 0);
+    // 只在子进程中执行了新程序，父进程的描述符都还在
+    assert_eq!(fstat(CLOEXEC_FD, &mut stat), 0);
+    // 目录、设备和不是 ELF 的文件都不能执行，执行失败时进程和描述符都不变
+    for path in ["/", "/dev/zero", "dup_file"] {
+        assert_eq!(exec(path), -1);
+    }
+    assert_eq!(fstat(CLOEXEC_FD, &mut stat), 0);
+    close(KEEP_FD);
+    close(CLOEXEC_FD);
+    println!("filetest_dup passed!");
+    0
+}
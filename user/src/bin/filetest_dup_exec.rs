@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+extern crate user_lib;
+
+use user_lib::{fstat, read, Stat, StatMode};
+
+/// 由 filetest_dup 执行，检查执行新程序后保留和关闭的描述符
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let mut stat = Stat::default();
+    assert_eq!(fstat(10, &mut stat), 0);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(fstat(11, &mut stat), -1);
+    // 继承的描述符保留了读写位置，文件已经读完
+    let mut buffer = [0u8; 4];
+    assert_eq!(read(10, &mut buffer), 0);
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, link, mkdir, open, read, unlink, write, OpenFlags};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let test_str = "Hello, link!";
+    let fd = open("link_src\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    assert_eq!(mkdir("link_dir\0"), 0);
+    assert_eq!(link("link_src\0", "link_dir/link_dst\0"), 0);
+    // 目标已存在、源不存在、源是目录都应失败
+    assert_eq!(link("link_src\0", "link_dir/link_dst\0"), -1);
+    assert_eq!(link("no_such_file\0", "link_other\0"), -1);
+    assert_eq!(link("link_dir\0", "link_other\0"), -1);
+
+    // 删除原名后仍能通过新名读到数据
+    assert_eq!(unlink("link_src\0"), 0);
+    assert_eq!(open("link_src\0", OpenFlags::RDONLY), -1);
+    let fd = open("link_dir/link_dst\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // 删除最后一个链接，目录不能被 unlink
+    assert_eq!(unlink("link_dir/link_dst\0"), 0);
+    assert_eq!(unlink("link_dir/link_dst\0"), -1);
+    assert_eq!(unlink("link_dir\0"), -1);
+    assert_eq!(open("link_dir/link_dst\0", OpenFlags::RDONLY), -1);
+    println!("filetest_link passed!");
+    0
+}
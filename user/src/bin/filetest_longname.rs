@@ -0,0 +1,64 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::{format, string::String};
+use user_lib::{close, mkdir, open, read, unlink, write, OpenFlags};
+
+/// 目录中创建的文件数，足够占满多个目录块
+const FILE_COUNT: usize = 200;
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    // 255 字节的文件名可以创建和读写，更长的不行
+    let mut name = String::from("long_");
+    while name.len() < 255 {
+        name.push((b'a' + (name.len() % 26) as u8) as char);
+    }
+    let path = format!("{name}\0");
+    let fd = open(&path, OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, name.as_bytes());
+    close(fd);
+    let fd = open(&path, OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 300];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(name.as_bytes(), &buffer[..read_len]);
+    assert_eq!(
+        open(&format!("{name}x\0"), OpenFlags::CREATE | OpenFlags::WRONLY),
+        -1
+    );
+    assert_eq!(unlink(&path), 0);
+
+    // 第一轮删除偶数号文件，第二轮重新创建它们再删除奇数号文件，空出的位置被复用
+    assert_eq!(mkdir("long_dir\0"), 0);
+    for round in 0..2 {
+        for i in 0..FILE_COUNT {
+            let fd = open(
+                &format!("long_dir/file_{i}\0"),
+                OpenFlags::CREATE | OpenFlags::WRONLY,
+            );
+            assert!(fd > 0);
+            close(fd as usize);
+        }
+        for i in (round..FILE_COUNT).step_by(2) {
+            assert_eq!(unlink(&format!("long_dir/file_{i}\0")), 0);
+        }
+    }
+    for i in 0..FILE_COUNT {
+        let fd = open(&format!("long_dir/file_{i}\0"), OpenFlags::RDONLY);
+        assert_eq!(fd > 0, i % 2 == 0);
+        if fd > 0 {
+            close(fd as usize);
+        }
+    }
+    println!("filetest_longname passed!");
+    0
+}
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::ptr::{read_volatile, write_volatile};
+use user_lib::{
+    close, mmap, munmap, open, pread, write, MapFlags, OpenFlags, ProtFlags, MAP_FAILED,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    // 两页多一点的文件，第二页开头是 b'B'
+    let fd = open("mmap_file\0", OpenFlags::CREATE | OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let page = [b'A'; PAGE_SIZE];
+    assert_eq!(write(fd, &page), PAGE_SIZE as isize);
+    let page = [b'B'; PAGE_SIZE];
+    assert_eq!(write(fd, &page), PAGE_SIZE as isize);
+    assert_eq!(write(fd, b"tail"), 4);
+
+    // 从第二页开始映射，超出文件的部分为零
+    let rw = ProtFlags::READ | ProtFlags::WRITE;
+    let len = 3 * PAGE_SIZE;
+    let addr = mmap(0, len, rw, MapFlags::PRIVATE, fd as isize, PAGE_SIZE);
+    assert_ne!(addr, MAP_FAILED);
+    let data = addr as usize as *mut u8;
+    // 关闭文件不影响已有的映射
+    close(fd);
+    assert_eq!(unsafe { read_volatile(data) }, b'B');
+    assert_eq!(unsafe { read_volatile(data.add(PAGE_SIZE - 1)) }, b'B');
+    assert_eq!(unsafe { read_volatile(data.add(PAGE_SIZE + 3)) }, b'l');
+    assert_eq!(unsafe { read_volatile(data.add(PAGE_SIZE + 4)) }, 0);
+    assert_eq!(unsafe { read_volatile(data.add(2 * PAGE_SIZE)) }, 0);
+
+    // 私有映射的写入不会写回文件
+    unsafe { write_volatile(data, b'x') };
+    assert_eq!(unsafe { read_volatile(data) }, b'x');
+    assert_eq!(munmap(addr as usize, len), 0);
+    let fd = open("mmap_file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 1];
+    assert_eq!(pread(fd, &mut buffer, PAGE_SIZE), 1);
+    assert_eq!(buffer[0], b'B');
+
+    // 只读打开的文件也能私有映射，偏移需要按页对齐，无效的文件不能映射
+    let addr = mmap(
+        0,
+        PAGE_SIZE,
+        ProtFlags::READ,
+        MapFlags::PRIVATE,
+        fd as isize,
+        0,
+    );
+    assert_ne!(addr, MAP_FAILED);
+    assert_eq!(unsafe { read_volatile(addr as usize as *const u8) }, b'A');
+    assert_eq!(munmap(addr as usize, PAGE_SIZE), 0);
+    assert_eq!(
+        mmap(
+            0,
+            PAGE_SIZE,
+            ProtFlags::READ,
+            MapFlags::PRIVATE,
+            fd as isize,
+            1
+        ),
+        MAP_FAILED
+    );
+    close(fd);
+    assert_eq!(
+        mmap(
+            0,
+            PAGE_SIZE,
+            ProtFlags::READ,
+            MapFlags::PRIVATE,
+            fd as isize,
+            0
+        ),
+        MAP_FAILED
+    );
+    println!("filetest_mmap passed!");
+    0
+}
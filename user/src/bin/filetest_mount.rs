@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mkdir, mount, open, umount, OpenFlags};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    assert_eq!(mkdir("mnt_dir\0"), 0);
+    let fd = open("mnt_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+
+    // 未知的文件系统类型不能挂载
+    assert_eq!(mount("\0", "mnt_dir\0", "no_such_fs\0"), -1);
+    // 根文件系统和不是挂载点的目录不能卸载
+    assert_eq!(umount("/\0"), -1);
+    assert_eq!(umount("mnt_dir\0"), -1);
+    assert_eq!(umount("mnt_file\0"), -1);
+    assert_eq!(umount("mnt_none\0"), -1);
+    println!("filetest_mount passed!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fstat, ftruncate, mkdir, open, read, rename, write, OpenFlags, Stat};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let test_str = "Hello, rename!";
+    assert_eq!(mkdir("mv_a\0"), 0);
+    assert_eq!(mkdir("mv_a/sub\0"), 0);
+    assert_eq!(mkdir("mv_b\0"), 0);
+    let fd = open("mv_a/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    // 同一目录内改名，再移动到另一个目录
+    assert_eq!(rename("mv_a/file\0", "mv_a/file2\0"), 0);
+    assert_eq!(open("mv_a/file\0", OpenFlags::RDONLY), -1);
+    assert_eq!(rename("mv_a/file2\0", "mv_b/file\0"), 0);
+    let fd = open("mv_b/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0u8; 100];
+    let read_len = read(fd, &mut buffer) as usize;
+    close(fd);
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+
+    // 移动目录后 `..` 指向新的父目录，目录不能移到自己下面
+    assert_eq!(rename("mv_a/sub\0", "mv_b/sub\0"), 0);
+    assert_eq!(rename("mv_b/sub/../file\0", "mv_b/file\0"), 0);
+    assert_eq!(rename("mv_b\0", "mv_b/sub/b\0"), -1);
+    assert_eq!(rename("mv_b/file\0", "mv_a\0"), -1);
+    assert_eq!(rename("mv_none\0", "mv_a/none\0"), -1);
+
+    // 截短后再加长，加长的部分读出为 0
+    let fd = open("mv_b/file\0", OpenFlags::RDWR);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(ftruncate(fd, 5), 0);
+    assert_eq!(ftruncate(fd, 4096), 0);
+    let mut stat = Stat::default();
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.size, 4096);
+    close(fd);
+    let fd = open("mv_b/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    let mut buffer = [0xffu8; 100];
+    assert_eq!(read(fd, &mut buffer), 100);
+    assert_eq!(&buffer[..5], &test_str.as_bytes()[..5]);
+    assert!(buffer[5..].iter().all(|b| *b == 0));
+    // 只读打开的文件不能截断
+    assert_eq!(ftruncate(fd, 0), -1);
+    close(fd);
+    println!("filetest_rename passed!");
+    0
+}
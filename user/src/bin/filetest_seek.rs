@@ -0,0 +1,92 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, lseek, open, pipe, pread, pwrite, read, write, OpenFlags, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// 在 `path` 上测试移动读写位置、定位读写和追加写，`path` 需以 `\0` 结尾
+fn test_file(path: &str) {
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR | OpenFlags::TRUNC);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"0123456789"), 10);
+
+    // 三种起点
+    let mut buffer = [0u8; 16];
+    assert_eq!(lseek(fd, 2, SEEK_SET), 2);
+    assert_eq!(read(fd, &mut buffer[..3]), 3);
+    assert_eq!(&buffer[..3], b"234");
+    assert_eq!(lseek(fd, 1, SEEK_CUR), 6);
+    assert_eq!(read(fd, &mut buffer[..1]), 1);
+    assert_eq!(buffer[0], b'6');
+    assert_eq!(lseek(fd, -3, SEEK_END), 7);
+    assert_eq!(read(fd, &mut buffer), 3);
+    assert_eq!(&buffer[..3], b"789");
+    assert_eq!(lseek(fd, -11, SEEK_END), -1);
+    assert_eq!(lseek(fd, 0, 3), -1);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 10);
+
+    // 定位读写不移动读写位置
+    assert_eq!(pwrite(fd, b"ab", 4), 2);
+    assert_eq!(pread(fd, &mut buffer[..6], 2), 6);
+    assert_eq!(&buffer[..6], b"23ab67");
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 10);
+
+    // 超过文件末尾写入，中间读出为 0
+    assert_eq!(lseek(fd, 14, SEEK_SET), 14);
+    assert_eq!(write(fd, b"x"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), 15);
+    assert_eq!(pread(fd, &mut buffer, 9), 6);
+    assert_eq!(&buffer[..6], b"9\0\0\0\0x");
+    close(fd);
+
+    // 追加写总是写到文件末尾，之后读写位置在末尾
+    let fd = open(path, OpenFlags::RDWR | OpenFlags::APPEND);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"yz"), 2);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 17);
+    assert_eq!(pread(fd, &mut buffer[..3], 14), 3);
+    assert_eq!(&buffer[..3], b"xyz");
+    assert_eq!(pread(fd, &mut buffer[..3], 0), 3);
+    assert_eq!(&buffer[..3], b"012");
+    close(fd);
+
+    // 文件已存在时 CREATE 不清空内容
+    let fd = open(
+        path,
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::APPEND,
+    );
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"!"), 1);
+    assert_eq!(lseek(fd, 0, SEEK_END), 18);
+    close(fd);
+    let fd = open(path, OpenFlags::RDONLY) as usize;
+    assert_eq!(pread(fd, &mut buffer, 0), 16);
+    assert_eq!(&buffer[..3], b"012");
+    assert_eq!(pread(fd, &mut buffer[..4], 14), 4);
+    assert_eq!(&buffer[..4], b"xyz!");
+    close(fd);
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    test_file("seek_file\0");
+    test_file("/tmp/seek_file\0");
+
+    // 管道不能移动读写位置
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    assert_eq!(lseek(pipe_fd[0], 0, SEEK_SET), -1);
+    assert_eq!(pwrite(pipe_fd[1], b"pipe", 0), -1);
+    close(pipe_fd[0]);
+    close(pipe_fd[1]);
+    println!("filetest_seek passed!");
+    0
+}
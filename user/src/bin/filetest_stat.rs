@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, fstat, link, open, unlink, write, OpenFlags, Stat, StatMode};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let test_str = "Hello, stat!";
+    let fd = open("stat_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+
+    let mut stat = Stat::default();
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.nlink, 1);
+    assert_eq!(stat.size, test_str.len() as u64);
+    assert_eq!(stat.perm, 0o644);
+    assert!(stat.crtime <= stat.mtime);
+
+    // 链接数随 link/unlink 变化，inode 号不变
+    let ino = stat.ino;
+    assert_eq!(link("stat_file\0", "stat_link\0"), 0);
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.nlink, 2);
+    assert_eq!(stat.ino, ino);
+    assert_eq!(unlink("stat_link\0"), 0);
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.nlink, 1);
+    close(fd);
+
+    let fd = open("/\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_eq!(stat.mode, StatMode::DIR);
+    assert_eq!(stat.ino, 0);
+    assert_eq!(stat.perm, 0o755);
+    close(fd);
+
+    // 打包进镜像的用户程序是可执行的
+    let fd = open("filetest_stat\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    assert_eq!(fstat(fd, &mut stat), 0);
+    assert_ne!(stat.perm & 0o111, 0);
+    close(fd);
+    assert_eq!(fstat(fd, &mut stat), -1);
+    println!("filetest_stat passed!");
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, mkdir, open, read, readlink, symlink, unlink, write, OpenFlags, Stat, StatMode,
+};
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let test_str = "Hello, symlink!";
+    assert_eq!(mkdir("sym_dir\0"), 0);
+    let fd = open("sym_dir/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    let fd = fd as usize;
+    write(fd, test_str.as_bytes());
+    close(fd);
+
+    // 相对路径、绝对路径和指向目录的链接
+    assert_eq!(symlink("sym_dir/file\0", "sym_rel\0"), 0);
+    assert_eq!(symlink("/sym_dir/file\0", "sym_abs\0"), 0);
+    assert_eq!(symlink("sym_dir\0", "sym_to_dir\0"), 0);
+    assert_eq!(symlink("sym_dir/file\0", "sym_rel\0"), -1);
+    for path in ["sym_rel\0", "sym_abs\0", "sym_to_dir/file\0"] {
+        let fd = open(path, OpenFlags::RDONLY);
+        assert!(fd > 0);
+        let fd = fd as usize;
+        let mut buffer = [0u8; 100];
+        let read_len = read(fd, &mut buffer) as usize;
+        let mut stat = Stat::default();
+        assert_eq!(fstat(fd, &mut stat), 0);
+        assert_eq!(stat.mode, StatMode::FILE);
+        close(fd);
+        assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
+    }
+
+    // readlink 返回链接本身的内容，对普通文件失败
+    let mut buffer = [0u8; 100];
+    let len = readlink("sym_rel\0", &mut buffer) as usize;
+    assert_eq!(&buffer[..len], b"sym_dir/file");
+    assert_eq!(readlink("sym_dir/file\0", &mut buffer), -1);
+
+    // 循环的链接无法打开
+    assert_eq!(symlink("sym_loop_b\0", "sym_loop_a\0"), 0);
+    assert_eq!(symlink("sym_loop_a\0", "sym_loop_b\0"), 0);
+    assert_eq!(open("sym_loop_a\0", OpenFlags::RDONLY), -1);
+
+    // 删除链接不影响目标，目标删除后链接悬空
+    assert_eq!(unlink("sym_abs\0"), 0);
+    let fd = open("sym_dir/file\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(unlink("sym_dir/file\0"), 0);
+    assert_eq!(open("sym_rel\0", OpenFlags::RDONLY), -1);
+    println!("filetest_symlink passed!");
+    0
+}
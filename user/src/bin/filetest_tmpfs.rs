@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, link, mkdir, mount, open, read, rename, umount, write, OpenFlags};
+
+/// 读出文件的全部内容，返回长度
+fn read_file(path: &str, buffer: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    if fd < 0 {
+        return fd;
+    }
+    let len = read(fd as usize, buffer);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let test_str = "Hello, tmpfs!";
+    let mut buffer = [0u8; 100];
+
+    // /tmp 在启动时挂载了 tmpfs
+    let fd = open("/tmp/file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    write(fd as usize, test_str.as_bytes());
+    close(fd as usize);
+    let len = read_file("/tmp/file\0", &mut buffer) as usize;
+    assert_eq!(test_str, core::str::from_utf8(&buffer[..len]).unwrap());
+
+    // 同一个 tmpfs 内可以建立硬链接和改名，跨文件系统不行
+    assert_eq!(link("/tmp/file\0", "/tmp/link\0"), 0);
+    assert_eq!(rename("/tmp/link\0", "/tmp/moved\0"), 0);
+    assert_eq!(read_file("/tmp/moved\0", &mut buffer) as usize, test_str.len());
+    assert_eq!(link("/tmp/file\0", "tmpfs_link\0"), -1);
+    assert_eq!(rename("/tmp/file\0", "tmpfs_file\0"), -1);
+
+    // 再挂载一个 tmpfs，卸载后其中的文件消失，原来目录下的文件重新可见
+    assert_eq!(mkdir("/tmp/sub\0"), 0);
+    let fd = open("/tmp/sub/under\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    assert_eq!(mount("tmpfs\0", "/tmp/sub\0", "tmpfs\0"), 0);
+    assert_eq!(open("/tmp/sub/under\0", OpenFlags::RDONLY), -1);
+    let fd = open("/tmp/sub/inner\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    // 其下还有挂载点时不能卸载
+    assert_eq!(umount("/tmp\0"), -1);
+    assert_eq!(umount("/tmp/sub\0"), 0);
+    assert_eq!(open("/tmp/sub/inner\0", OpenFlags::RDONLY), -1);
+    let fd = open("/tmp/sub/under\0", OpenFlags::RDONLY);
+    assert!(fd > 0);
+    close(fd as usize);
+    println!("filetest_tmpfs passed!");
+    0
+}
@@ -0,0 +1,62 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::{close, exit, fork, pipe, read, wait, write};
+
+/// 写入的字节数，超过管道缓冲区
+const LEN: usize = 10000;
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let [read_end, write_end] = pipe_fd;
+    if fork() == 0 {
+        // 子进程写，缓冲区满时阻塞到父进程读出
+        close(read_end);
+        let data: Vec<u8> = (0..LEN).map(|i| (i % 251) as u8).collect();
+        let mut written = 0;
+        while written < LEN {
+            let len = write(write_end, &data[written..]);
+            assert!(len > 0);
+            written += len as usize;
+        }
+        close(write_end);
+        exit(0);
+        unreachable!();
+    }
+    // 父进程读，写端全部关闭后读到文件尾
+    close(write_end);
+    let mut buffer = [0u8; 1000];
+    let mut total = 0;
+    loop {
+        let len = read(read_end, &mut buffer);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for (i, byte) in buffer[..len as usize].iter().enumerate() {
+            assert_eq!(*byte as usize, (total + i) % 251);
+        }
+        total += len as usize;
+    }
+    assert_eq!(total, LEN);
+    close(read_end);
+    let mut exit_code: i32 = 0;
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 0);
+
+    // 读端全部关闭后写入失败
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    close(pipe_fd[0]);
+    assert_eq!(write(pipe_fd[1], b"pipe"), -1);
+    close(pipe_fd[1]);
+    println!("pipetest passed!");
+    0
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use user_lib::*;
+
+/// 信号处理函数是否执行过
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+fn handler() {
+    HANDLED.store(true, Ordering::SeqCst);
+    sigreturn();
+}
+
+#[no_mangle]
+pub extern "C" fn main() -> i32 {
+    let mut new = SignalAction::default();
+    let old = SignalAction::default();
+    new.handler = handler as usize;
+    assert!(sigaction(SignalNo::SIGUSR1, &new, &old) >= 0);
+    let mut pipe_fd = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fd), 0);
+    let [read_end, write_end] = pipe_fd;
+    let parent = getpid();
+    if fork() == 0 {
+        // 子进程等父进程阻塞在空管道上，先发信号，再写入数据
+        close(read_end);
+        sleep(100);
+        assert_eq!(kill(parent, SignalNo::SIGUSR1), 0);
+        sleep(100);
+        assert_eq!(write(write_end, b"signal"), 6);
+        close(write_end);
+        exit(0);
+        unreachable!();
+    }
+    // 父进程读空管道，信号处理函数返回后继续读，读到子进程写入的数据
+    close(write_end);
+    let mut buffer = [0u8; 16];
+    let len = read(read_end, &mut buffer);
+    assert_eq!(len, 6);
+    assert_eq!(&buffer[..6], b"signal");
+    assert!(HANDLED.load(Ordering::SeqCst));
+    assert_eq!(read(read_end, &mut buffer), 0);
+    close(read_end);
+    let mut exit_code: i32 = 0;
+    assert!(wait(&mut exit_code) > 0);
+    assert_eq!(exit_code, 0);
+    println!("pipetest_signal passed!");
+    0
+}
@@ -12,8 +12,113 @@ const CR: u8 = 0x0du8;
 const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
-use alloc::string::String;
-use user_lib::{exec, fork, getchar, waitpid};
+use alloc::{string::String, vec::Vec};
+use user_lib::{
+    close, dup3, exec, exit, fork, getchar, open, pipe2, waitpid, OpenFlags, STDIN, STDOUT,
+};
+
+/// 一个命令，`< file` 和 `> file` 把标准输入输出重定向到文件
+struct Command<'a> {
+    app: &'a str,
+    input: Option<&'a str>,
+    output: Option<&'a str>,
+}
+
+impl<'a> Command<'a> {
+    /// 解析命令，格式错误时返回 `None`
+    fn parse(command: &'a str) -> Option<Self> {
+        let mut app = None;
+        let mut input = None;
+        let mut output = None;
+        let mut words = command.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "<" => input = Some(words.next()?),
+                ">" => output = Some(words.next()?),
+                _ if app.is_none() => app = Some(word),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            app: app?,
+            input,
+            output,
+        })
+    }
+}
+
+/// 打开 `path` 并替换描述符 `fd`，失败时退出
+fn redirect(path: &str, flags: OpenFlags, fd: usize) {
+    let mut c_path = String::from(path);
+    c_path.push('\0');
+    let file = open(c_path.as_str(), flags);
+    if file == -1 {
+        println!("Error when opening {}", path);
+        exit(-4);
+    }
+    assert_eq!(dup3(file as usize, fd, OpenFlags::empty()), fd as isize);
+    close(file as usize);
+}
+
+/// 执行一行命令，命令之间可以用 `|` 连接，前一个命令的标准输出接到后一个命令的标准输入
+fn run(line: &str) {
+    let mut commands = Vec::new();
+    for command in line.split('|') {
+        match Command::parse(command) {
+            Some(command) => commands.push(command),
+            None => {
+                println!("Error: invalid command");
+                return;
+            }
+        }
+    }
+    // 管道两端都在执行新程序时关闭，子进程只留下 dup3 到标准输入输出上的那一端，
+    // 否则读的一方等不到文件尾
+    let mut pipes: Vec<[usize; 2]> = Vec::new();
+    for _ in 1..commands.len() {
+        let mut pipe_fd = [0usize; 2];
+        assert_eq!(pipe2(&mut pipe_fd, OpenFlags::CLOEXEC), 0);
+        pipes.push(pipe_fd);
+    }
+    let mut pids = Vec::new();
+    for (i, command) in commands.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            // child process
+            if i > 0 {
+                dup3(pipes[i - 1][0], STDIN, OpenFlags::empty());
+            }
+            if i + 1 < commands.len() {
+                dup3(pipes[i][1], STDOUT, OpenFlags::empty());
+            }
+            if let Some(input) = command.input {
+                redirect(input, OpenFlags::RDONLY, STDIN);
+            }
+            if let Some(output) = command.output {
+                redirect(
+                    output,
+                    OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC,
+                    STDOUT,
+                );
+            }
+            if exec(command.app) == -1 {
+                println!("Error when executing!");
+                exit(-4);
+            }
+            unreachable!();
+        }
+        pids.push(pid);
+    }
+    for fd in pipes.iter().flatten() {
+        close(*fd);
+    }
+    for pid in pids {
+        let mut exit_code: i32 = 0;
+        let exit_pid = waitpid(pid, &mut exit_code);
+        assert_eq!(pid, exit_pid);
+        println!("Shell: Process {} exited with code {}", pid, exit_code);
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
@@ -27,20 +132,7 @@ pub extern "C" fn main() -> i32 {
                 // 换行
                 println!();
                 if !line.is_empty() {
-                    let pid = fork();
-                    if pid == 0 {
-                        // child process
-                        if exec(line.as_str()) == -1 {
-                            println!("Error when executing!");
-                            return -4;
-                        }
-                        unreachable!();
-                    } else {
-                        let mut exit_code: i32 = 0;
-                        let exit_pid = waitpid(pid as isize, &mut exit_code);
-                        assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
-                    }
+                    run(line.as_str());
                     line.clear();
                 }
                 print!(">> ");
@@ -0,0 +1,185 @@
+use crate::FileHandle;
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+
+/// 一个进程最多打开的文件数
+pub const FD_LIMIT: usize = 1024;
+
+/// 文件描述符表中的一项
+#[derive(Clone)]
+struct FdEntry {
+    file: Arc<Mutex<FileHandle>>,
+    /// 执行新程序时关闭
+    cloexec: bool,
+}
+
+/// 文件描述符表
+///
+/// 表项指向打开的文件，`dup` 得到的描述符和 `fork` 复制的表与原来的描述符指向同一个打开的文件，
+/// 共享读写位置。新的描述符总是取最小的空闲编号。
+#[derive(Clone, Default)]
+pub struct FdTable {
+    entries: Vec<Option<FdEntry>>,
+}
+
+impl FdTable {
+    /// 创建空的描述符表
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// 描述符 `fd` 指向的打开的文件
+    pub fn get(&self, fd: usize) -> Option<Arc<Mutex<FileHandle>>> {
+        self.entries
+            .get(fd)
+            .and_then(|entry| entry.as_ref())
+            .map(|entry| entry.file.clone())
+    }
+
+    /// 加入打开的文件，返回描述符，打开的文件过多时返回 `None`
+    pub fn insert(&mut self, file: FileHandle, cloexec: bool) -> Option<usize> {
+        self.insert_shared(Arc::new(Mutex::new(file)), cloexec)
+    }
+
+    /// 关闭描述符 `fd`，它没有打开时返回 `false`
+    pub fn close(&mut self, fd: usize) -> bool {
+        match self.entries.get_mut(fd) {
+            Some(entry) if entry.is_some() => {
+                *entry = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 复制描述符 `fd`，新描述符取最小的空闲编号，执行新程序时不关闭
+    pub fn dup(&mut self, fd: usize) -> Option<usize> {
+        let file = self.get(fd)?;
+        self.insert_shared(file, false)
+    }
+
+    /// 复制描述符 `old_fd` 到 `new_fd`，`new_fd` 已打开时先关闭它。
+    ///
+    /// `old_fd` 没有打开、两者相同或 `new_fd` 超出上限时返回 `None`。
+    pub fn dup3(&mut self, old_fd: usize, new_fd: usize, cloexec: bool) -> Option<usize> {
+        let file = self.get(old_fd)?;
+        if old_fd == new_fd || new_fd >= FD_LIMIT {
+            return None;
+        }
+        if new_fd >= self.entries.len() {
+            self.entries.resize(new_fd + 1, None);
+        }
+        self.entries[new_fd] = Some(FdEntry { file, cloexec });
+        Some(new_fd)
+    }
+
+    /// 关闭标记为执行新程序时关闭的描述符
+    pub fn close_on_exec(&mut self) {
+        for entry in self.entries.iter_mut() {
+            if matches!(entry, Some(entry) if entry.cloexec) {
+                *entry = None;
+            }
+        }
+    }
+
+    fn insert_shared(&mut self, file: Arc<Mutex<FileHandle>>, cloexec: bool) -> Option<usize> {
+        let fd = match self.entries.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None if self.entries.len() < FD_LIMIT => {
+                self.entries.push(None);
+                self.entries.len() - 1
+            }
+            None => return None,
+        };
+        self.entries[fd] = Some(FdEntry { file, cloexec });
+        Some(fd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 只有 `count` 个描述符的表
+    fn table(count: usize) -> FdTable {
+        let mut fd_table = FdTable::new();
+        for fd in 0..count {
+            assert_eq!(
+                fd_table.insert(FileHandle::empty(true, false), false),
+                Some(fd)
+            );
+        }
+        fd_table
+    }
+
+    fn same_file(fd_table: &FdTable, a: usize, b: usize) -> bool {
+        Arc::ptr_eq(&fd_table.get(a).unwrap(), &fd_table.get(b).unwrap())
+    }
+
+    #[test]
+    fn new_descriptors_take_the_lowest_free_number() {
+        let mut fd_table = table(3);
+        assert!(fd_table.close(1));
+        assert!(!fd_table.close(1));
+        assert!(!fd_table.close(5));
+        assert_eq!(
+            fd_table.insert(FileHandle::empty(true, false), false),
+            Some(1)
+        );
+        // dup 的描述符指向同一个打开的文件
+        assert_eq!(fd_table.dup(2), Some(3));
+        assert!(same_file(&fd_table, 2, 3));
+        assert!(fd_table.close(0));
+        assert_eq!(fd_table.dup(3), Some(0));
+        assert!(same_file(&fd_table, 0, 2));
+        assert_eq!(fd_table.dup(9), None);
+    }
+
+    #[test]
+    fn descriptors_are_limited() {
+        let mut fd_table = table(FD_LIMIT);
+        assert_eq!(fd_table.insert(FileHandle::empty(true, false), false), None);
+        assert_eq!(fd_table.dup(0), None);
+        assert!(fd_table.close(FD_LIMIT / 2));
+        assert_eq!(fd_table.dup(0), Some(FD_LIMIT / 2));
+    }
+
+    #[test]
+    fn dup3_and_close_on_exec() {
+        let mut fd_table = table(2);
+        assert_eq!(fd_table.dup3(0, 10, true), Some(10));
+        assert!(same_file(&fd_table, 0, 10));
+        // 中间空出的描述符可以再使用
+        assert_eq!(
+            fd_table.insert(FileHandle::empty(true, false), false),
+            Some(2)
+        );
+        // 目标已打开时被替换
+        assert_eq!(fd_table.dup3(1, 0, false), Some(0));
+        assert!(same_file(&fd_table, 0, 1));
+        assert_eq!(fd_table.dup3(1, 1, false), None);
+        assert_eq!(fd_table.dup3(7, 3, false), None);
+        assert_eq!(fd_table.dup3(1, FD_LIMIT, false), None);
+
+        let cloexec = fd_table
+            .insert(FileHandle::empty(true, false), true)
+            .unwrap();
+        let mut child = fd_table.clone();
+        child.close_on_exec();
+        assert!(child.get(10).is_none());
+        assert!(child.get(cloexec).is_none());
+        assert!(child.get(0).is_some() && child.get(2).is_some());
+        // fork 复制的表与原来的表共享打开的文件
+        assert!(Arc::ptr_eq(
+            &child.get(0).unwrap(),
+            &fd_table.get(0).unwrap()
+        ));
+        assert!(fd_table.get(10).is_some());
+        // dup 得到的描述符执行新程序时不关闭
+        let fd = fd_table.dup(cloexec).unwrap();
+        fd_table.close_on_exec();
+        assert!(fd_table.get(fd).is_some());
+    }
+}
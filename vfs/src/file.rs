@@ -0,0 +1,332 @@
+use crate::{FileType, Inode, PipeEnd};
+use alloc::{string::String, sync::Arc, vec::Vec};
+use bitflags::*;
+
+/// 用户传来的一组缓冲区
+pub struct UserBuffer {
+    /// 各段缓冲区
+    pub buffers: Vec<&'static mut [u8]>,
+}
+
+impl UserBuffer {
+    /// 由各段缓冲区创建
+    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
+        Self { buffers }
+    }
+
+    /// 总字节数
+    pub fn len(&self) -> usize {
+        self.buffers.iter().map(|b| b.len()).sum()
+    }
+
+    /// 是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+bitflags! {
+    /// 打开文件的选项
+    pub struct OpenFlags: u32 {
+        /// 只读
+        const RDONLY = 0;
+        /// 只写
+        const WRONLY = 1 << 0;
+        /// 读写
+        const RDWR = 1 << 1;
+        /// 不存在时创建
+        const CREATE = 1 << 9;
+        /// 清空文件
+        const TRUNC = 1 << 10;
+        /// 每次写入前把读写位置移到文件末尾
+        const APPEND = 1 << 11;
+        /// 执行新程序时关闭
+        const CLOEXEC = 1 << 19;
+    }
+}
+
+impl OpenFlags {
+    /// 返回 (可读, 可写)，为简单起见不检查选项是否合法
+    pub fn read_write(&self) -> (bool, bool) {
+        // 只有访问模式决定读写权限，CREATE、TRUNC、APPEND 等选项与之无关
+        let mode = self.intersection(Self::WRONLY | Self::RDWR);
+        if mode.is_empty() {
+            (true, false)
+        } else if mode.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// 移动读写位置的方式，见 [`FileHandle::seek`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeekFrom {
+    /// 移到文件开头之后的第几个字节
+    Start(usize),
+    /// 从当前位置前后移动
+    Current(isize),
+    /// 从文件末尾前后移动
+    End(isize),
+}
+
+/// 打开的文件
+#[derive(Clone)]
+pub struct FileHandle {
+    /// 文件对应的 inode，管道没有 inode
+    pub inode: Option<Arc<dyn Inode>>,
+    /// 管道的一端
+    pub pipe: Option<PipeEnd>,
+    /// 可读
+    pub read: bool,
+    /// 可写
+    pub write: bool,
+    /// 当前读写位置
+    pub offset: usize,
+    /// 追加写，每次写入前把读写位置移到文件末尾
+    pub append: bool,
+}
+
+impl FileHandle {
+    /// 打开 `inode`
+    pub fn new(read: bool, write: bool, inode: Arc<dyn Inode>) -> Self {
+        Self {
+            inode: Some(inode),
+            pipe: None,
+            read,
+            write,
+            offset: 0,
+            append: false,
+        }
+    }
+
+    /// 打开管道的一端
+    pub fn pipe(end: PipeEnd) -> Self {
+        Self {
+            inode: None,
+            read: !end.writable(),
+            write: end.writable(),
+            pipe: Some(end),
+            offset: 0,
+            append: false,
+        }
+    }
+
+    /// 没有 inode 的文件，读写都会失败
+    pub fn empty(read: bool, write: bool) -> Self {
+        Self {
+            inode: None,
+            pipe: None,
+            read,
+            write,
+            offset: 0,
+            append: false,
+        }
+    }
+
+    /// 是否可读
+    pub fn readable(&self) -> bool {
+        self.read
+    }
+
+    /// 是否可写
+    pub fn writable(&self) -> bool {
+        self.write
+    }
+
+    /// 从当前位置读到 `buf`，返回读到的字节数。
+    ///
+    /// 管道中暂时没有数据时返回 [`WOULD_BLOCK`](crate::WOULD_BLOCK)。
+    pub fn read(&mut self, mut buf: UserBuffer) -> isize {
+        if let Some(pipe) = &self.pipe {
+            return pipe.read(buf);
+        }
+        if let Some(inode) = &self.inode {
+            let len = read_inode(inode.as_ref(), self.offset, &mut buf);
+            self.offset += len;
+            len as _
+        } else {
+            -1
+        }
+    }
+
+    /// 把 `buf` 写到当前位置，追加写时写到文件末尾，返回写入的字节数。
+    ///
+    /// 管道已满时返回 [`WOULD_BLOCK`](crate::WOULD_BLOCK)。
+    pub fn write(&mut self, buf: UserBuffer) -> isize {
+        if let Some(pipe) = &self.pipe {
+            return pipe.write(buf);
+        }
+        if let Some(inode) = &self.inode {
+            if self.append {
+                let mut total_write_size = 0;
+                for slice in buf.buffers.iter() {
+                    let write_size = inode.append(slice);
+                    total_write_size += write_size;
+                    if write_size < slice.len() {
+                        break;
+                    }
+                }
+                self.offset = inode.metadata().size as usize;
+                total_write_size as _
+            } else {
+                let len = write_inode(inode.as_ref(), self.offset, &buf);
+                self.offset += len;
+                len as _
+            }
+        } else {
+            -1
+        }
+    }
+
+    /// 从 `offset` 处读到 `buf`，不移动读写位置，返回读到的字节数。
+    ///
+    /// 管道和没有 inode 的文件返回 -1。
+    pub fn read_at(&self, offset: usize, mut buf: UserBuffer) -> isize {
+        match self.seekable_inode() {
+            Some(inode) => read_inode(inode, offset, &mut buf) as _,
+            None => -1,
+        }
+    }
+
+    /// 把 `buf` 写到 `offset` 处，不移动读写位置，追加写时也写到 `offset` 处，返回写入的字节数。
+    ///
+    /// 管道和没有 inode 的文件返回 -1。
+    pub fn write_at(&self, offset: usize, buf: UserBuffer) -> isize {
+        match self.seekable_inode() {
+            Some(inode) => write_inode(inode, offset, &buf) as _,
+            None => -1,
+        }
+    }
+
+    /// 移动读写位置，返回新的位置。
+    ///
+    /// 新位置可以超过文件末尾，之后写入时中间的部分读出为 0。
+    /// 管道、字符设备和没有 inode 的文件不能移动，新位置小于 0 时也失败，返回 -1。
+    pub fn seek(&mut self, pos: SeekFrom) -> isize {
+        let inode = match self.seekable_inode() {
+            Some(inode) => inode,
+            None => return -1,
+        };
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => (inode.metadata().size as usize).checked_add_signed(delta),
+        };
+        match offset {
+            Some(offset) if offset <= isize::MAX as usize => {
+                self.offset = offset;
+                offset as _
+            }
+            _ => -1,
+        }
+    }
+
+    /// 可以定位读写的 inode
+    fn seekable_inode(&self) -> Option<&dyn Inode> {
+        match (&self.pipe, &self.inode) {
+            (None, Some(inode)) if inode.metadata().type_ != FileType::CharDevice => {
+                Some(inode.as_ref())
+            }
+            _ => None,
+        }
+    }
+
+    /// 读写返回 [`WOULD_BLOCK`](crate::WOULD_BLOCK) 后登记等待的任务，
+    /// 管道可能可以读写时调用 `wake(task)`，不是管道时什么也不做
+    pub fn wait(&self, task: usize, wake: fn(usize)) {
+        if let Some(pipe) = &self.pipe {
+            pipe.wait(task, wake);
+        }
+    }
+}
+
+/// 从 `offset` 处把 `inode` 的内容读到各段缓冲区，返回读到的字节数
+fn read_inode(inode: &dyn Inode, mut offset: usize, buf: &mut UserBuffer) -> usize {
+    let mut total_read_size = 0;
+    for slice in buf.buffers.iter_mut() {
+        let read_size = inode.read_at(offset, slice);
+        offset += read_size;
+        total_read_size += read_size;
+        if read_size < slice.len() {
+            break;
+        }
+    }
+    total_read_size
+}
+
+/// 把各段缓冲区写到 `inode` 的 `offset` 处，返回写入的字节数，文件不能再变大时只写入一部分
+fn write_inode(inode: &dyn Inode, mut offset: usize, buf: &UserBuffer) -> usize {
+    let mut total_write_size = 0;
+    for slice in buf.buffers.iter() {
+        let write_size = inode.write_at(offset, slice);
+        offset += write_size;
+        total_write_size += write_size;
+        if write_size < slice.len() {
+            break;
+        }
+    }
+    total_write_size
+}
+
+/// 内核使用的文件系统接口，路径都从根目录开始解析
+pub trait FSManager {
+    /// 打开文件
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>>;
+
+    /// 查找文件
+    fn find(&self, path: &str) -> Option<Arc<dyn Inode>>;
+
+    /// 创建目录
+    fn mkdir(&self, path: &str) -> isize;
+
+    /// 为 `src` 创建硬链接 `dst`
+    fn link(&self, src: &str, dst: &str) -> isize;
+
+    /// 删除一个硬链接
+    fn unlink(&self, path: &str) -> isize;
+
+    /// 把 `old` 移动到 `new`，`new` 已存在且不是目录时被替换
+    fn rename(&self, old: &str, new: &str) -> isize;
+
+    /// 创建指向 `target` 的符号链接 `linkpath`
+    fn symlink(&self, target: &str, linkpath: &str) -> isize;
+
+    /// 读出符号链接指向的路径
+    fn readlink(&self, path: &str) -> Option<String>;
+
+    /// 列出目录中的所有名字
+    fn readdir(&self, path: &str) -> Option<Vec<String>>;
+
+    /// 把 `source` 上类型为 `fs_type` 的文件系统挂载到目录 `target`
+    fn mount(&self, source: &str, target: &str, fs_type: &str) -> isize;
+
+    /// 卸载挂载在 `target` 上的文件系统
+    fn umount(&self, target: &str) -> isize;
+
+    /// 把所有缓存的修改写回
+    fn sync(&self);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_mode_decides_read_write() {
+        assert_eq!(OpenFlags::RDONLY.read_write(), (true, false));
+        assert_eq!(OpenFlags::WRONLY.read_write(), (false, true));
+        assert_eq!(OpenFlags::RDWR.read_write(), (true, true));
+        for flag in [
+            OpenFlags::CREATE,
+            OpenFlags::TRUNC,
+            OpenFlags::APPEND,
+            OpenFlags::CLOEXEC,
+        ] {
+            assert_eq!((OpenFlags::RDONLY | flag).read_write(), (true, false));
+            assert_eq!((OpenFlags::WRONLY | flag).read_write(), (false, true));
+            assert_eq!((OpenFlags::RDWR | flag).read_write(), (true, true));
+        }
+    }
+}
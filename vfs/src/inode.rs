@@ -0,0 +1,120 @@
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::any::Any;
+
+/// 文件类型
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileType {
+    /// 普通文件
+    File,
+    /// 目录
+    Directory,
+    /// 符号链接
+    SymLink,
+    /// 字符设备，如控制台
+    CharDevice,
+    /// 块设备，如磁盘
+    BlockDevice,
+}
+
+/// 文件的元数据
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    /// 文件系统内的 inode 编号
+    pub ino: u64,
+    /// 文件类型
+    pub type_: FileType,
+    /// 硬链接数
+    pub nlink: u32,
+    /// 字节数
+    pub size: u64,
+    /// 权限位，如 `0o644`
+    pub mode: u32,
+    /// 最后访问时间，单位为秒
+    pub atime: u64,
+    /// 最后修改时间，单位为秒
+    pub mtime: u64,
+    /// 创建时间，单位为秒
+    pub crtime: u64,
+}
+
+/// 文件系统中的一个文件、目录或符号链接
+///
+/// 目录项的名字不含 `/`，也不会是 `.` 或 `..`，它们由 [`MountTable`](crate::MountTable) 处理。
+/// 文件系统不支持的操作使用默认实现，总是失败。
+pub trait Inode: Send + Sync {
+    /// 获取元数据
+    fn metadata(&self) -> Metadata;
+
+    /// 从 `offset` 处读出数据，返回读到的字节数
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+
+    /// 从 `offset` 处写入数据，返回写入的字节数
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+
+    /// 把数据写到文件末尾，返回写入的字节数
+    ///
+    /// 默认实现先取文件大小再写入，文件系统可以覆盖它，让两步之间不被其他写入打断。
+    fn append(&self, buf: &[u8]) -> usize {
+        self.write_at(self.metadata().size as usize, buf)
+    }
+
+    /// 把文件长度设为 `len`
+    fn truncate(&self, _len: usize) -> bool {
+        false
+    }
+
+    /// 把这个文件所在文件系统缓存的修改写回
+    fn sync(&self) {}
+
+    /// 在目录中按名字查找
+    fn find(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+
+    /// 在目录中创建类型为 `type_` 的文件或目录，名字已存在时失败
+    fn create(&self, _name: &str, _type_: FileType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+
+    /// 在目录中创建指向 `target` 的符号链接
+    fn symlink(&self, _name: &str, _target: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+
+    /// 读出符号链接指向的路径
+    fn readlink(&self) -> Option<String> {
+        None
+    }
+
+    /// 在目录中创建指向 `target` 的硬链接，`target` 需在同一个文件系统中
+    fn link(&self, _name: &str, _target: &Arc<dyn Inode>) -> bool {
+        false
+    }
+
+    /// 删除目录中的一项，不能删除目录
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// 把目录中的 `old_name` 移动到同一个文件系统的目录 `new_dir` 中，改名为 `new_name`
+    fn rename(&self, _old_name: &str, _new_dir: &Arc<dyn Inode>, _new_name: &str) -> bool {
+        false
+    }
+
+    /// 列出目录中的所有名字
+    fn readdir(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// 转换为 [`Any`]，用于在 [`Inode::link`] 等操作中取得同一文件系统的具体类型
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// 一个可以挂载的文件系统实例
+pub trait FileSystem: Send + Sync {
+    /// 获取根目录
+    fn root_inode(&self) -> Arc<dyn Inode>;
+
+    /// 把缓存的修改写回
+    fn sync(&self);
+}
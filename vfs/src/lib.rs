@@ -0,0 +1,22 @@
+//! 虚拟文件系统层
+//!
+//! 各种文件系统通过实现 [`FileSystem`] 和 [`Inode`] 接入，
+//! [`MountTable`] 把它们挂载到同一棵目录树上，内核只通过 [`FSManager`] 访问文件。
+//! 打开的文件 [`FileHandle`] 也可以是管道的一端，进程用 [`FdTable`] 管理打开的文件。
+
+#![no_std]
+#![deny(warnings, missing_docs)]
+
+extern crate alloc;
+
+mod fd;
+mod file;
+mod inode;
+mod mount;
+mod pipe;
+
+pub use fd::{FdTable, FD_LIMIT};
+pub use file::{FSManager, FileHandle, OpenFlags, SeekFrom, UserBuffer};
+pub use inode::{FileSystem, FileType, Inode, Metadata};
+pub use mount::{MountFn, MountTable, SYMLINK_LIMIT};
+pub use pipe::{make_pipe, PipeEnd, PIPE_BUFFER_SIZE, WOULD_BLOCK};
@@ -0,0 +1,555 @@
+use crate::{FSManager, FileHandle, FileSystem, FileType, Inode, OpenFlags};
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use spin::Mutex;
+
+/// 解析一个路径时最多跟随的符号链接数
+pub const SYMLINK_LIMIT: usize = 8;
+
+/// 由挂载源创建文件系统实例，源不可用时返回 `None`
+pub type MountFn = fn(source: &str) -> Option<Arc<dyn FileSystem>>;
+
+/// 挂载表，把目录树上的路径映射到文件系统实例
+///
+/// 挂载点用规范化的绝对路径表示，即去掉首尾的 `/`，消去 `.`、`..` 和符号链接，
+/// 根目录的挂载点是空串。解析路径时进入挂载点就换到挂载的文件系统的根目录，
+/// 在挂载的根目录下 `..` 回到挂载点所在的目录。
+pub struct MountTable {
+    /// 挂载点到文件系统
+    mounts: Mutex<BTreeMap<String, Arc<dyn FileSystem>>>,
+    /// 文件系统类型到创建函数
+    fs_types: Mutex<BTreeMap<String, MountFn>>,
+}
+
+/// 解析路径时经过的一级目录
+struct Step {
+    name: String,
+    inode: Arc<dyn Inode>,
+}
+
+/// 把经过的各级目录连成规范化的路径
+fn join(steps: &[Step]) -> String {
+    let mut path = String::new();
+    for (i, step) in steps.iter().enumerate() {
+        if i > 0 {
+            path.push('/');
+        }
+        path.push_str(&step.name);
+    }
+    path
+}
+
+/// 规范化的路径 `dir` 下的 `name`
+fn child(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        String::from(name)
+    } else {
+        let mut path = String::from(dir);
+        path.push('/');
+        path.push_str(name);
+        path
+    }
+}
+
+impl MountTable {
+    /// 以 `root` 为根文件系统创建挂载表
+    pub fn new(root: Arc<dyn FileSystem>) -> Self {
+        let mut mounts = BTreeMap::new();
+        mounts.insert(String::new(), root);
+        Self {
+            mounts: Mutex::new(mounts),
+            fs_types: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 注册可以由 [`FSManager::mount`] 挂载的文件系统类型
+    pub fn register(&self, fs_type: &str, mount: MountFn) {
+        self.fs_types.lock().insert(String::from(fs_type), mount);
+    }
+
+    /// 把 `fs` 挂载到目录 `target`，`target` 已经是挂载点时失败
+    pub fn mount_fs(&self, target: &str, fs: Arc<dyn FileSystem>) -> bool {
+        let (inode, path) = match self.walk(target, true) {
+            Some(found) => found,
+            None => return false,
+        };
+        if inode.metadata().type_ != FileType::Directory {
+            return false;
+        }
+        let mut mounts = self.mounts.lock();
+        if mounts.contains_key(&path) {
+            return false;
+        }
+        mounts.insert(path, fs);
+        true
+    }
+
+    /// 卸载挂载在 `target` 上的文件系统，返回它。
+    ///
+    /// 根文件系统和其下还有挂载点的文件系统不能卸载。
+    /// 已经打开的文件仍然可以读写卸载的文件系统。
+    pub fn umount_fs(&self, target: &str) -> Option<Arc<dyn FileSystem>> {
+        let (_, path) = self.walk(target, true)?;
+        if path.is_empty() {
+            return None;
+        }
+        let mut mounts = self.mounts.lock();
+        if mounts.keys().any(|mount_point| is_under(mount_point, &path)) {
+            return None;
+        }
+        let fs = mounts.remove(&path)?;
+        fs.sync();
+        Some(fs)
+    }
+
+    /// 根目录
+    fn root(&self) -> Arc<dyn Inode> {
+        self.mounts.lock()[""].root_inode()
+    }
+
+    /// 从根目录解析 `path`，返回 inode 和它规范化的路径。
+    ///
+    /// 最后一个分量是符号链接时，`follow_last` 决定是否跟随。
+    fn walk(&self, path: &str, follow_last: bool) -> Option<(Arc<dyn Inode>, String)> {
+        let root = self.root();
+        let mut steps: Vec<Step> = Vec::new();
+        let mut pending: VecDeque<String> = path
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+        let mut links = 0;
+        while let Some(name) = pending.pop_front() {
+            match name.as_str() {
+                "." => continue,
+                ".." => {
+                    steps.pop();
+                    continue;
+                }
+                _ => {}
+            }
+            let dir = steps.last().map_or(&root, |step| &step.inode);
+            let mut inode = dir.find(&name)?;
+            if let Some(fs) = self.mounts.lock().get(&child(&join(&steps), &name)) {
+                inode = fs.root_inode();
+            }
+            if inode.metadata().type_ == FileType::SymLink && (follow_last || !pending.is_empty()) {
+                links += 1;
+                if links > SYMLINK_LIMIT {
+                    return None;
+                }
+                let target = inode.readlink()?;
+                // 绝对路径从根目录开始
+                if target.starts_with('/') {
+                    steps.clear();
+                }
+                for name in target.split('/').filter(|name| !name.is_empty()).rev() {
+                    pending.push_front(String::from(name));
+                }
+                continue;
+            }
+            steps.push(Step { name, inode });
+        }
+        let path = join(&steps);
+        let inode = steps.pop().map_or(root, |step| step.inode);
+        Some((inode, path))
+    }
+
+    /// 解析 `path` 最后一个分量所在的目录，返回目录、它规范化的路径和最后一个分量，
+    /// 最后一个分量可以不存在
+    fn walk_parent<'a>(&self, path: &'a str) -> Option<(Arc<dyn Inode>, String, &'a str)> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() || name == "." || name == ".." {
+            return None;
+        }
+        let (dir, dir_path) = self.walk(parent, true)?;
+        if dir.metadata().type_ != FileType::Directory {
+            return None;
+        }
+        Some((dir, dir_path, name))
+    }
+
+    /// 规范化的路径 `path` 是不是挂载点，或者其下有挂载点
+    fn is_busy(&self, path: &str) -> bool {
+        self.mounts
+            .lock()
+            .keys()
+            .any(|mount_point| mount_point == path || is_under(mount_point, path))
+    }
+}
+
+/// 规范化的路径 `path` 是否在目录 `dir` 之下
+fn is_under(path: &str, dir: &str) -> bool {
+    path.len() > dir.len()
+        && path.starts_with(dir)
+        && (dir.is_empty() || path.as_bytes()[dir.len()] == b'/')
+}
+
+impl FSManager for MountTable {
+    fn open(&self, path: &str, flags: OpenFlags) -> Option<Arc<FileHandle>> {
+        let (readable, writable) = flags.read_write();
+        let inode = match self.find(path) {
+            Some(inode) => {
+                if inode.metadata().type_ == FileType::Directory && flags.contains(OpenFlags::CREATE)
+                {
+                    return None;
+                }
+                // 已存在的文件只在指定 TRUNC 时清空
+                if flags.contains(OpenFlags::TRUNC) {
+                    inode.truncate(0);
+                }
+                inode
+            }
+            None if flags.contains(OpenFlags::CREATE) => {
+                let (dir, _, name) = self.walk_parent(path)?;
+                dir.create(name, FileType::File)?
+            }
+            None => return None,
+        };
+        let mut file = FileHandle::new(readable, writable, inode);
+        file.append = flags.contains(OpenFlags::APPEND);
+        Some(Arc::new(file))
+    }
+
+    fn find(&self, path: &str) -> Option<Arc<dyn Inode>> {
+        self.walk(path, true).map(|(inode, _)| inode)
+    }
+
+    fn mkdir(&self, path: &str) -> isize {
+        match self
+            .walk_parent(path)
+            .and_then(|(dir, _, name)| dir.create(name, FileType::Directory))
+        {
+            Some(_) => 0,
+            None => -1,
+        }
+    }
+
+    fn link(&self, src: &str, dst: &str) -> isize {
+        match (self.find(src), self.walk_parent(dst)) {
+            (Some(inode), Some((dir, _, name))) if dir.link(name, &inode) => 0,
+            _ => -1,
+        }
+    }
+
+    fn unlink(&self, path: &str) -> isize {
+        match self.walk_parent(path) {
+            Some((dir, _, name)) if dir.unlink(name) => 0,
+            _ => -1,
+        }
+    }
+
+    fn rename(&self, old: &str, new: &str) -> isize {
+        match (self.walk_parent(old), self.walk_parent(new)) {
+            (Some((old_dir, old_path, old_name)), Some((new_dir, _, new_name)))
+                if !self.is_busy(&child(&old_path, old_name))
+                    && old_dir.rename(old_name, &new_dir, new_name) =>
+            {
+                0
+            }
+            _ => -1,
+        }
+    }
+
+    fn symlink(&self, target: &str, linkpath: &str) -> isize {
+        match self
+            .walk_parent(linkpath)
+            .and_then(|(dir, _, name)| dir.symlink(name, target))
+        {
+            Some(_) => 0,
+            None => -1,
+        }
+    }
+
+    fn readlink(&self, path: &str) -> Option<String> {
+        self.walk(path, false)
+            .and_then(|(inode, _)| inode.readlink())
+    }
+
+    fn readdir(&self, path: &str) -> Option<Vec<String>> {
+        self.find(path).and_then(|inode| inode.readdir())
+    }
+
+    fn mount(&self, source: &str, target: &str, fs_type: &str) -> isize {
+        let mount = match self.fs_types.lock().get(fs_type) {
+            Some(mount) => *mount,
+            None => return -1,
+        };
+        match mount(source) {
+            Some(fs) if self.mount_fs(target, fs.clone()) => 0,
+            _ => -1,
+        }
+    }
+
+    fn umount(&self, target: &str) -> isize {
+        match self.umount_fs(target) {
+            Some(_) => 0,
+            None => -1,
+        }
+    }
+
+    fn sync(&self) {
+        for fs in self.mounts.lock().values() {
+            fs.sync();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metadata;
+    use alloc::format;
+    use core::any::Any;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+
+    /// 测试用的内存文件系统，符号链接的目标存放在 `data` 中
+    struct Node {
+        ino: u64,
+        type_: FileType,
+        data: Mutex<Vec<u8>>,
+        children: Mutex<BTreeMap<String, Arc<Node>>>,
+    }
+
+    impl Node {
+        fn new(type_: FileType, data: &[u8]) -> Arc<Self> {
+            Arc::new(Self {
+                ino: NEXT_INO.fetch_add(1, Ordering::Relaxed),
+                type_,
+                data: Mutex::new(data.to_vec()),
+                children: Mutex::new(BTreeMap::new()),
+            })
+        }
+
+        fn add(&self, name: &str, type_: FileType, data: &[u8]) -> Option<Arc<dyn Inode>> {
+            if self.type_ != FileType::Directory {
+                return None;
+            }
+            let mut children = self.children.lock();
+            if children.contains_key(name) {
+                return None;
+            }
+            let node = Node::new(type_, data);
+            children.insert(String::from(name), node.clone());
+            Some(node)
+        }
+    }
+
+    impl Inode for Node {
+        fn metadata(&self) -> Metadata {
+            Metadata {
+                ino: self.ino,
+                type_: self.type_,
+                nlink: 1,
+                size: self.data.lock().len() as u64,
+                mode: 0o755,
+                atime: 0,
+                mtime: 0,
+                crtime: 0,
+            }
+        }
+
+        fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+            let data = self.data.lock();
+            let len = data.len().saturating_sub(offset).min(buf.len());
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            len
+        }
+
+        fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+            let mut data = self.data.lock();
+            if data.len() < offset + buf.len() {
+                data.resize(offset + buf.len(), 0);
+            }
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+            buf.len()
+        }
+
+        fn truncate(&self, len: usize) -> bool {
+            self.data.lock().resize(len, 0);
+            true
+        }
+
+        fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+            self.children
+                .lock()
+                .get(name)
+                .map(|node| node.clone() as Arc<dyn Inode>)
+        }
+
+        fn create(&self, name: &str, type_: FileType) -> Option<Arc<dyn Inode>> {
+            self.add(name, type_, b"")
+        }
+
+        fn symlink(&self, name: &str, target: &str) -> Option<Arc<dyn Inode>> {
+            self.add(name, FileType::SymLink, target.as_bytes())
+        }
+
+        fn readlink(&self) -> Option<String> {
+            (self.type_ == FileType::SymLink)
+                .then(|| String::from_utf8(self.data.lock().clone()).unwrap())
+        }
+
+        fn readdir(&self) -> Option<Vec<String>> {
+            Some(self.children.lock().keys().cloned().collect())
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct MemFs(Arc<Node>);
+
+    impl FileSystem for MemFs {
+        fn root_inode(&self) -> Arc<dyn Inode> {
+            self.0.clone()
+        }
+
+        fn sync(&self) {}
+    }
+
+    fn mem_fs() -> Arc<dyn FileSystem> {
+        Arc::new(MemFs(Node::new(FileType::Directory, b"")))
+    }
+
+    fn ino(fs: &MountTable, path: &str) -> Option<u64> {
+        fs.find(path).map(|inode| inode.metadata().ino)
+    }
+
+    /// 根文件系统中有 `/mnt/hidden` 和 `/top`，`/mnt` 上挂载了有 `inner` 的文件系统
+    fn mounted() -> (MountTable, Arc<dyn FileSystem>) {
+        let table = MountTable::new(mem_fs());
+        assert_eq!(table.mkdir("/mnt"), 0);
+        assert!(table.open("/mnt/hidden", OpenFlags::CREATE).is_some());
+        assert!(table.open("/top", OpenFlags::CREATE).is_some());
+        let fs = mem_fs();
+        fs.root_inode().create("inner", FileType::File).unwrap();
+        assert!(table.mount_fs("mnt", fs.clone()));
+        (table, fs)
+    }
+
+    #[test]
+    fn mount_hides_the_directory_below() {
+        let (table, fs) = mounted();
+        assert!(table.find("/mnt/hidden").is_none());
+        assert_eq!(ino(&table, "/mnt"), Some(fs.root_inode().metadata().ino));
+        assert_eq!(
+            ino(&table, "mnt/./inner"),
+            Some(fs.root_inode().find("inner").unwrap().metadata().ino)
+        );
+        // 在挂载的文件系统中创建
+        assert!(table.open("/mnt/new", OpenFlags::CREATE).is_some());
+        assert!(fs.root_inode().find("new").is_some());
+        // 同一个挂载点不能重复挂载，文件上不能挂载
+        assert!(!table.mount_fs("/mnt", mem_fs()));
+        assert!(!table.mount_fs("/top", mem_fs()));
+        assert!(!table.mount_fs("/missing", mem_fs()));
+    }
+
+    #[test]
+    fn dotdot_leaves_the_mount_root() {
+        let (table, _) = mounted();
+        let root = ino(&table, "/");
+        assert_eq!(ino(&table, "/mnt/.."), root);
+        assert_eq!(ino(&table, "/mnt/../top"), ino(&table, "/top"));
+        assert_eq!(ino(&table, "/mnt/../mnt/inner"), ino(&table, "/mnt/inner"));
+        // 根目录的 `..` 还是根目录
+        assert_eq!(ino(&table, "/../.."), root);
+        assert_eq!(ino(&table, "../mnt/inner"), ino(&table, "/mnt/inner"));
+    }
+
+    #[test]
+    fn symlinks_cross_mounts() {
+        let (table, _) = mounted();
+        // 根文件系统中的绝对链接指向挂载的文件系统
+        assert_eq!(table.symlink("/mnt/inner", "/to_inner"), 0);
+        assert_eq!(ino(&table, "/to_inner"), ino(&table, "/mnt/inner"));
+        // 挂载的文件系统中的相对链接经过 `..` 回到根文件系统
+        assert_eq!(table.symlink("../top", "/mnt/up"), 0);
+        assert_eq!(ino(&table, "/mnt/up"), ino(&table, "/top"));
+        assert_eq!(table.readlink("/mnt/up").as_deref(), Some("../top"));
+        // 指向挂载点的链接进入挂载的文件系统
+        assert_eq!(table.symlink("mnt", "/dir_link"), 0);
+        assert_eq!(ino(&table, "/dir_link/inner"), ino(&table, "/mnt/inner"));
+        assert!(table.open("/dir_link/created", OpenFlags::CREATE).is_some());
+        assert!(table.find("/mnt/created").is_some());
+        // 不跟随最后一个分量时得到链接本身
+        assert_eq!(table.readlink("/dir_link").as_deref(), Some("mnt"));
+    }
+
+    #[test]
+    fn symlink_limit() {
+        let table = MountTable::new(mem_fs());
+        assert!(table.open("/target", OpenFlags::CREATE).is_some());
+        assert_eq!(table.symlink("target", "/l0"), 0);
+        for i in 1..=SYMLINK_LIMIT {
+            let target = format!("l{}", i - 1);
+            assert_eq!(table.symlink(&target, &format!("/l{i}")), 0);
+        }
+        // `l{n}` 要跟随 n + 1 个链接
+        let target = ino(&table, "/target");
+        assert_eq!(ino(&table, &format!("/l{}", SYMLINK_LIMIT - 1)), target);
+        assert!(table.find(&format!("/l{SYMLINK_LIMIT}")).is_none());
+        // 指向自己的链接
+        assert_eq!(table.symlink("loop", "/loop"), 0);
+        assert!(table.find("/loop").is_none());
+        assert!(table.open("/loop/x", OpenFlags::CREATE).is_none());
+    }
+
+    #[test]
+    fn umount_refuses_busy_mount_points() {
+        let table = MountTable::new(mem_fs());
+        table.register("memfs", |_| Some(mem_fs()));
+        assert_eq!(table.mkdir("/a"), 0);
+        assert_eq!(table.mount("none", "/a", "memfs"), 0);
+        assert_eq!(table.mount("none", "/a", "unknown"), -1);
+        assert_eq!(table.mkdir("/a/b"), 0);
+        assert_eq!(table.mount("none", "/a/b", "memfs"), 0);
+        // 其下还有挂载点时不能卸载，挂载点也不能改名
+        assert_eq!(table.umount("/a"), -1);
+        assert_eq!(table.rename("/a/b", "/a/c"), -1);
+        assert_eq!(table.rename("/a", "/moved"), -1);
+        // 根文件系统不能卸载
+        assert_eq!(table.umount("/"), -1);
+        assert_eq!(table.umount("/a/b"), 0);
+        assert_eq!(table.umount("/a"), 0);
+        assert_eq!(table.umount("/a"), -1);
+        // 卸载后原来的目录重新可见
+        assert!(table.find("/a/b").is_none());
+        assert_eq!(table.readdir("/a"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn open_flags() {
+        let table = MountTable::new(mem_fs());
+        assert!(table.open("/file", OpenFlags::RDONLY).is_none());
+        let file = table
+            .open("/file", OpenFlags::CREATE | OpenFlags::WRONLY)
+            .unwrap();
+        assert!(!file.readable() && file.writable());
+        file.inode.as_ref().unwrap().write_at(0, b"data");
+        let file = table.open("/file", OpenFlags::RDONLY).unwrap();
+        assert_eq!(file.inode.as_ref().unwrap().metadata().size, 4);
+        // 已存在的文件只在指定 TRUNC 时清空
+        let file = table
+            .open("/file", OpenFlags::TRUNC | OpenFlags::WRONLY)
+            .unwrap();
+        assert_eq!(file.inode.as_ref().unwrap().metadata().size, 0);
+        let file = table
+            .open("/file", OpenFlags::APPEND | OpenFlags::WRONLY)
+            .unwrap();
+        assert!(file.append);
+        // 目录可以只读打开，不能创建
+        assert!(table.open("/", OpenFlags::RDONLY).is_some());
+        assert_eq!(table.mkdir("/dir"), 0);
+        assert!(table.open("/dir", OpenFlags::CREATE).is_none());
+    }
+}
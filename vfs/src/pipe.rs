@@ -0,0 +1,245 @@
+use crate::UserBuffer;
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+/// 管道缓冲区的字节数
+pub const PIPE_BUFFER_SIZE: usize = 4096;
+
+/// 管道暂时不能读写，[`FileHandle::read`](crate::FileHandle::read) 和
+/// [`FileHandle::write`](crate::FileHandle::write) 返回它，调用者应等待后重试
+pub const WOULD_BLOCK: isize = -2;
+
+/// 管道，两端共享的有界环形缓冲区
+struct Pipe {
+    inner: Mutex<PipeInner>,
+}
+
+struct PipeInner {
+    /// 已写入还未读出的数据
+    buffer: VecDeque<u8>,
+    /// 打开的读端数
+    readers: usize,
+    /// 打开的写端数
+    writers: usize,
+    /// 等待管道可以读写的任务和唤醒它们的函数
+    waiters: Vec<(usize, fn(usize))>,
+}
+
+impl Pipe {
+    /// 唤醒所有等待的任务，它们会重试读写
+    fn wake_all(&self) {
+        let waiters = core::mem::take(&mut self.inner.lock().waiters);
+        for (task, wake) in waiters {
+            wake(task);
+        }
+    }
+}
+
+/// 管道的读端或写端
+///
+/// 复制一端会增加这一端的引用数，所有写端关闭后读端读到文件尾，所有读端关闭后写端写入失败。
+pub struct PipeEnd {
+    pipe: Arc<Pipe>,
+    writable: bool,
+}
+
+/// 创建一个管道，返回 (读端, 写端)
+pub fn make_pipe() -> (PipeEnd, PipeEnd) {
+    let pipe = Arc::new(Pipe {
+        inner: Mutex::new(PipeInner {
+            buffer: VecDeque::with_capacity(PIPE_BUFFER_SIZE),
+            readers: 1,
+            writers: 1,
+            waiters: Vec::new(),
+        }),
+    });
+    let read_end = PipeEnd {
+        pipe: pipe.clone(),
+        writable: false,
+    };
+    let write_end = PipeEnd {
+        pipe,
+        writable: true,
+    };
+    (read_end, write_end)
+}
+
+impl PipeEnd {
+    /// 是否是写端
+    pub fn writable(&self) -> bool {
+        self.writable
+    }
+
+    /// 读出缓冲区中已有的数据，返回读到的字节数。
+    ///
+    /// 缓冲区为空时，写端都已关闭则返回 0，否则返回 [`WOULD_BLOCK`]。
+    pub fn read(&self, mut buf: UserBuffer) -> isize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let mut inner = self.pipe.inner.lock();
+        if inner.buffer.is_empty() {
+            return if inner.writers == 0 { 0 } else { WOULD_BLOCK };
+        }
+        let mut total = 0;
+        for slice in buf.buffers.iter_mut() {
+            let len = slice.len().min(inner.buffer.len());
+            for (dst, src) in slice.iter_mut().zip(inner.buffer.drain(..len)) {
+                *dst = src;
+            }
+            total += len;
+            if inner.buffer.is_empty() {
+                break;
+            }
+        }
+        drop(inner);
+        // 腾出了空间，等待写入的任务可以继续
+        self.pipe.wake_all();
+        total as _
+    }
+
+    /// 把数据写入缓冲区，返回写入的字节数，缓冲区满时只写入一部分。
+    ///
+    /// 读端都已关闭时返回 -1，缓冲区已满时返回 [`WOULD_BLOCK`]。
+    pub fn write(&self, buf: UserBuffer) -> isize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let mut inner = self.pipe.inner.lock();
+        if inner.readers == 0 {
+            return -1;
+        }
+        if inner.buffer.len() == PIPE_BUFFER_SIZE {
+            return WOULD_BLOCK;
+        }
+        let mut total = 0;
+        for slice in buf.buffers.iter() {
+            let len = slice.len().min(PIPE_BUFFER_SIZE - inner.buffer.len());
+            inner.buffer.extend(&slice[..len]);
+            total += len;
+            if inner.buffer.len() == PIPE_BUFFER_SIZE {
+                break;
+            }
+        }
+        drop(inner);
+        // 有了数据，等待读出的任务可以继续
+        self.pipe.wake_all();
+        total as _
+    }
+
+    /// 登记等待管道可以读写的任务，下一次读写或另一端全部关闭时调用 `wake(task)`
+    pub fn wait(&self, task: usize, wake: fn(usize)) {
+        let mut inner = self.pipe.inner.lock();
+        if !inner.waiters.iter().any(|(waiter, _)| *waiter == task) {
+            inner.waiters.push((task, wake));
+        }
+    }
+}
+
+impl Clone for PipeEnd {
+    fn clone(&self) -> Self {
+        let mut inner = self.pipe.inner.lock();
+        if self.writable {
+            inner.writers += 1;
+        } else {
+            inner.readers += 1;
+        }
+        Self {
+            pipe: self.pipe.clone(),
+            writable: self.writable,
+        }
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut inner = self.pipe.inner.lock();
+        let remaining = if self.writable {
+            inner.writers -= 1;
+            inner.writers
+        } else {
+            inner.readers -= 1;
+            inner.readers
+        };
+        drop(inner);
+        // 一端全部关闭，另一端等待的任务会读到文件尾或写入失败
+        if remaining == 0 {
+            self.pipe.wake_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, vec};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// 长为 `len` 的缓冲区，测试中泄漏内存以得到 `'static` 的切片
+    fn buffer(data: &[u8], len: usize) -> UserBuffer {
+        let mut buf = vec![0u8; len];
+        buf[..data.len()].copy_from_slice(data);
+        UserBuffer::new(vec![Box::leak(buf.into_boxed_slice())])
+    }
+
+    /// 从 `end` 读出至多 `len` 个字节
+    fn read(end: &PipeEnd, len: usize) -> (isize, Vec<u8>) {
+        let buf = Box::leak(vec![0u8; len].into_boxed_slice());
+        let ptr = buf.as_ptr();
+        let ret = end.read(UserBuffer::new(vec![buf]));
+        // 缓冲区泄漏了，读完后仍然有效
+        let data = unsafe { core::slice::from_raw_parts(ptr, len) };
+        (ret, data[..ret.max(0) as usize].to_vec())
+    }
+
+    #[test]
+    fn read_sees_eof_after_last_writer_is_dropped() {
+        let (read_end, write_end) = make_pipe();
+        assert_eq!(read(&read_end, 4).0, WOULD_BLOCK);
+        assert_eq!(write_end.write(buffer(b"abc", 3)), 3);
+        let other_writer = write_end.clone();
+        drop(write_end);
+        assert_eq!(read(&read_end, 4), (3, b"abc".to_vec()));
+        // 还有一个写端，暂时没有数据
+        assert_eq!(read(&read_end, 4).0, WOULD_BLOCK);
+        drop(other_writer);
+        assert_eq!(read(&read_end, 4), (0, Vec::new()));
+    }
+
+    #[test]
+    fn write_fails_after_last_reader_is_dropped() {
+        let (read_end, write_end) = make_pipe();
+        let other_reader = read_end.clone();
+        drop(read_end);
+        assert_eq!(write_end.write(buffer(b"x", 1)), 1);
+        drop(other_reader);
+        assert_eq!(write_end.write(buffer(b"x", 1)), -1);
+    }
+
+    #[test]
+    fn full_pipe_blocks_writers() {
+        let (read_end, write_end) = make_pipe();
+        let len = PIPE_BUFFER_SIZE + 10;
+        assert_eq!(write_end.write(buffer(&[], len)), PIPE_BUFFER_SIZE as isize);
+        assert_eq!(write_end.write(buffer(b"x", 1)), WOULD_BLOCK);
+        assert_eq!(read(&read_end, 10).0, 10);
+        assert_eq!(write_end.write(buffer(&[], len)), 10);
+    }
+
+    static WOKEN: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn waiters_are_woken_once_when_the_other_end_closes() {
+        fn wake(task: usize) {
+            WOKEN.fetch_add(task, Ordering::SeqCst);
+        }
+        let (read_end, write_end) = make_pipe();
+        assert_eq!(read(&read_end, 1).0, WOULD_BLOCK);
+        // 同一个任务登记多次也只唤醒一次
+        read_end.wait(7, wake);
+        read_end.wait(7, wake);
+        drop(write_end);
+        assert_eq!(WOKEN.load(Ordering::SeqCst), 7);
+        assert_eq!(read(&read_end, 1).0, 0);
+    }
+}
@@ -1,7 +1,13 @@
-use easy_fs::{BlockDevice, EasyFileSystem};
+use easy_fs::{
+    fsck, BlockDevice, DiskInodeType, EasyFileSystem, Geometry, Inode, Problem, JOURNAL_BLOCKS,
+};
+use std::collections::hash_map::DefaultHasher;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 
 const BLOCK_SZ: usize = 512;
 
@@ -23,18 +29,85 @@ impl BlockDevice for BlockFile {
     }
 }
 
-pub fn easy_fs_pack(cases: &Vec<String>, target: &str) -> std::io::Result<()> {
+/// Geometry and extra contents of the image
+#[derive(Hash)]
+pub struct ImageConfig {
+    /// Total number of blocks
+    pub total_blocks: u32,
+    /// Blocks of the inode bitmap, each allows 4096 inodes
+    pub inode_bitmap_blocks: u32,
+    /// Host files copied into the root directory
+    pub files: Vec<PathBuf>,
+    /// Host directory mirrored into the root directory
+    pub root: Option<PathBuf>,
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            total_blocks: 64 * 2048,
+            inode_bitmap_blocks: 1,
+            files: vec![],
+            root: None,
+        }
+    }
+}
+
+pub fn easy_fs_pack(
+    cases: &Vec<String>,
+    target: &str,
+    config: &ImageConfig,
+) -> std::io::Result<()> {
+    let image = Path::new(target).join("fs.img");
+    let stamp_path = Path::new(target).join("fs.img.stamp");
+    // Skip packing if neither the inputs nor the image changed. Running the kernel
+    // modifies the image, so its modification time is part of the stamp too.
+    let inputs = hash_inputs(cases, target, config)?;
+    let stamp = image_stamp(inputs, &image);
+    if stamp.is_some() && std::fs::read_to_string(&stamp_path).ok() == stamp {
+        println!("{} is up to date.", image.display());
+        return Ok(());
+    }
+    let _ = std::fs::remove_file(&stamp_path);
+    pack(cases, target, &image, config)?;
+    std::fs::write(&stamp_path, image_stamp(inputs, &image).unwrap())
+}
+
+fn pack(
+    cases: &Vec<String>,
+    target: &str,
+    image: &Path,
+    config: &ImageConfig,
+) -> std::io::Result<()> {
+    // Check the layout easy-fs would format: there must be room for a data area besides
+    // the super block, the inode area and the journal, which must hold the largest operation
+    let geometry = match Geometry::new(config.total_blocks, config.inode_bitmap_blocks) {
+        Some(geometry) => geometry,
+        None => {
+            return Err(Error::other(format!(
+                "{} blocks do not fit {} inode bitmap block(s) and a journal of at least {} blocks",
+                config.total_blocks, config.inode_bitmap_blocks, JOURNAL_BLOCKS
+            )))
+        }
+    };
     let block_file = Arc::new(BlockFile(Mutex::new({
         let f = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open(format!("{}/{}", target, "fs.img"))?;
-        f.set_len(64 * 2048 * 512).unwrap();
+            .truncate(true)
+            .open(image)?;
+        f.set_len(config.total_blocks as u64 * BLOCK_SZ as u64)
+            .unwrap();
         f
     })));
+    EasyFileSystem::set_clock(host_clock);
+    println!(
+        "{} data blocks, {} journal blocks",
+        geometry.data_area_blocks, geometry.journal_blocks
+    );
     println!("Packing Testcases...");
-    let efs = EasyFileSystem::create(block_file, 64 * 2048, 1);
+    let efs = EasyFileSystem::create(block_file, config.total_blocks, config.inode_bitmap_blocks);
     println!("Packing Testcases...");
     let root_inode = Arc::new(EasyFileSystem::root_inode(&efs));
     println!("Packing Testcases...");
@@ -48,8 +121,21 @@ pub fn easy_fs_pack(cases: &Vec<String>, target: &str) -> std::io::Result<()> {
         let inode = root_inode.create(case.as_str()).unwrap();
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
+        // test cases are executable
+        inode.set_mode(0o755);
         // println!("{}", all_data.len());
     }
+    for file in &config.files {
+        println!("{}", file.display());
+        let name = file.file_name().unwrap().to_str().unwrap();
+        put_file(&root_inode, name, &std::fs::read(file)?)?;
+    }
+    if let Some(root) = &config.root {
+        println!("Mirroring {}", root.display());
+        mirror_dir(root, &root_inode)?;
+    }
+    // write back the block cache, it lives until the process exits
+    efs.lock().sync();
     println!("List Testcases in EFS: ");
     // list app
     for case in root_inode.readdir() {
@@ -57,3 +143,370 @@ pub fn easy_fs_pack(cases: &Vec<String>, target: &str) -> std::io::Result<()> {
     }
     Ok(())
 }
+
+/// Hash the inputs of packing: the image config, the case names and all host file contents.
+///
+/// The hash also changes when xtask itself is rebuilt, e.g. when the easy-fs disk format changed.
+fn hash_inputs(cases: &Vec<String>, target: &str, config: &ImageConfig) -> std::io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    std::fs::metadata(std::env::current_exe()?)?
+        .modified()?
+        .hash(&mut hasher);
+    config.hash(&mut hasher);
+    for case in cases {
+        case.hash(&mut hasher);
+        std::fs::read(Path::new(target).join(case))?.hash(&mut hasher);
+    }
+    for file in &config.files {
+        std::fs::read(file)?.hash(&mut hasher);
+    }
+    if let Some(root) = &config.root {
+        hash_dir(root, &mut hasher)?;
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash the names and file contents of a directory tree in name order
+fn hash_dir(host: &Path, hasher: &mut DefaultHasher) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(host)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        entry.file_name().hash(hasher);
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            hash_dir(&entry.path(), hasher)?;
+        } else if file_type.is_file() {
+            std::fs::read(entry.path())?.hash(hasher);
+        } else if file_type.is_symlink() {
+            std::fs::read_link(entry.path())?.hash(hasher);
+        }
+    }
+    Ok(())
+}
+
+/// Stamp made of the input hash and the image size and modification time, `None` if there is no image
+fn image_stamp(inputs: u64, image: &Path) -> Option<String> {
+    let meta = std::fs::metadata(image).ok()?;
+    let modified = meta
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?;
+    Some(format!(
+        "{inputs:016x} {} {}",
+        meta.len(),
+        modified.as_nanos()
+    ))
+}
+
+/// Copy the host directory tree `host` into the easy-fs directory `dir` recursively
+fn mirror_dir(host: &Path, dir: &Inode) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(host)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().unwrap();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            let inode = put_dir(dir, &name)?;
+            mirror_dir(&entry.path(), &inode)?;
+        } else if file_type.is_file() {
+            put_file(dir, &name, &std::fs::read(entry.path())?)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            put_symlink(dir, &name, target.to_str().unwrap())?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the directory `name` in `dir`, creating it if missing
+fn put_dir(dir: &Inode, name: &str) -> std::io::Result<Arc<Inode>> {
+    dir.find(name)
+        .filter(|inode| inode.is_dir())
+        .or_else(|| dir.mkdir(name))
+        .ok_or_else(|| Error::other(format!("Cannot create directory {name}")))
+}
+
+/// Write `data` to the file `name` in `dir`, overwriting an existing file
+fn put_file(dir: &Inode, name: &str, data: &[u8]) -> std::io::Result<()> {
+    let inode = match dir.find(name) {
+        Some(inode) if inode.is_file() => {
+            inode.clear();
+            inode
+        }
+        Some(_) => return Err(Error::other(format!("{name} is a directory"))),
+        None => dir
+            .create(name)
+            .ok_or_else(|| Error::other(format!("Cannot create file {name}")))?,
+    };
+    inode.write_at(0, data);
+    Ok(())
+}
+
+/// Create the symbolic link `name` to `target` in `dir`, replacing an existing file or link
+fn put_symlink(dir: &Inode, name: &str, target: &str) -> std::io::Result<()> {
+    if dir.find(name).is_some() && !dir.unlink(name) {
+        return Err(Error::other(format!("{name} is a directory")));
+    }
+    dir.symlink(name, target)
+        .map(|_| ())
+        .ok_or_else(|| Error::other(format!("Cannot create symbolic link {name}")))
+}
+
+/// Copy the easy-fs directory `dir` into the host directory `host` recursively
+fn extract_dir(dir: &Inode, host: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(host)?;
+    for name in dir.readdir() {
+        if name == "." || name == ".." {
+            continue;
+        }
+        let inode = dir.find(&name).unwrap();
+        if inode.is_dir() {
+            extract_dir(&inode, &host.join(&name))?;
+        } else if let Some(target) = inode.readlink() {
+            extract_symlink(&target, &host.join(&name))?;
+        } else {
+            std::fs::write(host.join(&name), read_all(&inode))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create the host symbolic link `host` to `target`
+#[cfg(unix)]
+fn extract_symlink(target: &str, host: &Path) -> std::io::Result<()> {
+    let _ = std::fs::remove_file(host);
+    std::os::unix::fs::symlink(target, host)
+}
+
+/// Skipped on hosts without symbolic links
+#[cfg(not(unix))]
+fn extract_symlink(target: &str, host: &Path) -> std::io::Result<()> {
+    println!("Skip symbolic link {} -> {target}", host.display());
+    Ok(())
+}
+
+/// Read the whole file
+fn read_all(inode: &Inode) -> Vec<u8> {
+    let mut data = vec![0u8; inode.stat().size as usize];
+    inode.read_at(0, &mut data);
+    data
+}
+
+/// Host time in seconds since the Unix epoch
+fn host_clock() -> u32 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+}
+
+/// Open an existing image as a block device
+fn open_image(image: &Path) -> std::io::Result<Arc<BlockFile>> {
+    EasyFileSystem::set_clock(host_clock);
+    let f = OpenOptions::new().read(true).write(true).open(image)?;
+    Ok(Arc::new(BlockFile(Mutex::new(f))))
+}
+
+/// Open an existing image and return its root directory
+fn open_root(image: &Path) -> std::io::Result<Inode> {
+    Ok(EasyFileSystem::root_inode(&EasyFileSystem::open(
+        open_image(image)?,
+    )))
+}
+
+fn not_found(path: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("{path} not found in the image"),
+    )
+}
+
+/// List the entry at `path` in the image, one line per inode
+pub fn easy_fs_ls(image: &Path, path: &str) -> std::io::Result<()> {
+    let root = open_root(image)?;
+    let inode = root.lookup_nofollow(path).ok_or_else(|| not_found(path))?;
+    let entries = if inode.is_dir() {
+        inode
+            .readdir()
+            .into_iter()
+            .map(|name| (inode.find(&name).unwrap(), name))
+            .collect()
+    } else {
+        vec![(inode, String::from(path))]
+    };
+    for (inode, name) in entries {
+        let stat = inode.stat();
+        let type_ = match stat.type_ {
+            DiskInodeType::Directory => 'd',
+            DiskInodeType::File => '-',
+            DiskInodeType::SymLink => 'l',
+        };
+        let mode: String = (0..9)
+            .map(|i| match stat.mode & (0o400 >> i) {
+                0 => '-',
+                _ => ['r', 'w', 'x'][i % 3],
+            })
+            .collect();
+        let target = inode
+            .readlink()
+            .map(|target| format!(" -> {target}"))
+            .unwrap_or_default();
+        println!(
+            "{:>6} {type_}{mode} {:>3} {:>10} {:>10} {name}{target}",
+            stat.ino, stat.nlink, stat.size, stat.mtime
+        );
+    }
+    Ok(())
+}
+
+/// Read the whole file at `path` in the image
+pub fn easy_fs_cat(image: &Path, path: &str) -> std::io::Result<Vec<u8>> {
+    let root = open_root(image)?;
+    match root.lookup(path) {
+        Some(inode) if inode.is_file() => Ok(read_all(&inode)),
+        Some(_) => Err(Error::other(format!("{path} is a directory"))),
+        None => Err(not_found(path)),
+    }
+}
+
+/// Copy the file or directory tree at `path` in the image to `host`
+pub fn easy_fs_extract(image: &Path, path: &str, host: &Path) -> std::io::Result<()> {
+    let root = open_root(image)?;
+    let inode = root.lookup(path).ok_or_else(|| not_found(path))?;
+    if inode.is_dir() {
+        extract_dir(&inode, host)
+    } else {
+        std::fs::write(host, read_all(&inode))
+    }
+}
+
+/// Copy the host file or directory tree `host` to `path` in the image.
+///
+/// If `path` is an existing directory, `host` is copied into it under its own name.
+pub fn easy_fs_add(image: &Path, host: &Path, path: &str) -> std::io::Result<()> {
+    let root = open_root(image)?;
+    let host_name = host.file_name().and_then(|name| name.to_str());
+    let (dir, name) = match (root.lookup(path), host_name) {
+        (Some(dir), Some(name)) if dir.is_dir() => (dir, name),
+        _ => root.lookup_parent(path).ok_or_else(|| not_found(path))?,
+    };
+    if host.is_dir() {
+        let inode = put_dir(&dir, name)?;
+        mirror_dir(host, &inode)?;
+    } else {
+        put_file(&dir, name, &std::fs::read(host)?)?;
+    }
+    root.sync();
+    Ok(())
+}
+
+/// Remove the file at `path` in the image
+pub fn easy_fs_rm(image: &Path, path: &str) -> std::io::Result<()> {
+    let root = open_root(image)?;
+    let (dir, name) = root.lookup_parent(path).ok_or_else(|| not_found(path))?;
+    if !dir.unlink(name) {
+        return Err(Error::other(format!(
+            "Cannot remove {path}, not found or a directory"
+        )));
+    }
+    root.sync();
+    Ok(())
+}
+
+/// Move `from` to `to` in the image, replacing `to` if it is not a directory
+pub fn easy_fs_mv(image: &Path, from: &str, to: &str) -> std::io::Result<()> {
+    let root = open_root(image)?;
+    let (old_dir, old_name) = root.lookup_parent(from).ok_or_else(|| not_found(from))?;
+    let (new_dir, new_name) = root.lookup_parent(to).ok_or_else(|| not_found(to))?;
+    if !old_dir.rename(old_name, &new_dir, new_name) {
+        return Err(Error::other(format!("Cannot move {from} to {to}")));
+    }
+    root.sync();
+    Ok(())
+}
+
+/// Check the easy-fs image at `image`, fix what is found if `repair` is set
+pub fn easy_fs_check(image: &Path, repair: bool) -> std::io::Result<Vec<Problem>> {
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let problems = fsck(&efs, repair);
+    efs.lock().sync();
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temporary directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xtask-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Pack an empty image of 4096 blocks in `dir`
+    fn empty_image(dir: &Path) -> PathBuf {
+        let image = dir.join("fs.img");
+        let config = ImageConfig {
+            total_blocks: 4096,
+            ..Default::default()
+        };
+        pack(&vec![], dir.to_str().unwrap(), &image, &config).unwrap();
+        image
+    }
+
+    #[test]
+    fn add_then_extract_round_trip() {
+        let dir = temp_dir("round-trip");
+        let image = empty_image(&dir);
+        let host = dir.join("tree");
+        std::fs::create_dir_all(host.join("sub/deeper")).unwrap();
+        std::fs::write(host.join("small.txt"), b"hello").unwrap();
+        // spans several blocks, including indirect ones
+        let big: Vec<u8> = (0..40_000).map(|i| (i % 253) as u8).collect();
+        std::fs::write(host.join("sub/big.bin"), &big).unwrap();
+        std::fs::write(host.join("sub/deeper/empty"), b"").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("../small.txt", host.join("sub/link")).unwrap();
+
+        easy_fs_add(&image, &host, "/").unwrap();
+        easy_fs_add(&image, &host.join("small.txt"), "/renamed.txt").unwrap();
+        assert_eq!(easy_fs_cat(&image, "/tree/sub/big.bin").unwrap(), big);
+        assert_eq!(easy_fs_cat(&image, "/renamed.txt").unwrap(), b"hello");
+
+        let out = dir.join("out");
+        easy_fs_extract(&image, "/tree", &out).unwrap();
+        assert_eq!(std::fs::read(out.join("small.txt")).unwrap(), b"hello");
+        assert_eq!(std::fs::read(out.join("sub/big.bin")).unwrap(), big);
+        assert_eq!(std::fs::read(out.join("sub/deeper/empty")).unwrap(), b"");
+        #[cfg(unix)]
+        assert_eq!(
+            std::fs::read_link(out.join("sub/link")).unwrap(),
+            Path::new("../small.txt")
+        );
+        assert!(easy_fs_check(&image, false).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rm_and_mv_update_the_image() {
+        let dir = temp_dir("rm-mv");
+        let image = empty_image(&dir);
+        let host = dir.join("file");
+        std::fs::write(&host, b"data").unwrap();
+        easy_fs_add(&image, &host, "/a").unwrap();
+        easy_fs_add(&image, &host, "/b").unwrap();
+        easy_fs_mv(&image, "/a", "/b").unwrap();
+        assert!(easy_fs_cat(&image, "/a").is_err());
+        assert_eq!(easy_fs_cat(&image, "/b").unwrap(), b"data");
+        easy_fs_rm(&image, "/b").unwrap();
+        assert_eq!(
+            easy_fs_cat(&image, "/b").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+        assert!(easy_fs_rm(&image, "/b").is_err());
+        assert!(easy_fs_check(&image, false).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -63,6 +63,8 @@ enum Commands {
     Make(BuildArgs),
     Asm(AsmArgs),
     Qemu(QemuArgs),
+    Fsck(FsckArgs),
+    Fs(FsArgs),
 }
 
 fn main() {
@@ -73,6 +75,8 @@ fn main() {
         }
         Asm(args) => args.dump(),
         Qemu(args) => args.run(),
+        Fsck(args) => args.check(),
+        Fs(args) => args.run(),
     }
 }
 
@@ -194,6 +198,10 @@ struct QemuArgs {
     /// Port for gdb to connect. If set, qemu will block and wait gdb to connect.
     #[clap(long)]
     gdb: Option<u16>,
+    /// Image attached as the virtio block device instead of the packed fs.img,
+    /// e.g. a FAT32 image made by mkfs.vfat, which is mounted read-only.
+    #[clap(long)]
+    disk: Option<PathBuf>,
 }
 
 impl QemuArgs {
@@ -222,21 +230,21 @@ impl QemuArgs {
             .args(&["-m", "64M"])
             .args(&["-serial", "mon:stdio"]);
         if self.build.ch > 5 {
+            let disk = self.disk.clone().unwrap_or_else(|| {
+                target_dir
+                    .join(if self.build.release {
+                        "release"
+                    } else {
+                        "debug"
+                    })
+                    .join("fs.img")
+            });
             // Add VirtIO Device
             qemu.args(&[
                 "-drive",
                 format!(
                     "file={},if=none,format=raw,id=x0",
-                    target_dir
-                        .join(if self.build.release {
-                            "release"
-                        } else {
-                            "debug"
-                        })
-                        .join("fs.img")
-                        .into_os_string()
-                        .into_string()
-                        .unwrap()
+                    disk.into_os_string().into_string().unwrap()
                 )
                 .as_str(),
             ])
@@ -252,6 +260,117 @@ impl QemuArgs {
     }
 }
 
+#[derive(Args)]
+struct ImageArgs {
+    /// Path of the image, defaults to the fs.img of the last build.
+    #[clap(long)]
+    image: Option<PathBuf>,
+    /// target architecture
+    #[clap(long, value_enum, default_value_t = Arch::Riscv64)]
+    arch: Arch,
+    /// use the image built in release mode
+    #[clap(long)]
+    release: bool,
+}
+
+impl ImageArgs {
+    fn path(&self) -> PathBuf {
+        let image = self.image.clone().unwrap_or_else(|| {
+            self.arch
+                .target_dir()
+                .join(if self.release { "release" } else { "debug" })
+                .join("fs.img")
+        });
+        if !image.is_file() {
+            eprintln!("Error: image '{}' not found.", image.display());
+            std::process::exit(1);
+        }
+        image
+    }
+}
+
+#[derive(Args)]
+struct FsckArgs {
+    #[clap(flatten)]
+    image: ImageArgs,
+    /// fix the problems found
+    #[clap(long)]
+    repair: bool,
+}
+
+impl FsckArgs {
+    fn check(self) {
+        let image = self.image.path();
+        let problems = fs_pack::easy_fs_check(&image, self.repair).unwrap();
+        for problem in &problems {
+            println!("{problem}");
+        }
+        if problems.is_empty() {
+            println!("'{}' is clean.", image.display());
+        } else if self.repair {
+            println!(
+                "{} problem(s) repaired in '{}'.",
+                problems.len(),
+                image.display()
+            );
+        } else {
+            println!(
+                "{} problem(s) found in '{}'.",
+                problems.len(),
+                image.display()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Args)]
+struct FsArgs {
+    #[clap(flatten)]
+    image: ImageArgs,
+    #[clap(subcommand)]
+    command: FsCommands,
+}
+
+#[derive(Subcommand)]
+enum FsCommands {
+    /// List a directory or a file in the image
+    Ls {
+        #[clap(default_value = "/")]
+        path: String,
+    },
+    /// Print a file in the image
+    Cat { path: String },
+    /// Copy a file or directory from the image to the host
+    Extract { path: String, host: PathBuf },
+    /// Copy a file or directory from the host into the image
+    Add { host: PathBuf, path: String },
+    /// Remove a file from the image
+    Rm { path: String },
+    /// Move or rename a file or directory in the image
+    Mv { from: String, to: String },
+}
+
+impl FsArgs {
+    fn run(self) {
+        use FsCommands::*;
+        let image = self.image.path();
+        let result = match self.command {
+            Ls { path } => fs_pack::easy_fs_ls(&image, &path),
+            Cat { path } => fs_pack::easy_fs_cat(&image, &path)
+                .and_then(|data| std::io::Write::write_all(&mut std::io::stdout(), &data)),
+            Extract { path, host } => fs_pack::easy_fs_extract(&image, &path, &host),
+            Add { host, path } => fs_pack::easy_fs_add(&image, &host, &path),
+            Rm { path } => fs_pack::easy_fs_rm(&image, &path),
+            Mv { from, to } => fs_pack::easy_fs_mv(&image, &from, &to),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn objcopy(elf: impl AsRef<Path>, binary: bool) -> PathBuf {
     let elf = elf.as_ref();
     let bin = elf.with_extension("bin");
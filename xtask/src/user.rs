@@ -1,4 +1,7 @@
-use crate::{fs_pack::easy_fs_pack, objcopy, Arch, PROJECT};
+use crate::{
+    fs_pack::{easy_fs_pack, ImageConfig},
+    objcopy, Arch, PROJECT,
+};
 use os_xtask_utils::{Cargo, CommandExt};
 use serde_derive::Deserialize;
 use std::{collections::HashMap, ffi::OsStr, fs::File, io::Write, path::PathBuf};
@@ -8,6 +11,14 @@ struct Cases {
     base: Option<u64>,
     step: Option<u64>,
     pub cases: Option<Vec<String>>,
+    /// Host directory mirrored into fs.img, relative to the project root
+    pub root: Option<String>,
+    /// Size of fs.img in MiB, 64 by default
+    pub fs_size: Option<u32>,
+    /// Minimum number of inodes fs.img can hold, 4096 by default
+    pub inodes: Option<u32>,
+    /// Host files copied into the root directory of fs.img, relative to the project root
+    pub files: Option<Vec<String>>,
 }
 
 pub struct CasesInfo {
@@ -39,6 +50,26 @@ impl Cases {
             }
         }
     }
+
+    /// Parameters of fs.img, defaults are used for those not set
+    fn image_config(&self) -> ImageConfig {
+        let default = ImageConfig::default();
+        ImageConfig {
+            total_blocks: self
+                .fs_size
+                .map_or(default.total_blocks, |size| size * 2048),
+            inode_bitmap_blocks: self.inodes.map_or(default.inode_bitmap_blocks, |inodes| {
+                inodes.div_ceil(4096).max(1)
+            }),
+            files: self
+                .files
+                .iter()
+                .flatten()
+                .map(|file| PROJECT.join(file))
+                .collect(),
+            root: self.root.as_ref().map(|root| PROJECT.join(root)),
+        }
+    }
 }
 
 fn build_one(name: impl AsRef<OsStr>, release: bool, base_address: u64, arch: Arch) -> PathBuf {
@@ -141,13 +172,14 @@ app_names:"
         });
     } else if ch >= 6 {
         easy_fs_pack(
-            &cases.cases.unwrap(),
+            cases.cases.as_ref().unwrap(),
             target_dir
                 .join(if release { "release" } else { "debug" })
                 .into_os_string()
                 .into_string()
                 .unwrap()
                 .as_str(),
+            &cases.image_config(),
         )
         .unwrap();
     }